@@ -1,17 +1,20 @@
+mod report;
 mod test_runner;
 
 use std::path::Path;
 use std::process;
 
 use clap::{Parser, Subcommand};
-use codespan_reporting::diagnostic::{Diagnostic, Label, Severity};
-use codespan_reporting::files::SimpleFiles;
-use codespan_reporting::term;
-use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use codespan_reporting::diagnostic::Severity;
+use codespan_reporting::term::termcolor::ColorChoice;
 
-use interpreter::{DiagnosticError, RuntimeValue};
+use interpreter::RuntimeValue;
+use mdl::lint::{Level, LintLevels};
+use mdl::parser::ParseError;
 
-const SUBCOMMANDS: &[&str] = &["run", "test", "help"];
+use report::{MessageFormat, Reporter};
+
+const SUBCOMMANDS: &[&str] = &["run", "check", "test", "fmt", "renumber", "explain", "help"];
 
 #[derive(Parser)]
 #[command(name = "mdl", version, about = "Markdownlang interpreter")]
@@ -20,6 +23,10 @@ struct Cli {
     #[arg(long, global = true)]
     no_color: bool,
 
+    /// How diagnostics are written to stderr
+    #[arg(long, global = true, value_enum, default_value_t)]
+    message_format: MessageFormat,
+
     #[command(subcommand)]
     command: Command,
 }
@@ -29,8 +36,20 @@ enum Command {
     /// Run a markdownlang program
     Run(RunArgs),
 
+    /// Statically check a program: undefined blocks and variables, arity
+    Check(CheckArgs),
+
     /// Run .test.md test files
     Test(TestArgs),
+
+    /// Rewrite source files in the canonical style
+    Fmt(FmtArgs),
+
+    /// Number fences 1, 2, 3... in every block, or insert a new fence
+    Renumber(RenumberArgs),
+
+    /// Explain a diagnostic code, or list all codes
+    Explain(ExplainArgs),
 }
 
 #[derive(clap::Args)]
@@ -46,9 +65,16 @@ struct RunArgs {
     #[arg(long)]
     check: bool,
 
-    /// Dump parsed AST
-    #[arg(long)]
-    ast: bool,
+    /// Dump the parsed AST as Rust debug output, or with `--ast=json` as JSON
+    #[arg(
+        long,
+        value_enum,
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "debug"
+    )]
+    ast: Option<AstFormat>,
 
     /// List all block names in the program
     #[arg(long)]
@@ -58,11 +84,73 @@ struct RunArgs {
     #[arg(short, long)]
     quiet: bool,
 
+    #[command(flatten)]
+    lints: LintArgs,
+
     /// Arguments passed to the entrypoint block (after --)
     #[arg(last = true)]
     args: Vec<String>,
 }
 
+/// How `--ast` prints the program.
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum AstFormat {
+    /// Rust `{:#?}` output; its shape may change between versions
+    Debug,
+    /// One JSON document, see the `serde` feature of the `mdl` crate
+    Json,
+}
+
+#[derive(clap::Args)]
+struct CheckArgs {
+    /// Markdown source file to check
+    file: String,
+
+    #[command(flatten)]
+    lints: LintArgs,
+}
+
+/// Warning levels. A specific code overrides `warnings`; `<!-- mdl-allow: CODE -->`
+/// comments in the source override both.
+#[derive(clap::Args)]
+struct LintArgs {
+    /// Treat every warning as an error (same as `-D warnings`)
+    #[arg(long)]
+    deny_warnings: bool,
+
+    /// Silence a warning code, or `warnings` for all. Repeatable.
+    #[arg(short = 'A', long = "allow", value_name = "CODE")]
+    allow: Vec<String>,
+
+    /// Report a warning code as a warning, or `warnings` for all. Repeatable.
+    #[arg(short = 'W', long = "warn", value_name = "CODE")]
+    warn: Vec<String>,
+
+    /// Report a warning code as an error, or `warnings` for all. Repeatable.
+    #[arg(short = 'D', long = "deny", value_name = "CODE")]
+    deny: Vec<String>,
+}
+
+impl LintArgs {
+    /// Build the warning levels, exiting with an error on unknown codes.
+    fn levels(&self) -> LintLevels {
+        let mut levels = LintLevels::default();
+        if self.deny_warnings {
+            levels.set_all(Level::Deny);
+        }
+        let flags = [(&self.allow, Level::Allow), (&self.warn, Level::Warn), (&self.deny, Level::Deny)];
+        for (names, level) in flags {
+            for name in names {
+                if let Err(message) = levels.set_named(name, level) {
+                    eprintln!("error: {}", message);
+                    process::exit(1);
+                }
+            }
+        }
+        levels
+    }
+}
+
 #[derive(clap::Args)]
 struct TestArgs {
     /// Path to a .test.md file or directory containing them
@@ -75,6 +163,55 @@ struct TestArgs {
     /// List available categories and exit
     #[arg(long)]
     list_categories: bool,
+
+    #[command(flatten)]
+    lints: LintArgs,
+}
+
+#[derive(clap::Args)]
+struct FmtArgs {
+    /// Markdown files, or directories to search for them
+    #[arg(required = true)]
+    paths: Vec<String>,
+
+    /// Don't write anything; fail if any file is not formatted
+    #[arg(long)]
+    check: bool,
+}
+
+#[derive(clap::Args)]
+struct RenumberArgs {
+    /// Markdown files, or directories to search for them
+    #[arg(required = true)]
+    paths: Vec<String>,
+
+    /// Don't write anything; fail if any fence numbers would change
+    #[arg(long, conflicts_with = "insert")]
+    check: bool,
+
+    /// Instead of renumbering, insert a fence with this number into one file,
+    /// moving the fences numbered N or higher up by one
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        requires_all = ["block", "instruction"]
+    )]
+    insert: Option<u64>,
+
+    /// Block to insert the fence into
+    #[arg(long, requires = "insert")]
+    block: Option<String>,
+
+    /// Instruction of the inserted fence, without its number
+    #[arg(long, requires = "insert")]
+    instruction: Option<String>,
+}
+
+#[derive(clap::Args)]
+struct ExplainArgs {
+    /// Diagnostic code, e.g. E0101 (omit to list every code)
+    code: Option<String>,
 }
 
 fn main() {
@@ -91,65 +228,283 @@ fn main() {
 
     let cli = Cli::parse_from(&args);
 
+    let color_choice = if cli.no_color {
+        ColorChoice::Never
+    } else {
+        ColorChoice::Auto
+    };
+    let mut reporter = Reporter::new(cli.message_format, color_choice);
+
     match cli.command {
-        Command::Run(run_args) => do_run(run_args, cli.no_color),
+        Command::Run(run_args) => do_run(run_args, &mut reporter),
+        Command::Check(check_args) => do_check(check_args, &mut reporter),
+        Command::Explain(explain_args) => do_explain(explain_args),
+        Command::Fmt(fmt_args) => do_fmt(fmt_args, &mut reporter),
+        Command::Renumber(renumber_args) => do_renumber(renumber_args, &mut reporter),
         Command::Test(test_args) => {
             let path = Path::new(&test_args.path);
             if test_args.list_categories {
                 test_runner::list_categories(path);
                 return;
             }
-            let exit_code = test_runner::run_tests(path, cli.no_color, &test_args.category);
-            process::exit(exit_code);
+            let exit_code = test_runner::run_tests(
+                path,
+                cli.no_color,
+                &test_args.category,
+                &test_args.lints.levels(),
+                &mut reporter,
+            );
+            reporter.exit(exit_code);
         }
     }
 }
 
-fn do_run(args: RunArgs, no_color: bool) {
-    let color_choice = if no_color {
-        ColorChoice::Never
-    } else {
-        ColorChoice::Auto
-    };
+/// Read and parse a source file, exiting with reported diagnostics if it has
+/// any error. Returns the program and its parse warnings, which are left for
+/// the caller to report.
+fn load_program(file: &str, reporter: &mut Reporter) -> (mdl::Program, Vec<ParseError>) {
+    let (program, diagnostics) = parse_file(file, reporter);
+    if diagnostics.iter().all(ParseError::is_warning) {
+        return (program, diagnostics);
+    }
+    for diagnostic in &diagnostics {
+        reporter.parse_error(diagnostic);
+    }
+    reporter.exit(1)
+}
 
+/// Read and parse `file`, recovering from syntax errors: the partial program
+/// is returned along with every parse error.
+fn parse_file(file: &str, reporter: &mut Reporter) -> (mdl::Program, Vec<ParseError>) {
     // Read source
-    let source = match std::fs::read_to_string(&args.file) {
+    let source = match std::fs::read_to_string(file) {
         Ok(s) => s,
         Err(e) => {
-            eprintln!("error: cannot read '{}': {}", args.file, e);
+            eprintln!("error: cannot read '{}': {}", file, e);
             process::exit(1);
         }
     };
 
-    // Set up codespan file database
-    let mut files = SimpleFiles::new();
-    let file_id = files.add(args.file.clone(), source.clone());
-
-    // Parse
-    let parser = mdl::parser::Parser::new(source, file_id);
-    let program = match parser.parse() {
-        Ok(p) => p,
-        Err(errors) => {
-            let writer = StandardStream::stderr(color_choice);
-            let config = term::Config::default();
-            for error in &errors {
-                let diagnostic = error.to_diagnostic();
-                let _ =
-                    term::emit_to_write_style(&mut writer.lock(), &config, &files, &diagnostic);
+    // Register the file for diagnostics, then parse
+    let file_id = reporter.add_file(file, source.clone(), base_dir_of(file));
+    mdl::parser::Parser::new(source, file_id).parse_recovering()
+}
+
+/// Directory that relative imports of `file` resolve against.
+fn base_dir_of(file: &str) -> std::path::PathBuf {
+    Path::new(file)
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| std::path::PathBuf::from("."))
+}
+
+fn do_check(args: CheckArgs, reporter: &mut Reporter) {
+    // Syntax errors do not stop the check: the rest of the program is still
+    // analyzed so every problem is reported in one go
+    let (program, mut diagnostics) = parse_file(&args.file, reporter);
+
+    diagnostics.extend(mdl::analysis::check(&program, &base_dir_of(&args.file)));
+    let diagnostics = args.lints.levels().apply(&program, diagnostics);
+    for diagnostic in &diagnostics {
+        reporter.parse_error(diagnostic);
+    }
+
+    let errors = count_errors(&diagnostics);
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 {
+        reporter.status(&format!("error: {} has {} error(s) and {} warning(s)", args.file, errors, warnings));
+        reporter.exit(1);
+    }
+    reporter.status(&format!("ok: {} checked with {} warning(s)", args.file, warnings));
+    reporter.finish();
+}
+
+/// Number of diagnostics reported as errors, including denied warnings.
+fn count_errors(diagnostics: &[ParseError]) -> usize {
+    diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count()
+}
+
+fn do_fmt(args: FmtArgs, reporter: &mut Reporter) {
+    let mut files = Vec::new();
+    for path in &args.paths {
+        collect_markdown_files(Path::new(path), &mut files);
+    }
+    let changed = rewrite_files(&files, args.check, "reformat", mdl::format::format_source);
+    if args.check && changed > 0 {
+        reporter.status(&format!("error: {} of {} file(s) would be reformatted", changed, files.len()));
+        reporter.exit(1);
+    }
+    let verb = if args.check { "already formatted" } else { "formatted" };
+    reporter.status(&format!("ok: {} file(s) {}, {} changed", files.len(), verb, changed));
+    reporter.finish();
+}
+
+fn do_renumber(args: RenumberArgs, reporter: &mut Reporter) {
+    if let (Some(index), Some(block), Some(instruction)) = (args.insert, &args.block, &args.instruction) {
+        let [file] = args.paths.as_slice() else {
+            eprintln!("error: --insert takes a single file");
+            process::exit(1);
+        };
+        let mut found = true;
+        rewrite_files(&[file.into()], false, "renumber", |source| {
+            let mut tree = mdl::cst::SourceFile::parse(source);
+            match tree.find_block_mut(block) {
+                Some(target) => target.insert_fence(index, instruction),
+                None => found = false,
+            }
+            tree.to_string()
+        });
+        if !found {
+            eprintln!("error: no block named '{}' in {}", block, file);
+            process::exit(1);
+        }
+        reporter.status(&format!("ok: inserted fence {} into block '{}'", index, block));
+        reporter.finish();
+        return;
+    }
+
+    let mut files = Vec::new();
+    for path in &args.paths {
+        collect_markdown_files(Path::new(path), &mut files);
+    }
+    let changed = rewrite_files(&files, args.check, "renumber", |source| {
+        let mut tree = mdl::cst::SourceFile::parse(source);
+        tree.renumber();
+        tree.to_string()
+    });
+    if args.check && changed > 0 {
+        reporter.status(&format!("error: {} of {} file(s) would be renumbered", changed, files.len()));
+        reporter.exit(1);
+    }
+    reporter.status(&format!("ok: {} file(s) checked, {} renumbered", files.len(), changed));
+    reporter.finish();
+}
+
+/// Apply `rewrite` to the markdownlang source of each file and write the
+/// files that change back, or with `check`, only list them as files the
+/// command `would <action>`. Returns the number of changed files.
+fn rewrite_files(
+    files: &[std::path::PathBuf],
+    check: bool,
+    action: &str,
+    mut rewrite: impl FnMut(&str) -> String,
+) -> usize {
+    let mut changed = 0;
+    for file in files {
+        let content = match std::fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("error: cannot read '{}': {}", file.display(), e);
+                process::exit(1);
+            }
+        };
+        // The frontmatter of a .test.md file is not markdownlang
+        let (frontmatter, source) = if test_runner::is_test_file(file) {
+            match test_runner::split_frontmatter(&content) {
+                Ok((frontmatter, _, source)) => (frontmatter, source),
+                Err(e) => {
+                    eprintln!("error: cannot read the frontmatter of '{}': {}", file.display(), e);
+                    process::exit(1);
+                }
             }
+        } else {
+            ("", content.as_str())
+        };
+        let rewritten = format!("{}{}", frontmatter, rewrite(source));
+        if rewritten == content {
+            continue;
+        }
+        changed += 1;
+        if check {
+            println!("would {} {}", action, file.display());
+        } else if let Err(e) = std::fs::write(file, rewritten) {
+            eprintln!("error: cannot write '{}': {}", file.display(), e);
             process::exit(1);
         }
+    }
+    changed
+}
+
+/// `path` itself if it is a file, else every `.md` file below it, sorted.
+fn collect_markdown_files(path: &Path, out: &mut Vec<std::path::PathBuf>) {
+    if !path.is_dir() {
+        out.push(path.to_path_buf());
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(path) else {
+        return;
     };
+    let mut paths: Vec<_> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() || path.extension().is_some_and(|ext| ext == "md") {
+            collect_markdown_files(&path, out);
+        }
+    }
+}
+
+fn do_explain(args: ExplainArgs) {
+    use mdl::codes::Code;
+
+    let Some(code) = args.code else {
+        for code in Code::ALL {
+            println!("{}  {}", code, code.summary());
+        }
+        return;
+    };
+    match Code::lookup(&code) {
+        Some(code) => {
+            println!("{}: {}", code, code.summary());
+            println!();
+            print!("{}", code.explanation());
+        }
+        None => {
+            eprintln!("error: unknown diagnostic code '{}'", code);
+            let known = Code::ALL.map(|c| c.as_str());
+            if let Some(note) = mdl::suggest::did_you_mean(&code.to_uppercase(), known) {
+                eprintln!("  = note: {}", note);
+            }
+            eprintln!("  = note: run `mdl explain` to list every code");
+            process::exit(1);
+        }
+    }
+}
+
+fn do_run(args: RunArgs, reporter: &mut Reporter) {
+    let (program, parse_warnings) = load_program(&args.file, reporter);
+    let levels = args.lints.levels();
+
+    // Parse and static analysis warnings (fatal only when denied)
+    let static_warnings = mdl::analysis::analyze(&program);
+    let mut reported = parse_warnings;
+    reported.extend(static_warnings.iter().cloned());
+    let reported = levels.apply(&program, reported);
+    for warning in &reported {
+        reporter.parse_error(warning);
+    }
+    let denied = count_errors(&reported);
+    if denied > 0 {
+        reporter.status(&format!("error: aborting due to {} denied warning(s)", denied));
+        reporter.exit(1);
+    }
 
     // --check: parse succeeded, exit
     if args.check {
-        eprintln!("ok: {} parsed successfully", args.file);
+        reporter.status(&format!("ok: {} parsed successfully", args.file));
+        reporter.finish();
         return;
     }
 
     // --ast: dump AST
-    if args.ast {
-        println!("{:#?}", program);
+    if let Some(format) = args.ast {
+        match format {
+            AstFormat::Debug => println!("{:#?}", program),
+            AstFormat::Json => println!("{}", serde_json::to_string_pretty(&program).expect("the AST serializes")),
+        }
+        reporter.finish();
         return;
     }
 
@@ -164,19 +519,31 @@ fn do_run(args: RunArgs, no_color: bool) {
                 } else {
                     ""
                 };
-                println!("{}{} {} {}", pad, prefix, block.name, has_chain);
+                let params = match &block.parameters {
+                    Some(params) => format!(
+                        " ({})",
+                        params
+                            .iter()
+                            .map(|p| match p.annotation {
+                                Some(annotation) => format!("{}: {}", p.name, annotation),
+                                None => p.name.clone(),
+                            })
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    ),
+                    None => String::new(),
+                };
+                println!("{}{} {}{} {}", pad, prefix, block.name, params, has_chain);
                 print_blocks(&block.children, indent + 1);
             }
         }
         print_blocks(&program.blocks, 0);
+        reporter.finish();
         return;
     }
 
     // Determine base directory for imports
-    let base_dir = Path::new(&args.file)
-        .parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| std::path::PathBuf::from("."));
+    let base_dir = base_dir_of(&args.file);
 
     // Parse CLI arguments into RuntimeValues
     let arguments: Vec<RuntimeValue> = args.args.iter().map(|s| parse_arg(s)).collect();
@@ -190,57 +557,28 @@ fn do_run(args: RunArgs, no_color: bool) {
         interpreter::execute_program_entry(&program, &mut stdout, base_dir, &args.entry, arguments)
     };
 
-    let writer = StandardStream::stderr(color_choice);
-    let config = term::Config::default();
-
     match result {
-        Ok((_value, warnings)) => {
-            emit_diagnostics(&writer, &config, &files, &warnings);
+        Ok((_value, mut warnings)) => {
+            // Same-fence UB found statically is also reported by the runtime
+            warnings.retain(|w| !static_warnings.iter().any(|s| w.repeats(s)));
+            let warnings = interpreter::apply_lint_levels(&levels, &program, warnings);
+            for warning in &warnings {
+                reporter.runtime(warning);
+            }
+            let denied = warnings.iter().filter(|w| !w.is_warning).count();
+            if denied > 0 {
+                reporter.status(&format!("error: {} denied warning(s) at runtime", denied));
+                reporter.exit(1);
+            }
+            reporter.finish();
         }
         Err(error) => {
-            emit_diagnostic_error(&writer, &config, &files, &error);
-            process::exit(1);
+            reporter.runtime(&error);
+            reporter.exit(1);
         }
     }
 }
 
-fn emit_diagnostic_error(
-    writer: &StandardStream,
-    config: &term::Config,
-    files: &SimpleFiles<String, String>,
-    error: &DiagnosticError,
-) {
-    if let Some(span) = &error.span {
-        let severity = if error.is_warning {
-            Severity::Warning
-        } else {
-            Severity::Error
-        };
-        let diagnostic = Diagnostic::new(severity)
-            .with_message(error.to_string())
-            .with_labels(vec![Label::primary(error.source_id, span.clone())]);
-        let _ = term::emit_to_write_style(&mut writer.lock(), config, files, &diagnostic);
-    } else {
-        let prefix = if error.is_warning {
-            "warning"
-        } else {
-            "runtime error"
-        };
-        eprintln!("{}: {}", prefix, error);
-    }
-}
-
-fn emit_diagnostics(
-    writer: &StandardStream,
-    config: &term::Config,
-    files: &SimpleFiles<String, String>,
-    diagnostics: &[DiagnosticError],
-) {
-    for diag in diagnostics {
-        emit_diagnostic_error(writer, config, files, diag);
-    }
-}
-
 /// Parse a CLI argument string into a RuntimeValue.
 /// Numbers become Number, "true"/"false" become Boolean, everything else is String.
 fn parse_arg(s: &str) -> RuntimeValue {
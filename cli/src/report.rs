@@ -0,0 +1,506 @@
+use std::collections::BTreeSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle, Severity};
+use codespan_reporting::files::{Files, SimpleFiles};
+use codespan_reporting::term;
+use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use serde::Serialize;
+use serde_json::json;
+
+use interpreter::DiagnosticError;
+use mdl::block::reference::resolve_import_path;
+use mdl::codes::Code;
+use mdl::parser::ParseError;
+
+/// How diagnostics are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MessageFormat {
+    /// Rendered source snippets for people
+    #[default]
+    Human,
+    /// One JSON object per line
+    Json,
+    /// A single SARIF 2.1.0 log, written when the command finishes
+    Sarif,
+}
+
+/// Writes diagnostics in the chosen format. SARIF results are collected and
+/// written by [`Reporter::finish`], so commands must finish (or
+/// [`Reporter::exit`]) through the reporter.
+pub struct Reporter {
+    sink: Sink,
+    color_choice: ColorChoice,
+    sources: Sources,
+}
+
+/// Where diagnostics go. Human output is rendered as it is reported; only
+/// the machine formats turn diagnostics into records.
+enum Sink {
+    Human,
+    Records(RecordSink),
+}
+
+/// Destination of machine-readable records.
+enum RecordSink {
+    /// One JSON object per line, written as it is reported.
+    Json,
+    /// Results collected for the log written by [`Reporter::finish`].
+    Sarif {
+        results: Vec<serde_json::Value>,
+        rules: BTreeSet<&'static str>,
+    },
+}
+
+/// The registered source files, for locating spans.
+struct Sources {
+    files: SimpleFiles<String, String>,
+    /// Directory imported files are read from, for backtrace locations.
+    base_dir: PathBuf,
+}
+
+impl Reporter {
+    pub fn new(format: MessageFormat, color_choice: ColorChoice) -> Self {
+        let sink = match format {
+            MessageFormat::Human => Sink::Human,
+            MessageFormat::Json => Sink::Records(RecordSink::Json),
+            MessageFormat::Sarif => Sink::Records(RecordSink::Sarif {
+                results: Vec::new(),
+                rules: BTreeSet::new(),
+            }),
+        };
+        Reporter {
+            sink,
+            color_choice,
+            sources: Sources {
+                files: SimpleFiles::new(),
+                base_dir: PathBuf::from("."),
+            },
+        }
+    }
+
+    /// Register a source file; diagnostics refer to it by the returned id.
+    pub fn add_file(&mut self, name: &str, source: String, base_dir: PathBuf) -> usize {
+        self.sources.base_dir = base_dir;
+        self.sources.files.add(name.to_string(), source)
+    }
+
+    pub fn format(&self) -> MessageFormat {
+        match self.sink {
+            Sink::Human => MessageFormat::Human,
+            Sink::Records(RecordSink::Json) => MessageFormat::Json,
+            Sink::Records(RecordSink::Sarif { .. }) => MessageFormat::Sarif,
+        }
+    }
+
+    /// Print a summary line such as `ok: ...`; machine formats skip it.
+    pub fn status(&self, line: &str) {
+        if let Sink::Human = self.sink {
+            eprintln!("{}", line);
+        }
+    }
+
+    /// Report a parse error or static analysis diagnostic.
+    pub fn parse_error(&mut self, error: &ParseError) {
+        match &mut self.sink {
+            Sink::Human => {
+                let writer = StandardStream::stderr(self.color_choice);
+                let config = term::Config::default();
+                let diagnostic = error.to_diagnostic();
+                let _ = term::emit_to_write_style(&mut writer.lock(), &config, &self.sources.files, &diagnostic);
+            }
+            Sink::Records(sink) => sink.write(self.sources.parse_error_record(error)),
+        }
+    }
+
+    /// Report a runtime error or warning, with its backtrace.
+    pub fn runtime(&mut self, error: &DiagnosticError) {
+        match &mut self.sink {
+            Sink::Human => {
+                self.emit_human(error);
+                self.emit_backtrace(error);
+            }
+            Sink::Records(sink) => sink.write(self.sources.runtime_record(error)),
+        }
+    }
+
+    /// Report the outcome of one `.test.md` file (machine formats only).
+    pub fn test_result(&mut self, path: &Path, description: Option<&str>, failure: Option<&str>) {
+        match &mut self.sink {
+            Sink::Human => {}
+            Sink::Records(RecordSink::Json) => {
+                let record = TestRecord {
+                    kind: "test",
+                    path: path.display().to_string(),
+                    description,
+                    outcome: if failure.is_some() { "fail" } else { "pass" },
+                    reason: failure,
+                };
+                emit_json(&record);
+            }
+            Sink::Records(RecordSink::Sarif { results, rules }) => {
+                if let Some(reason) = failure {
+                    rules.insert(TEST_FAILURE_RULE);
+                    results.push(json!({
+                        "ruleId": TEST_FAILURE_RULE,
+                        "level": "error",
+                        "message": { "text": reason },
+                        "locations": [{
+                            "physicalLocation": { "artifactLocation": { "uri": path.display().to_string() } }
+                        }],
+                    }));
+                }
+            }
+        }
+    }
+
+    /// Write any collected SARIF log.
+    pub fn finish(&mut self) {
+        let Sink::Records(RecordSink::Sarif { results, rules }) = &mut self.sink else {
+            return;
+        };
+        let rules: Vec<serde_json::Value> = rules
+            .iter()
+            .map(|id| match Code::lookup(id) {
+                Some(code) => json!({
+                    "id": id,
+                    "shortDescription": { "text": code.summary() },
+                    "fullDescription": { "text": code.explanation() },
+                    "defaultConfiguration": { "level": if code.is_warning() { "warning" } else { "error" } },
+                }),
+                None => json!({
+                    "id": id,
+                    "shortDescription": { "text": "test failed" },
+                }),
+            })
+            .collect();
+        let log = json!({
+            "version": "2.1.0",
+            "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+            "runs": [{
+                "tool": {
+                    "driver": {
+                        "name": "mdl",
+                        "version": env!("CARGO_PKG_VERSION"),
+                        "rules": rules,
+                    }
+                },
+                "results": std::mem::take(results),
+            }],
+        });
+        eprintln!("{}", serde_json::to_string_pretty(&log).expect("SARIF log serializes"));
+    }
+
+    /// Finish reporting and exit with `code`.
+    pub fn exit(&mut self, code: i32) -> ! {
+        self.finish();
+        process::exit(code)
+    }
+
+    fn emit_human(&self, error: &DiagnosticError) {
+        let Some(span) = &error.span else {
+            let prefix = if error.is_warning {
+                "warning"
+            } else {
+                "runtime error"
+            };
+            eprintln!("{}[{}]: {}", prefix, error.code(), error);
+            for note in &error.notes {
+                eprintln!("  = {}", note);
+            }
+            return;
+        };
+        let severity = if error.is_warning {
+            Severity::Warning
+        } else {
+            Severity::Error
+        };
+        let mut labels = vec![Label::primary(error.source_id, span.clone())];
+        labels.extend(error.secondary.iter().map(|label| {
+            Label::secondary(error.source_id, label.span.clone()).with_message(&label.message)
+        }));
+        let diagnostic = Diagnostic::new(severity)
+            .with_message(error.to_string())
+            .with_code(error.code().as_str())
+            .with_labels(labels)
+            .with_notes(error.notes.clone());
+        let writer = StandardStream::stderr(self.color_choice);
+        let config = term::Config::default();
+        let _ = term::emit_to_write_style(&mut writer.lock(), &config, &self.sources.files, &diagnostic);
+    }
+
+    /// Print the call stack of a runtime error, most recent call first.
+    /// Nothing is printed for errors raised directly in the entry block.
+    fn emit_backtrace(&self, error: &DiagnosticError) {
+        if error.trace.len() < 2 {
+            return;
+        }
+        eprintln!("backtrace (most recent call first):");
+        let mut frames = error.trace.iter().rev().enumerate().peekable();
+        while let Some((index, frame)) = frames.next() {
+            // Collapse runs of identical frames, e.g. from unbounded recursion
+            let mut repeats = 0;
+            while frames.next_if(|(_, next)| *next == frame).is_some() {
+                repeats += 1;
+            }
+            match &frame.call_site {
+                Some(span) => {
+                    let location = match self.sources.frame_location(frame, span, error.source_id) {
+                        Some((file, line, column)) => format!("{}:{}:{}", file, line, column),
+                        None => frame.file.clone().unwrap_or_else(|| format!("byte {}", span.start)),
+                    };
+                    eprintln!("  {:>3}: {}, called at {}", index, frame.block, location)
+                }
+                None if index + 1 == error.trace.len() => {
+                    eprintln!("  {:>3}: {} (entry point)", index, frame.block)
+                }
+                None => eprintln!("  {:>3}: {}", index, frame.block),
+            }
+            if repeats > 0 {
+                eprintln!("       [previous frame repeated {} more time(s)]", repeats);
+            }
+        }
+    }
+}
+
+impl RecordSink {
+    fn write(&mut self, record: Record) {
+        match self {
+            RecordSink::Json => emit_json(&record),
+            RecordSink::Sarif { results, rules } => {
+                rules.insert(record.code);
+                results.push(sarif_result(&record));
+            }
+        }
+    }
+}
+
+impl Sources {
+    fn parse_error_record(&self, error: &ParseError) -> Record {
+        let labels = error
+            .labels
+            .iter()
+            .filter_map(|(style, span, message)| {
+                Some(LabelRecord {
+                    span: self.span_record(error.file_id, span)?,
+                    message: message.clone(),
+                    primary: *style == LabelStyle::Primary,
+                })
+            })
+            .collect();
+        Record {
+            kind: "diagnostic",
+            severity: severity_name(error.severity),
+            code: error.code.as_str(),
+            message: error.message.clone(),
+            file: self.file_name(error.file_id),
+            span: self.span_record(error.file_id, &error.span),
+            labels,
+            notes: error.notes.clone(),
+            trace: Vec::new(),
+        }
+    }
+
+    fn runtime_record(&self, error: &DiagnosticError) -> Record {
+        let labels = error
+            .secondary
+            .iter()
+            .filter_map(|label| {
+                Some(LabelRecord {
+                    span: self.span_record(error.source_id, &label.span)?,
+                    message: label.message.clone(),
+                    primary: false,
+                })
+            })
+            .collect();
+        let trace = error
+            .trace
+            .iter()
+            .rev()
+            .map(|frame| {
+                let location = frame
+                    .call_site
+                    .as_ref()
+                    .and_then(|span| self.frame_location(frame, span, error.source_id));
+                FrameRecord {
+                    block: frame.block.clone(),
+                    file: location.as_ref().map(|(file, _, _)| file.clone()),
+                    line: location.as_ref().map(|(_, line, _)| *line),
+                    column: location.as_ref().map(|(_, _, column)| *column),
+                }
+            })
+            .collect();
+        Record {
+            kind: "diagnostic",
+            severity: if error.is_warning { "warning" } else { "error" },
+            code: error.code().as_str(),
+            message: error.to_string(),
+            file: error.span.as_ref().and_then(|_| self.file_name(error.source_id)),
+            span: error.span.as_ref().and_then(|span| self.span_record(error.source_id, span)),
+            labels,
+            notes: error.notes.clone(),
+            trace,
+        }
+    }
+
+    fn file_name(&self, file_id: usize) -> Option<String> {
+        self.files.get(file_id).ok().map(|file| file.name().clone())
+    }
+
+    /// Byte and 1-based line/column positions of `span`.
+    fn span_record(&self, file_id: usize, span: &Range<usize>) -> Option<SpanRecord> {
+        let start = self.files.location(file_id, span.start).ok()?;
+        let end = self.files.location(file_id, span.end).ok()?;
+        Some(SpanRecord {
+            byte_start: span.start,
+            byte_end: span.end,
+            line_start: start.line_number,
+            column_start: start.column_number,
+            line_end: end.line_number,
+            column_end: end.column_number,
+        })
+    }
+
+    /// File, line and column of a call site, reading imported files from
+    /// the base directory.
+    fn frame_location(
+        &self,
+        frame: &interpreter::Frame,
+        span: &Range<usize>,
+        source_id: usize,
+    ) -> Option<(String, usize, usize)> {
+        let (name, source) = match &frame.file {
+            None => {
+                let file = self.files.get(source_id).ok()?;
+                (file.name().clone(), file.source().clone())
+            }
+            Some(path) => {
+                let resolved = resolve_import_path(&self.base_dir, path);
+                let source = std::fs::read_to_string(&resolved).ok()?;
+                (resolved.display().to_string(), source)
+            }
+        };
+        let before = &source[..span.start.min(source.len())];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+        Some((name, line, column))
+    }
+}
+
+/// Rule id of SARIF results for failing `.test.md` files.
+const TEST_FAILURE_RULE: &str = "test-failure";
+
+/// A diagnostic as written by `--message-format=json`.
+#[derive(Serialize)]
+struct Record {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    severity: &'static str,
+    code: &'static str,
+    message: String,
+    /// Null for runtime errors without a source location.
+    file: Option<String>,
+    span: Option<SpanRecord>,
+    labels: Vec<LabelRecord>,
+    notes: Vec<String>,
+    /// Active block invocations of a runtime error, most recent first.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    trace: Vec<FrameRecord>,
+}
+
+#[derive(Serialize)]
+struct SpanRecord {
+    byte_start: usize,
+    byte_end: usize,
+    line_start: usize,
+    column_start: usize,
+    line_end: usize,
+    column_end: usize,
+}
+
+#[derive(Serialize)]
+struct LabelRecord {
+    span: SpanRecord,
+    message: String,
+    primary: bool,
+}
+
+#[derive(Serialize)]
+struct FrameRecord {
+    block: String,
+    file: Option<String>,
+    line: Option<usize>,
+    column: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct TestRecord<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    path: String,
+    description: Option<&'a str>,
+    outcome: &'static str,
+    reason: Option<&'a str>,
+}
+
+fn severity_name(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Bug | Severity::Error => "error",
+        Severity::Warning => "warning",
+        Severity::Note | Severity::Help => "note",
+    }
+}
+
+fn emit_json(record: &impl Serialize) {
+    eprintln!("{}", serde_json::to_string(record).expect("diagnostic record serializes"));
+}
+
+/// A SARIF `result` object for a diagnostic record.
+fn sarif_result(record: &Record) -> serde_json::Value {
+    let mut text = record.message.clone();
+    for note in &record.notes {
+        text.push_str("\nnote: ");
+        text.push_str(note);
+    }
+    let location = |span: &SpanRecord| {
+        json!({
+            "physicalLocation": {
+                "artifactLocation": { "uri": record.file },
+                "region": {
+                    "startLine": span.line_start,
+                    "startColumn": span.column_start,
+                    "endLine": span.line_end,
+                    "endColumn": span.column_end,
+                    "byteOffset": span.byte_start,
+                    "byteLength": span.byte_end - span.byte_start,
+                },
+            }
+        })
+    };
+    let mut result = json!({
+        "ruleId": record.code,
+        "level": if record.severity == "warning" { "warning" } else { "error" },
+        "message": { "text": text },
+    });
+    if let Some(span) = &record.span {
+        result["locations"] = json!([location(span)]);
+    }
+    let related: Vec<serde_json::Value> = record
+        .labels
+        .iter()
+        .filter(|label| !label.primary)
+        .enumerate()
+        .map(|(id, label)| {
+            let mut related = location(&label.span);
+            related["id"] = json!(id);
+            related["message"] = json!({ "text": label.message });
+            related
+        })
+        .collect();
+    if !related.is_empty() {
+        result["relatedLocations"] = json!(related);
+    }
+    result
+}
@@ -1,19 +1,41 @@
 use std::collections::BTreeMap;
+use std::ops::Range;
 use std::panic;
 use std::path::{Path, PathBuf};
 
 use serde::Deserialize;
 
 use interpreter::{DiagnosticError, RuntimeValue};
+use mdl::codes::Code;
+use mdl::lint::LintLevels;
+use mdl::parser::ParseError;
+
+use crate::report::{MessageFormat, Reporter};
 
 #[derive(Debug, Deserialize)]
 pub struct ExpectedWarning {
-    /// Substring that must appear in the warning message.
+    /// Substring that must appear in the diagnostic message.
     pub contains: String,
 
     /// If set, the warning's span must start on this 1-based source line.
     #[serde(default)]
     pub line: Option<usize>,
+
+    /// If set, one of the diagnostic's notes must contain this substring.
+    #[serde(default)]
+    pub note: Option<String>,
+
+    /// If set, the diagnostic's code must be this one, e.g. "W0201".
+    #[serde(default)]
+    pub code: Option<String>,
+}
+
+/// A produced diagnostic, as compared against an [`ExpectedWarning`].
+struct ActualDiagnostic<'a> {
+    code: Code,
+    message: String,
+    span: Option<Range<usize>>,
+    notes: &'a [String],
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,6 +60,14 @@ pub struct TestConfig {
     #[serde(default)]
     pub expect_error: Option<String>,
 
+    /// If set, one of the runtime error's notes must contain this substring.
+    #[serde(default)]
+    pub expect_error_note: Option<String>,
+
+    /// If set, the runtime error's code must be this one, e.g. "E0101".
+    #[serde(default)]
+    pub expect_error_code: Option<String>,
+
     /// If true, the test expects parsing to fail.
     #[serde(default)]
     pub expect_parse_error: bool,
@@ -46,6 +76,12 @@ pub struct TestConfig {
     /// Each entry checks message substring and optionally the source line.
     #[serde(default)]
     pub expect_warnings: Option<Vec<ExpectedWarning>>,
+
+    /// Expected `mdl check` diagnostics (errors and warnings), in order.
+    /// If present (even empty), the static checker runs; when no runtime
+    /// expectations are set the program is not executed.
+    #[serde(default)]
+    pub expect_check: Option<Vec<ExpectedWarning>>,
 }
 
 fn default_entry() -> String {
@@ -62,15 +98,23 @@ fn toml_arg_to_runtime(val: &toml::Value) -> RuntimeValue {
     }
 }
 
-/// Parse a `.test.md` file into its TOML config and mdl source.
-fn parse_test_file(content: &str) -> Result<(TestConfig, &str), String> {
-    let content = content.trim_start_matches('\u{feff}'); // strip BOM
+/// Whether `path` names a `.test.md` file.
+pub fn is_test_file(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| name.ends_with(".test.md"))
+}
+
+/// Split a `.test.md` file into its frontmatter section (`---` delimiters
+/// included), the TOML between the delimiters, and the mdl source after it.
+pub fn split_frontmatter(content: &str) -> Result<(&str, &str, &str), String> {
+    let body = content.trim_start_matches('\u{feff}'); // strip BOM
 
-    if !content.starts_with("---") {
+    if !body.starts_with("---") {
         return Err("missing opening --- frontmatter delimiter".into());
     }
 
-    let after_open = &content[3..];
+    let after_open = &body[3..];
     let after_open = after_open
         .strip_prefix('\n')
         .or_else(|| after_open.strip_prefix("\r\n"))
@@ -87,6 +131,12 @@ fn parse_test_file(content: &str) -> Result<(TestConfig, &str), String> {
         .or_else(|| after_open[rest_start..].strip_prefix('\n'))
         .unwrap_or(&after_open[rest_start..]);
 
+    Ok((&content[..content.len() - source.len()], toml_str, source))
+}
+
+/// Parse a `.test.md` file into its TOML config and mdl source.
+fn parse_test_file(content: &str) -> Result<(TestConfig, &str), String> {
+    let (_, toml_str, source) = split_frontmatter(content)?;
     let config: TestConfig =
         toml::from_str(toml_str).map_err(|e| format!("TOML parse error: {}", e))?;
 
@@ -104,9 +154,9 @@ pub struct TestResult {
     pub outcome: TestOutcome,
 }
 
-fn run_single_test_safe(path: &Path) -> TestResult {
+fn run_single_test_safe(path: &Path, levels: &LintLevels) -> TestResult {
     let path_buf = path.to_path_buf();
-    match panic::catch_unwind(panic::AssertUnwindSafe(|| run_single_test(&path_buf))) {
+    match panic::catch_unwind(panic::AssertUnwindSafe(|| run_single_test(&path_buf, levels))) {
         Ok(result) => result,
         Err(payload) => {
             let msg = if let Some(s) = payload.downcast_ref::<&str>() {
@@ -125,7 +175,7 @@ fn run_single_test_safe(path: &Path) -> TestResult {
     }
 }
 
-fn run_single_test(path: &Path) -> TestResult {
+fn run_single_test(path: &Path, levels: &LintLevels) -> TestResult {
     // 1. Read file
     let content = match std::fs::read_to_string(path) {
         Ok(c) => c,
@@ -150,10 +200,11 @@ fn run_single_test(path: &Path) -> TestResult {
         }
     };
 
-    if config.expect_parse_error == false
+    if !config.expect_parse_error
         && config.expect_error.is_none()
         && config.expect_output.is_none()
         && config.expect_warnings.is_none()
+        && config.expect_check.is_none()
     {
         panic!("test {} has no expectations defined", path.display());
     }
@@ -162,39 +213,64 @@ fn run_single_test(path: &Path) -> TestResult {
 
     // 3. Parse mdl source
     let parser = mdl::parser::Parser::new(source.to_string(), 0);
-    let parse_result = parser.parse();
+    let (program, parse_diagnostics) = parser.parse_recovering();
+    let (parse_warnings, parse_errors): (Vec<ParseError>, Vec<ParseError>) =
+        parse_diagnostics.iter().cloned().partition(ParseError::is_warning);
 
     // 4. Handle expect_parse_error
     if config.expect_parse_error {
         return TestResult {
             path: path.to_path_buf(),
             description,
-            outcome: match parse_result {
-                Err(_) => TestOutcome::Pass,
-                Ok(_) => TestOutcome::Fail("expected parse error, but parsing succeeded".into()),
+            outcome: if parse_errors.is_empty() {
+                TestOutcome::Fail("expected parse error, but parsing succeeded".into())
+            } else {
+                TestOutcome::Pass
             },
         };
     }
 
-    let program = match parse_result {
-        Ok(p) => p,
-        Err(errs) => {
-            let msgs: Vec<String> = errs.iter().map(|e| e.message.clone()).collect();
+    let base_dir = path
+        .parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."));
+
+    // 5. Static checks (`mdl check`), which like the command report syntax
+    // errors alongside the analysis of the rest of the program
+    if let Some(expected) = &config.expect_check {
+        let mut diagnostics = parse_diagnostics.clone();
+        diagnostics.extend(mdl::analysis::check(&program, &base_dir));
+        let diagnostics = levels.apply(&program, diagnostics);
+        if let Some(reason) = check_static_diagnostics(source, &diagnostics, expected) {
             return TestResult {
                 path: path.to_path_buf(),
                 description,
-                outcome: TestOutcome::Fail(format!("unexpected parse error: {}", msgs.join("; "))),
+                outcome: TestOutcome::Fail(reason),
             };
         }
-    };
+        if config.expect_error.is_none()
+            && config.expect_output.is_none()
+            && config.expect_warnings.is_none()
+        {
+            return TestResult {
+                path: path.to_path_buf(),
+                description,
+                outcome: TestOutcome::Pass,
+            };
+        }
+    }
 
-    // 5. Execute
-    let arguments: Vec<RuntimeValue> = config.args.iter().map(toml_arg_to_runtime).collect();
+    if !parse_errors.is_empty() {
+        let msgs: Vec<String> = parse_errors.iter().map(|e| e.message.clone()).collect();
+        return TestResult {
+            path: path.to_path_buf(),
+            description,
+            outcome: TestOutcome::Fail(format!("unexpected parse error: {}", msgs.join("; "))),
+        };
+    }
 
-    let base_dir = path
-        .parent()
-        .map(|p| p.to_path_buf())
-        .unwrap_or_else(|| PathBuf::from("."));
+    // 6. Execute
+    let arguments: Vec<RuntimeValue> = config.args.iter().map(toml_arg_to_runtime).collect();
 
     let mut output_buf = Vec::new();
     let exec_result = interpreter::execute_program_entry(
@@ -205,23 +281,52 @@ fn run_single_test(path: &Path) -> TestResult {
         arguments,
     );
 
-    // 6. Split result into value/error and diagnostics
-    let (exec_result, diagnostics) = match exec_result {
-        Ok((val, diags)) => (Ok(val), diags),
-        Err(err) => (Err(err), Vec::new()),
+    // 7. Split result into value/error and diagnostics.
+    // Parse and static analysis warnings come first, followed by runtime warnings.
+    let static_warnings = mdl::analysis::analyze(&program);
+    let mut diagnostics: Vec<DiagnosticError> = parse_warnings
+        .iter()
+        .chain(&static_warnings)
+        .map(|w| {
+            let mut warning = DiagnosticError::warning(w.code, w.message.clone(), w.span.clone(), w.file_id);
+            warning.notes = w.notes.clone();
+            warning
+        })
+        .collect();
+    let exec_result = match exec_result {
+        Ok((val, diags)) => {
+            diagnostics.extend(
+                diags
+                    .into_iter()
+                    .filter(|d| !static_warnings.iter().any(|w| d.repeats(w))),
+            );
+            Ok(val)
+        }
+        Err(err) => Err(err),
     };
+    let diagnostics = interpreter::apply_lint_levels(levels, &program, diagnostics);
 
-    // 7. Check error/output expectations
+    // 8. Check error/output expectations
     let outcome = match (&config.expect_error, &config.expect_output, exec_result) {
         (Some(expected_err), _, Err(runtime_err)) => {
             let err_str = runtime_err.to_string();
-            if err_str.contains(expected_err.as_str()) {
-                None
-            } else {
+            if !err_str.contains(expected_err.as_str()) {
                 Some(format!(
                     "expected error containing \"{}\", got: {}",
                     expected_err, err_str
                 ))
+            } else if let Some(expected_code) = &config.expect_error_code
+                && !runtime_err.code().as_str().eq_ignore_ascii_case(expected_code)
+            {
+                Some(format!(
+                    "expected error code {}, got {}",
+                    expected_code,
+                    runtime_err.code()
+                ))
+            } else {
+                config.expect_error_note.as_ref().and_then(|expected_note| {
+                    missing_note(&runtime_err.notes, expected_note).map(|reason| format!("error: {}", reason))
+                })
             }
         }
         (Some(expected_err), _, Ok(_)) => Some(format!(
@@ -259,15 +364,20 @@ fn run_single_test(path: &Path) -> TestResult {
         };
     }
 
-    // 8. Check warning expectations
-    if let Some(expected_warnings) = &config.expect_warnings {
-        if let Some(reason) = check_warnings(source, &diagnostics, expected_warnings) {
-            return TestResult {
-                path: path.to_path_buf(),
-                description,
-                outcome: TestOutcome::Fail(reason),
-            };
-        }
+    // 9. Check warning expectations. Without any, denied warnings fail the test.
+    let reason = match &config.expect_warnings {
+        Some(expected_warnings) => check_warnings(source, &diagnostics, expected_warnings),
+        None => diagnostics
+            .iter()
+            .find(|d| !d.is_warning)
+            .map(|d| format!("denied warning[{}]: {}", d.code(), d)),
+    };
+    if let Some(reason) = reason {
+        return TestResult {
+            path: path.to_path_buf(),
+            description,
+            outcome: TestOutcome::Fail(reason),
+        };
     }
 
     TestResult {
@@ -292,18 +402,56 @@ fn check_warnings(
     diagnostics: &[DiagnosticError],
     expected: &[ExpectedWarning],
 ) -> Option<String> {
-    let actual_warnings: Vec<&DiagnosticError> =
-        diagnostics.iter().filter(|d| d.is_warning).collect();
+    // Runtime errors are checked separately; every diagnostic here is a
+    // warning, possibly denied
+    let actual: Vec<ActualDiagnostic> = diagnostics
+        .iter()
+        .map(|d| ActualDiagnostic {
+            code: d.code(),
+            message: d.to_string(),
+            span: d.span.clone(),
+            notes: &d.notes,
+        })
+        .collect();
+    check_expected("warning", source, &actual, expected)
+}
+
+/// Check that `mdl check` diagnostics (errors and warnings) match expectations.
+fn check_static_diagnostics(
+    source: &str,
+    diagnostics: &[mdl::parser::ParseError],
+    expected: &[ExpectedWarning],
+) -> Option<String> {
+    let actual: Vec<ActualDiagnostic> = diagnostics
+        .iter()
+        .map(|d| ActualDiagnostic {
+            code: d.code,
+            message: d.message.clone(),
+            span: Some(d.span.clone()),
+            notes: &d.notes,
+        })
+        .collect();
+    check_expected("diagnostic", source, &actual, expected)
+}
 
-    if actual_warnings.len() != expected.len() {
-        let actual_msgs: Vec<String> = actual_warnings
+/// Compare produced diagnostics against expectations, in order.
+fn check_expected(
+    kind: &str,
+    source: &str,
+    actual: &[ActualDiagnostic],
+    expected: &[ExpectedWarning],
+) -> Option<String> {
+    if actual.len() != expected.len() {
+        let actual_msgs: Vec<String> = actual
             .iter()
-            .map(|w| format!("  - {}", w))
+            .map(|d| format!("  - {}", d.message))
             .collect();
         return Some(format!(
-            "expected {} warning(s), got {}\n  actual warnings:\n{}",
+            "expected {} {}(s), got {}\n  actual {}s:\n{}",
             expected.len(),
-            actual_warnings.len(),
+            kind,
+            actual.len(),
+            kind,
             if actual_msgs.is_empty() {
                 "    (none)".to_string()
             } else {
@@ -312,29 +460,42 @@ fn check_warnings(
         ));
     }
 
-    for (i, (actual, expected)) in actual_warnings.iter().zip(expected.iter()).enumerate() {
-        let msg = actual.to_string();
+    for (i, (actual, expected)) in actual.iter().zip(expected.iter()).enumerate() {
+        if !actual.message.contains(&expected.contains) {
+            return Some(format!(
+                "{}[{}]: expected message containing \"{}\", got: {}",
+                kind, i, expected.contains, actual.message
+            ));
+        }
 
-        if !msg.contains(&expected.contains) {
+        if let Some(expected_code) = &expected.code
+            && !actual.code.as_str().eq_ignore_ascii_case(expected_code)
+        {
             return Some(format!(
-                "warning[{}]: expected message containing \"{}\", got: {}",
-                i, expected.contains, msg
+                "{}[{}]: expected code {}, got {}",
+                kind, i, expected_code, actual.code
             ));
         }
 
+        if let Some(expected_note) = &expected.note
+            && let Some(reason) = missing_note(actual.notes, expected_note)
+        {
+            return Some(format!("{}[{}]: {}", kind, i, reason));
+        }
+
         if let Some(expected_line) = expected.line {
             if let Some(span) = &actual.span {
                 let actual_line = byte_offset_to_line(source, span.start);
                 if actual_line != expected_line {
                     return Some(format!(
-                        "warning[{}]: expected on line {}, but span is on line {}",
-                        i, expected_line, actual_line
+                        "{}[{}]: expected on line {}, but span is on line {}",
+                        kind, i, expected_line, actual_line
                     ));
                 }
             } else {
                 return Some(format!(
-                    "warning[{}]: expected on line {}, but warning has no span",
-                    i, expected_line
+                    "{}[{}]: expected on line {}, but {} has no span",
+                    kind, i, expected_line, kind
                 ));
             }
         }
@@ -343,6 +504,22 @@ fn check_warnings(
     None
 }
 
+/// Describe why no note contains `expected`, or None if one does.
+fn missing_note(notes: &[String], expected: &str) -> Option<String> {
+    if notes.iter().any(|note| note.contains(expected)) {
+        return None;
+    }
+    Some(format!(
+        "expected a note containing \"{}\", got: {}",
+        expected,
+        if notes.is_empty() {
+            "(no notes)".to_string()
+        } else {
+            notes.join("; ")
+        }
+    ))
+}
+
 /// Discover `.test.md` files grouped by category (subfolder relative to root).
 /// Files directly in `root` get category "" (uncategorized).
 /// Returns a BTreeMap so categories are sorted alphabetically.
@@ -364,15 +541,13 @@ fn collect_tests(dir: &Path, root: &Path, out: &mut BTreeMap<String, Vec<PathBuf
         let path = entry.path();
         if path.is_dir() {
             collect_tests(&path, root, out);
-        } else if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-            if name.ends_with(".test.md") {
-                let category = path
-                    .parent()
-                    .and_then(|p| p.strip_prefix(root).ok())
-                    .map(|p| p.to_string_lossy().replace('\\', "/"))
-                    .unwrap_or_default();
-                out.entry(category).or_default().push(path);
-            }
+        } else if is_test_file(&path) {
+            let category = path
+                .parent()
+                .and_then(|p| p.strip_prefix(root).ok())
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_default();
+            out.entry(category).or_default().push(path);
         }
     }
 }
@@ -425,38 +600,67 @@ fn bold(s: &str, no_color: bool) -> String {
     }
 }
 
+/// Hand a test outcome to the reporter for machine-readable output.
+fn report_result(reporter: &mut Reporter, result: &TestResult) {
+    let failure = match &result.outcome {
+        TestOutcome::Pass => None,
+        TestOutcome::Fail(reason) => Some(reason.as_str()),
+    };
+    reporter.test_result(&result.path, result.description.as_deref(), failure);
+}
+
 /// Run all `.test.md` files under `path` (or a single file).
 /// If `categories` is non-empty, only run tests in those categories.
+/// Warnings at a `levels` level of deny fail tests that do not list their
+/// expected warnings.
 /// Returns exit code: 0 = all pass, 1 = any failure.
-pub fn run_tests(path: &Path, no_color: bool, categories: &[String]) -> i32 {
+pub fn run_tests(
+    path: &Path,
+    no_color: bool,
+    categories: &[String],
+    levels: &LintLevels,
+    reporter: &mut Reporter,
+) -> i32 {
+    // Progress and summary lines are for people; machine formats get one
+    // record per test from the reporter instead
+    let human = reporter.format() == MessageFormat::Human;
+    macro_rules! say {
+        ($($arg:tt)*) => {
+            if human {
+                eprintln!($($arg)*);
+            }
+        };
+    }
+
     // Single file mode — ignore categories
     if path.is_file() {
-        let result = run_single_test_safe(path);
+        let result = run_single_test_safe(path, levels);
+        report_result(reporter, &result);
         let label = result
             .description
             .as_deref()
             .unwrap_or_else(|| path.file_stem().and_then(|s| s.to_str()).unwrap_or("?"));
         return match &result.outcome {
             TestOutcome::Pass => {
-                eprintln!("  {}  {}", pass_label(no_color), label);
-                eprintln!();
-                eprintln!(
+                say!("  {}  {}", pass_label(no_color), label);
+                say!();
+                say!(
                     "test result: {}. 1 passed, 0 failed",
                     if no_color { "ok" } else { "\x1b[32mok\x1b[0m" }
                 );
                 0
             }
             TestOutcome::Fail(reason) => {
-                eprintln!("  {}  {}", fail_label(no_color), label);
-                eprintln!();
-                eprintln!("failures:");
-                eprintln!();
-                eprintln!("  --- {} ---", path.display());
+                say!("  {}  {}", fail_label(no_color), label);
+                say!();
+                say!("failures:");
+                say!();
+                say!("  --- {} ---", path.display());
                 for line in reason.lines() {
-                    eprintln!("  {}", line);
+                    say!("  {}", line);
                 }
-                eprintln!();
-                eprintln!(
+                say!();
+                say!(
                     "test result: {}. 0 passed, 1 failed (of 1)",
                     if no_color {
                         "FAILED"
@@ -524,11 +728,12 @@ pub fn run_tests(path: &Path, no_color: bool, categories: &[String]) -> i32 {
         } else {
             cat.to_string()
         };
-        eprintln!();
-        eprintln!("{}", bold(&header, no_color));
+        say!();
+        say!("{}", bold(&header, no_color));
 
         for file in *files {
-            let result = run_single_test_safe(file);
+            let result = run_single_test_safe(file, levels);
+            report_result(reporter, &result);
             let label = result
                 .description
                 .as_deref()
@@ -537,11 +742,11 @@ pub fn run_tests(path: &Path, no_color: bool, categories: &[String]) -> i32 {
             match &result.outcome {
                 TestOutcome::Pass => {
                     passed += 1;
-                    eprintln!("  {}  {}", pass_label(no_color), label);
+                    say!("  {}  {}", pass_label(no_color), label);
                 }
                 TestOutcome::Fail(_) => {
                     failed += 1;
-                    eprintln!("  {}  {}", fail_label(no_color), label);
+                    say!("  {}  {}", fail_label(no_color), label);
                     failures.push(result);
                 }
             }
@@ -550,26 +755,26 @@ pub fn run_tests(path: &Path, no_color: bool, categories: &[String]) -> i32 {
 
     // Print failure details
     if !failures.is_empty() {
-        eprintln!();
-        eprintln!("failures:");
+        say!();
+        say!("failures:");
         for f in &failures {
-            eprintln!();
-            eprintln!("  --- {} ---", f.path.display());
+            say!();
+            say!("  --- {} ---", f.path.display());
             if let TestOutcome::Fail(reason) = &f.outcome {
                 for line in reason.lines() {
-                    eprintln!("  {}", line);
+                    say!("  {}", line);
                 }
             }
         }
     }
 
     // Summary
-    eprintln!();
+    say!();
     if failed == 0 {
         if no_color {
-            eprintln!("test result: ok. {} passed, 0 failed", passed);
+            say!("test result: ok. {} passed, 0 failed", passed);
         } else {
-            eprintln!(
+            say!(
                 "test result: \x1b[32mok\x1b[0m. {} passed, 0 failed",
                 passed
             );
@@ -578,12 +783,12 @@ pub fn run_tests(path: &Path, no_color: bool, categories: &[String]) -> i32 {
     } else {
         let total = passed + failed;
         if no_color {
-            eprintln!(
+            say!(
                 "test result: FAILED. {} passed, {} failed (of {})",
                 passed, failed, total
             );
         } else {
-            eprintln!(
+            say!(
                 "test result: \x1b[31mFAILED\x1b[0m. {} passed, {} failed (of {})",
                 passed, failed, total
             );
@@ -0,0 +1,25 @@
+mod common;
+
+use common::{mdl, temp_file};
+
+#[test]
+fn ast_json_is_one_document() {
+    let source = "# Main (n: Number = 2)\n\n1. x = n * 3\n<!-- mdl-allow: W0201 -->\n2. **{x}**\n";
+    let (_dir, file) = temp_file("main.md", source);
+    let output = mdl(&["run", "--ast=json", file.to_str().unwrap()]);
+    assert!(output.status.success());
+
+    let ast: serde_json::Value = serde_json::from_slice(&output.stdout).expect("stdout is JSON");
+    let main = &ast["blocks"][0];
+    assert_eq!(main["name"], "Main");
+    assert_eq!(main["parameters"][0]["annotation"], "Number");
+    assert_eq!(main["parameters"][0]["default"]["NumberLiteral"][0], 2.0);
+
+    let groups = main["chain"]["groups"].as_array().unwrap();
+    assert_eq!(groups.len(), 2);
+    let assignment = &groups[0]["instructions"][0]["Assignment"];
+    assert_eq!(assignment["variable"], "x");
+    assert_eq!(assignment["value"]["BinaryOperation"]["operator"], "Multiplication");
+    assert_eq!(assignment["value"]["BinaryOperation"]["operator_span"], serde_json::json!({"start": 33, "end": 34}));
+    assert_eq!(ast["suppressions"][0]["codes"], serde_json::json!(["W0201"]));
+}
@@ -0,0 +1,20 @@
+//! Helpers shared by the command-line tests.
+
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use tempfile::TempDir;
+
+/// A new temporary directory holding the file `name` with `content`. The
+/// directory is removed when the returned guard is dropped.
+pub fn temp_file(name: &str, content: &str) -> (TempDir, PathBuf) {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let file = dir.path().join(name);
+    std::fs::write(&file, content).unwrap();
+    (dir, file)
+}
+
+/// Run the `mdl` binary with `args`.
+pub fn mdl(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mdl")).args(args).output().expect("failed to run mdl")
+}
@@ -0,0 +1,36 @@
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use common::{mdl, temp_file};
+
+fn mdl_fmt(args: &[&str], path: &Path) -> Output {
+    mdl(&[&["fmt"], args, &[path.to_str().unwrap()]].concat())
+}
+
+#[test]
+fn check_reports_files_and_fmt_rewrites_them() {
+    let (dir, messy) = temp_file("messy.test.md", "---\nexpect_output = \"3\"\n---\n\n# Main\n1) x=1+2\n2. **{ x }**");
+    std::fs::write(dir.path().join("tidy.md"), "# Main\n1. **{1}**\n").unwrap();
+
+    let output = mdl_fmt(&["--check"], dir.path());
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("messy.test.md") && !stdout.contains("tidy.md"), "{stdout}");
+
+    assert!(mdl_fmt(&[], dir.path()).status.success());
+    assert_eq!(
+        std::fs::read_to_string(&messy).unwrap(),
+        "---\nexpect_output = \"3\"\n---\n\n# Main\n1. x = 1 + 2\n2. **{x}**\n"
+    );
+    assert!(mdl_fmt(&["--check"], dir.path()).status.success());
+}
+
+#[test]
+fn only_test_files_have_frontmatter() {
+    let (dir, plain) = temp_file("plain.md", "---\n\n# Main\n1) x=1\n\n---\n");
+
+    assert!(mdl_fmt(&[], dir.path()).status.success());
+    assert_eq!(std::fs::read_to_string(&plain).unwrap(), "---\n\n# Main\n1. x = 1\n\n---\n");
+}
@@ -0,0 +1,70 @@
+mod common;
+
+use common::{mdl, temp_file};
+
+const PROGRAM: &str = "# Main\n\n1. x = 1\n1. **{x}**\n2. [](#Nope)\n";
+
+/// Run `mdl` on PROGRAM and return its stderr.
+fn mdl_stderr(args: &[&str]) -> String {
+    let (_dir, file) = temp_file("main.md", PROGRAM);
+    let output = mdl(&[args, &[file.to_str().unwrap()]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    String::from_utf8(output.stderr).unwrap()
+}
+
+#[test]
+fn json_writes_one_object_per_diagnostic() {
+    let stderr = mdl_stderr(&["check", "--message-format=json"]);
+    let records: Vec<serde_json::Value> = stderr
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line is a JSON object"))
+        .collect();
+    assert_eq!(records.len(), 2);
+
+    let warning = &records[0];
+    assert_eq!(warning["severity"], "warning");
+    assert_eq!(warning["code"], "W0201");
+    assert_eq!(warning["span"]["line_start"], 4);
+    assert_eq!(warning["span"]["column_start"], 7);
+    assert_eq!(warning["span"]["byte_start"], 23);
+    assert_eq!(warning["labels"][1]["message"], "assigned here");
+    assert!(warning["notes"][0].as_str().unwrap().contains("may run in any order"));
+    assert!(warning["file"].as_str().unwrap().ends_with("main.md"));
+
+    assert_eq!(records[1]["severity"], "error");
+    assert_eq!(records[1]["code"], "E0101");
+}
+
+#[test]
+fn json_reports_runtime_errors() {
+    let stderr = mdl_stderr(&["run", "--message-format=json", "-A", "W0201"]);
+    let record: serde_json::Value = serde_json::from_str(stderr.trim()).unwrap();
+    assert_eq!(record["code"], "E0101");
+    assert_eq!(record["message"], "undefined block: Nope");
+    assert_eq!(record["span"]["line_start"], 5);
+}
+
+#[test]
+fn sarif_writes_a_single_log() {
+    let stderr = mdl_stderr(&["check", "--message-format=sarif"]);
+    let log: serde_json::Value = serde_json::from_str(&stderr).expect("stderr is one SARIF document");
+    assert_eq!(log["version"], "2.1.0");
+
+    let run = &log["runs"][0];
+    let rules: Vec<&str> = run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rule| rule["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(rules, ["E0101", "W0201"]);
+
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["ruleId"], "W0201");
+    assert_eq!(results[0]["level"], "warning");
+    let region = &results[0]["locations"][0]["physicalLocation"]["region"];
+    assert_eq!(region["startLine"], 4);
+    assert_eq!(region["startColumn"], 7);
+    assert_eq!(results[0]["relatedLocations"][0]["message"]["text"], "assigned here");
+}
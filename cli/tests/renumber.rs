@@ -0,0 +1,20 @@
+mod common;
+
+use common::{mdl, temp_file};
+
+#[test]
+fn renumber_compacts_and_inserts_fences() {
+    let (_dir, file) = temp_file("main.md", "# Main\n10. x = 1\n20. **{x}**\n");
+    let path = file.to_str().unwrap();
+
+    assert_eq!(mdl(&["renumber", "--check", path]).status.code(), Some(1));
+    assert!(mdl(&["renumber", path]).status.success());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "# Main\n1. x = 1\n2. **{x}**\n");
+
+    let insert = ["renumber", path, "--insert", "2", "--block", "main", "--instruction", "x = 2"];
+    assert_eq!(mdl(&insert).status.code(), Some(1), "block names are case-sensitive");
+    let insert = ["renumber", path, "--insert", "2", "--block", "Main", "--instruction", "x = 2"];
+    assert!(mdl(&insert).status.success());
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "# Main\n1. x = 1\n2. x = 2\n3. **{x}**\n");
+    assert_eq!(String::from_utf8(mdl(&["run", path]).stdout).unwrap().trim(), "2");
+}
@@ -1,6 +1,10 @@
 use std::collections::HashMap;
 use std::ops::Range;
 
+use mdl::block::scope::BlockPath;
+use mdl::instruction::types::TypeAnnotation;
+
+use crate::error::Frame;
 use crate::runtime_value::RuntimeValue;
 
 /// Tracks variable reads and writes within a single fence group for UB detection.
@@ -8,12 +12,41 @@ use crate::runtime_value::RuntimeValue;
 struct FenceContext {
     /// Variables read during this fence group: name → [(instruction_index, span)].
     reads: HashMap<String, Vec<(usize, Range<usize>)>>,
-    /// Variables written during this fence group: name → [instruction_index].
-    writes: HashMap<String, Vec<usize>>,
+    /// Variables written during this fence group: name → [(instruction_index, span)].
+    writes: HashMap<String, Vec<(usize, Range<usize>)>>,
     /// Index of the currently executing instruction within this fence group.
     current_instruction: usize,
 }
 
+/// An access pattern within one fence group whose outcome depends on the
+/// (undefined) order in which the group's instructions run.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FenceConflict {
+    /// The variable is read by one instruction and written by others;
+    /// `writes` holds the spans of those conflicting writes.
+    ReadWrite {
+        variable: String,
+        read: Range<usize>,
+        writes: Vec<Range<usize>>,
+    },
+    /// The variable is written by more than one instruction; `writes` holds
+    /// every write span in execution order.
+    WriteWrite {
+        variable: String,
+        writes: Vec<Range<usize>>,
+    },
+}
+
+impl FenceConflict {
+    /// Where the conflict is reported: the read, or the second write.
+    pub fn span(&self) -> &Range<usize> {
+        match self {
+            FenceConflict::ReadWrite { read, .. } => read,
+            FenceConflict::WriteWrite { writes, .. } => &writes[1],
+        }
+    }
+}
+
 /// Result of looking up a variable in the environment.
 pub enum VariableLookup<'a> {
     /// Variable found with a value.
@@ -25,12 +58,39 @@ pub enum VariableLookup<'a> {
         /// current block — reading such a variable is undefined behavior.
         non_lexical_scope: bool,
     },
+    /// Variable is a pattern binding of the match arm being evaluated.
+    Binding(&'a RuntimeValue),
     /// Variable is hoisted (declared) but has not been assigned yet — UB.
     HoistedUnassigned,
     /// Variable does not exist in any scope.
     NotFound,
 }
 
+/// Evaluated arguments of a block invocation.
+#[derive(Debug, Clone, Default)]
+pub struct Arguments {
+    /// Positional arguments, in call order.
+    pub positional: Vec<RuntimeValue>,
+    /// Named arguments (`name: value`), in call order.
+    pub named: Vec<(String, RuntimeValue)>,
+}
+
+impl Arguments {
+    /// Look up a named argument.
+    pub fn get_named(&self, name: &str) -> Option<&RuntimeValue> {
+        self.named.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+impl From<Vec<RuntimeValue>> for Arguments {
+    fn from(positional: Vec<RuntimeValue>) -> Self {
+        Arguments {
+            positional,
+            named: Vec::new(),
+        }
+    }
+}
+
 /// A single scope level, corresponding to a block invocation.
 #[derive(Debug, Clone)]
 pub struct Scope {
@@ -39,15 +99,27 @@ pub struct Scope {
     variables: HashMap<String, Option<RuntimeValue>>,
     /// Positional arguments (#0, #1, ...).
     arguments: Vec<RuntimeValue>,
+    /// Named arguments (#name, #:).
+    named_arguments: Vec<(String, RuntimeValue)>,
+    /// Type annotations of parameters and variables, checked on assignment.
+    declared_types: HashMap<String, TypeAnnotation>,
     /// Name of the block this scope belongs to.
     block_name: String,
     /// Names of all lexical ancestor blocks (parent, grandparent, ...).
     lexical_ancestors: Vec<String>,
+    /// Pattern bindings of the match arms currently being evaluated, innermost last.
+    /// They shadow block variables and are dropped once the arm result is evaluated.
+    bindings: Vec<HashMap<String, RuntimeValue>>,
 }
 
 impl Scope {
+    /// Create a scope for a block invocation. Declared `parameters` are bound
+    /// to the positional arguments as already-assigned hoisted variables;
+    /// parameters past the last positional argument stay unassigned until
+    /// [`Scope::push_argument`] fills them from a named argument or default.
     pub fn new(
-        arguments: Vec<RuntimeValue>,
+        arguments: Arguments,
+        parameters: &[String],
         hoisted_vars: Vec<String>,
         block_name: String,
         lexical_ancestors: Vec<String>,
@@ -56,14 +128,25 @@ impl Scope {
         for var in hoisted_vars {
             variables.insert(var, None);
         }
+        for (idx, name) in parameters.iter().enumerate() {
+            variables.insert(name.clone(), arguments.positional.get(idx).cloned());
+        }
         Scope {
             variables,
-            arguments,
+            arguments: arguments.positional,
+            named_arguments: arguments.named,
+            declared_types: HashMap::new(),
             block_name,
             lexical_ancestors,
+            bindings: Vec::new(),
         }
     }
 
+    /// Look up a pattern binding, searching from the innermost arm outward.
+    pub fn get_binding(&self, name: &str) -> Option<&RuntimeValue> {
+        self.bindings.iter().rev().find_map(|frame| frame.get(name))
+    }
+
     pub fn get_variable(&self, name: &str) -> Option<&Option<RuntimeValue>> {
         self.variables.get(name)
     }
@@ -79,6 +162,21 @@ impl Scope {
     pub fn get_all_arguments(&self) -> &[RuntimeValue] {
         &self.arguments
     }
+
+    /// Record the annotated type of a parameter or variable.
+    pub fn declare_type(&mut self, name: &str, annotation: TypeAnnotation) {
+        self.declared_types.insert(name.to_string(), annotation);
+    }
+
+    pub fn declared_type(&self, name: &str) -> Option<TypeAnnotation> {
+        self.declared_types.get(name).copied()
+    }
+
+    /// Append a filled-in argument, binding it to `parameter`.
+    pub fn push_argument(&mut self, parameter: &str, value: RuntimeValue) {
+        self.set_variable(parameter, value.clone());
+        self.arguments.push(value);
+    }
 }
 
 /// The full environment is a stack of scopes.
@@ -88,6 +186,20 @@ pub struct Environment {
     scopes: Vec<Scope>,
     /// Stack of fence contexts for same-fence UB detection.
     fence_stack: Vec<FenceContext>,
+    /// Active block invocations, outermost first (attached to errors).
+    call_stack: Vec<Frame>,
+    /// For each frame, the local import path of the invoked block's file
+    /// (None for the main program).
+    frame_files: Vec<Option<String>>,
+    /// For each frame, where the invoked block sits in the main program
+    /// (empty for imported blocks). Local references resolve from here.
+    frame_paths: Vec<BlockPath>,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Environment {
@@ -95,9 +207,47 @@ impl Environment {
         Environment {
             scopes: Vec::new(),
             fence_stack: Vec::new(),
+            call_stack: Vec::new(),
+            frame_files: Vec::new(),
+            frame_paths: Vec::new(),
         }
     }
 
+    /// Enter a block invoked at `call_site` from the current file.
+    /// `file` is the local import the block comes from, if any, and `path`
+    /// where it sits in the main program.
+    pub fn push_frame(&mut self, block: &str, path: BlockPath, call_site: Option<Range<usize>>, file: Option<String>) {
+        self.call_stack.push(Frame {
+            block: block.to_string(),
+            call_site,
+            file: self.current_file().map(str::to_string),
+        });
+        self.frame_files.push(file);
+        self.frame_paths.push(path);
+    }
+
+    /// Leave the innermost block invocation.
+    pub fn pop_frame(&mut self) {
+        self.call_stack.pop();
+        self.frame_files.pop();
+        self.frame_paths.pop();
+    }
+
+    /// Local import path of the file the executing block comes from; None for the main program.
+    pub fn current_file(&self) -> Option<&str> {
+        self.frame_files.last().and_then(|file| file.as_deref())
+    }
+
+    /// Path of the executing block in the main program; empty outside it.
+    pub fn current_path(&self) -> &[usize] {
+        self.frame_paths.last().map_or(&[], Vec::as_slice)
+    }
+
+    /// The active block invocations, outermost first.
+    pub fn call_stack(&self) -> &[Frame] {
+        &self.call_stack
+    }
+
     /// Begin tracking accesses for a new fence group.
     pub fn push_fence_context(&mut self) {
         self.fence_stack.push(FenceContext::default());
@@ -122,38 +272,55 @@ impl Environment {
     }
 
     /// Record a variable write in the current fence context.
-    pub fn record_fence_write(&mut self, name: &str) {
+    pub fn record_fence_write(&mut self, name: &str, span: Range<usize>) {
         if let Some(ctx) = self.fence_stack.last_mut() {
             let idx = ctx.current_instruction;
-            ctx.writes.entry(name.to_string()).or_default().push(idx);
+            ctx.writes
+                .entry(name.to_string())
+                .or_default()
+                .push((idx, span));
         }
     }
 
-    /// End the current fence context and return UB violations:
-    /// variables that were read by one instruction and written by a
-    /// different instruction within the same fence group.
-    /// Returns (variable_name, read_spans) pairs.
-    pub fn pop_fence_context(&mut self) -> Vec<(String, Vec<Range<usize>>)> {
+    /// End the current fence context and return UB violations: variables
+    /// read by one instruction and written by a different instruction, and
+    /// variables written by more than one instruction of the fence group,
+    /// in source order.
+    pub fn pop_fence_context(&mut self) -> Vec<FenceConflict> {
         let ctx = self.fence_stack.pop().expect("no fence context to pop");
         let mut violations = Vec::new();
 
         for (name, read_entries) in &ctx.reads {
-            if let Some(write_indices) = ctx.writes.get(name) {
+            if let Some(write_entries) = ctx.writes.get(name) {
                 // Flag reads from instructions different than any write instruction
-                let ub_spans: Vec<Range<usize>> = read_entries
-                    .iter()
-                    .filter(|(read_idx, _)| {
-                        write_indices.iter().any(|write_idx| read_idx != write_idx)
-                    })
-                    .map(|(_, span)| span.clone())
-                    .collect();
-
-                if !ub_spans.is_empty() {
-                    violations.push((name.clone(), ub_spans));
+                for (read_idx, span) in read_entries {
+                    let writes: Vec<Range<usize>> = write_entries
+                        .iter()
+                        .filter(|(write_idx, _)| read_idx != write_idx)
+                        .map(|(_, write_span)| write_span.clone())
+                        .collect();
+                    if !writes.is_empty() {
+                        violations.push(FenceConflict::ReadWrite {
+                            variable: name.clone(),
+                            read: span.clone(),
+                            writes,
+                        });
+                    }
                 }
             }
         }
 
+        for (name, write_entries) in &ctx.writes {
+            if write_entries.iter().any(|(idx, _)| *idx != write_entries[0].0) {
+                violations.push(FenceConflict::WriteWrite {
+                    variable: name.clone(),
+                    writes: write_entries.iter().map(|(_, span)| span.clone()).collect(),
+                });
+            }
+        }
+
+        // Accesses are grouped in hash maps; report in a stable order
+        violations.sort_by_key(|conflict| (conflict.span().start, conflict.span().end));
         violations
     }
 
@@ -169,8 +336,21 @@ impl Environment {
         self.scopes.last_mut().expect("no scope on stack")
     }
 
+    /// Enter a match arm: make its pattern bindings visible in the current scope.
+    pub fn push_bindings(&mut self, bindings: HashMap<String, RuntimeValue>) {
+        self.current_scope_mut().bindings.push(bindings);
+    }
+
+    /// Leave a match arm: drop the bindings pushed by the matching `push_bindings`.
+    pub fn pop_bindings(&mut self) {
+        self.current_scope_mut().bindings.pop();
+    }
+
     /// Look up a variable, searching from innermost scope outward.
     pub fn get_variable(&self, name: &str) -> Option<&RuntimeValue> {
+        if let Some(val) = self.scopes.last().and_then(|s| s.get_binding(name)) {
+            return Some(val);
+        }
         for scope in self.scopes.iter().rev() {
             if let Some(val) = scope.get_variable(name) {
                 return val.as_ref();
@@ -182,6 +362,9 @@ impl Environment {
     /// Look up a variable with scope information.
     pub fn get_variable_info(&self, name: &str) -> VariableLookup<'_> {
         let current = self.scopes.last().expect("no scope on stack");
+        if let Some(val) = current.get_binding(name) {
+            return VariableLookup::Binding(val);
+        }
         for (depth, scope) in self.scopes.iter().rev().enumerate() {
             if let Some(val) = scope.get_variable(name) {
                 let is_cross_scope = depth > 0;
@@ -203,6 +386,9 @@ impl Environment {
 
     /// Check if a variable name exists in any scope (hoisted).
     pub fn has_variable(&self, name: &str) -> bool {
+        if self.scopes.last().is_some_and(|s| s.get_binding(name).is_some()) {
+            return true;
+        }
         for scope in self.scopes.iter().rev() {
             if scope.get_variable(name).is_some() {
                 return true;
@@ -211,6 +397,27 @@ impl Environment {
         false
     }
 
+    /// Names readable without undefined behavior from the current block:
+    /// match bindings, its own variables and those of its lexical ancestors.
+    pub fn visible_variables(&self) -> Vec<&str> {
+        let Some(current) = self.scopes.last() else {
+            return Vec::new();
+        };
+        let mut names: Vec<&str> = current
+            .bindings
+            .iter()
+            .flat_map(|frame| frame.keys().map(String::as_str))
+            .collect();
+        for scope in self.scopes.iter().rev() {
+            if scope.block_name == current.block_name
+                || current.lexical_ancestors.contains(&scope.block_name)
+            {
+                names.extend(scope.variables.keys().map(String::as_str));
+            }
+        }
+        names
+    }
+
     /// Set a variable in the current (innermost) scope.
     pub fn set_variable(&mut self, name: &str, value: RuntimeValue) {
         // First check if the variable is hoisted in the current scope
@@ -223,6 +430,29 @@ impl Environment {
         self.scopes.last()?.get_argument(index)
     }
 
+    /// Get the annotated type of a variable in the current scope.
+    pub fn declared_type(&self, name: &str) -> Option<TypeAnnotation> {
+        self.scopes.last()?.declared_type(name)
+    }
+
+    /// Get a named argument from the current scope.
+    pub fn get_named_argument(&self, name: &str) -> Option<&RuntimeValue> {
+        let scope = self.scopes.last()?;
+        scope
+            .named_arguments
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, v)| v)
+    }
+
+    /// Get all named arguments (for #:) from the current scope.
+    pub fn get_named_arguments(&self) -> &[(String, RuntimeValue)] {
+        self.scopes
+            .last()
+            .map(|s| s.named_arguments.as_slice())
+            .unwrap_or(&[])
+    }
+
     /// Get all arguments (for #*) from the current scope.
     pub fn get_all_arguments(&self) -> &[RuntimeValue] {
         self.scopes
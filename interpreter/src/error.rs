@@ -1,19 +1,48 @@
 use std::fmt;
 use std::ops::Range;
 
+use mdl::Program;
+use mdl::codes::Code;
+use mdl::lint::{Level, LintLevels, denied_note};
+use mdl::parser::ParseError;
+
 #[derive(Debug)]
 pub enum RuntimeError {
     TypeError { expected: String, got: String },
+    /// A value that does not match a type annotation, e.g. `parameter 'a' of block 'Add'`.
+    AnnotationMismatch {
+        subject: String,
+        expected: String,
+        got: String,
+    },
     UndefinedVariable(String),
     UndefinedBlock(String),
     ArgumentOutOfBounds(usize),
+    UndefinedNamedArgument(String),
+    ArityMismatch {
+        block: String,
+        /// Number of parameters without a default.
+        required: usize,
+        /// Total number of declared parameters.
+        declared: usize,
+        got: usize,
+    },
+    /// A named argument that matches none of the block's declared parameters.
+    UnknownNamedArgument { block: String, name: String },
+    /// A parameter given both positionally and by name.
+    DuplicateArgument { block: String, name: String },
+    /// A required parameter that received no argument.
+    MissingArgument { block: String, name: String },
     NonExhaustiveMatch,
     DivisionByZero,
     NoEntryPoint,
     ImportNotImplemented(String),
+    /// A local import that could not be resolved, read or parsed.
+    ImportFailed { path: String, reason: String },
     IoError(String),
     StackOverflow,
-    Custom(String),
+    /// A diagnostic without a dedicated variant, e.g. a runtime warning.
+    Custom(Code, String),
 }
 
 impl fmt::Display for RuntimeError {
@@ -22,11 +51,54 @@ impl fmt::Display for RuntimeError {
             RuntimeError::TypeError { expected, got } => {
                 write!(f, "type error: expected {}, got {}", expected, got)
             }
+            RuntimeError::AnnotationMismatch {
+                subject,
+                expected,
+                got,
+            } => write!(
+                f,
+                "type mismatch: {} is declared as {}, got {}",
+                subject, expected, got
+            ),
             RuntimeError::UndefinedVariable(name) => write!(f, "undefined variable: {}", name),
             RuntimeError::UndefinedBlock(name) => write!(f, "undefined block: {}", name),
             RuntimeError::ArgumentOutOfBounds(idx) => {
                 write!(f, "argument index {} out of bounds", idx)
             }
+            RuntimeError::UndefinedNamedArgument(name) => {
+                write!(f, "no argument named '{}'", name)
+            }
+            RuntimeError::ArityMismatch {
+                block,
+                required,
+                declared,
+                got,
+            } if required == declared => write!(
+                f,
+                "arity mismatch: block '{}' expects {} argument(s), got {}",
+                block, declared, got
+            ),
+            RuntimeError::ArityMismatch {
+                block,
+                required,
+                declared,
+                got,
+            } => write!(
+                f,
+                "arity mismatch: block '{}' expects {} to {} arguments, got {}",
+                block, required, declared, got
+            ),
+            RuntimeError::UnknownNamedArgument { block, name } => {
+                write!(f, "block '{}' has no parameter named '{}'", block, name)
+            }
+            RuntimeError::DuplicateArgument { block, name } => write!(
+                f,
+                "parameter '{}' of block '{}' is given both positionally and by name",
+                name, block
+            ),
+            RuntimeError::MissingArgument { block, name } => {
+                write!(f, "block '{}' is missing an argument for parameter '{}'", block, name)
+            }
             RuntimeError::NonExhaustiveMatch => {
                 write!(f, "non-exhaustive match: no arm matched")
             }
@@ -35,9 +107,38 @@ impl fmt::Display for RuntimeError {
             RuntimeError::ImportNotImplemented(path) => {
                 write!(f, "imports not yet implemented: {}", path)
             }
+            RuntimeError::ImportFailed { path, reason } => {
+                write!(f, "cannot load import '{}': {}", path, reason)
+            }
             RuntimeError::IoError(msg) => write!(f, "I/O error: {}", msg),
             RuntimeError::StackOverflow => write!(f, "stack overflow"),
-            RuntimeError::Custom(msg) => write!(f, "{}", msg),
+            RuntimeError::Custom(_, msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl RuntimeError {
+    /// The stable diagnostic code of this error.
+    pub fn code(&self) -> Code {
+        match self {
+            RuntimeError::TypeError { .. } => Code::TypeError,
+            RuntimeError::AnnotationMismatch { .. } => Code::AnnotationMismatch,
+            RuntimeError::UndefinedVariable(_) => Code::UndefinedVariable,
+            RuntimeError::UndefinedBlock(_) => Code::UndefinedBlock,
+            RuntimeError::ArgumentOutOfBounds(_) => Code::ArgumentOutOfBounds,
+            RuntimeError::UndefinedNamedArgument(_) => Code::UndefinedNamedArgument,
+            RuntimeError::ArityMismatch { .. } => Code::ArityMismatch,
+            RuntimeError::UnknownNamedArgument { .. } => Code::UnknownNamedArgument,
+            RuntimeError::DuplicateArgument { .. } => Code::DuplicateArgument,
+            RuntimeError::MissingArgument { .. } => Code::MissingArgument,
+            RuntimeError::NonExhaustiveMatch => Code::NonExhaustiveMatch,
+            RuntimeError::DivisionByZero => Code::DivisionByZero,
+            RuntimeError::NoEntryPoint => Code::NoEntryPoint,
+            RuntimeError::ImportNotImplemented(_) => Code::RemoteImport,
+            RuntimeError::ImportFailed { .. } => Code::ImportFailed,
+            RuntimeError::IoError(_) => Code::IoError,
+            RuntimeError::StackOverflow => Code::StackOverflow,
+            RuntimeError::Custom(code, _) => *code,
         }
     }
 }
@@ -47,31 +148,91 @@ impl std::error::Error for RuntimeError {}
 /// A runtime error or warning enriched with source location information.
 #[derive(Debug)]
 pub struct DiagnosticError {
-    pub error: RuntimeError,
+    /// Boxed so that `Result<_, DiagnosticError>` stays small on the happy path.
+    pub error: Box<RuntimeError>,
     pub span: Option<Range<usize>>,
     pub source_id: usize,
     pub is_warning: bool,
+    /// Related source locations, e.g. the write a racing read conflicts with.
+    pub secondary: Vec<SecondaryLabel>,
+    /// Extra explanation shown below the source snippet.
+    pub notes: Vec<String>,
+    /// Block invocations active when the error was raised, outermost first.
+    /// Empty for warnings and for errors raised outside any block.
+    pub trace: Vec<Frame>,
+}
+
+/// One active block invocation in a runtime call stack.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// Name of the invoked block.
+    pub block: String,
+    /// Span of the invoking expression; None for the entry block and for
+    /// invocations from evaluated documents.
+    pub call_site: Option<Range<usize>>,
+    /// Local import path of the file containing the call site; None for the
+    /// main program.
+    pub file: Option<String>,
+}
+
+/// A related source location shown alongside a diagnostic's primary span.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SecondaryLabel {
+    pub span: Range<usize>,
+    pub message: String,
 }
 
 impl DiagnosticError {
     /// Create a warning diagnostic with a source span.
-    pub fn warning(message: String, span: Range<usize>, source_id: usize) -> Self {
+    pub fn warning(code: Code, message: String, span: Range<usize>, source_id: usize) -> Self {
         DiagnosticError {
-            error: RuntimeError::Custom(message),
+            error: Box::new(RuntimeError::Custom(code, message)),
             span: Some(span),
             source_id,
             is_warning: true,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            trace: Vec::new(),
         }
     }
+
+    /// The stable diagnostic code, e.g. `E0101`.
+    pub fn code(&self) -> Code {
+        self.error.code()
+    }
+
+    /// Point at a related source location in the same file.
+    pub fn with_secondary(mut self, span: Range<usize>, message: impl Into<String>) -> Self {
+        self.secondary.push(SecondaryLabel {
+            span,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Whether this diagnostic repeats a static analysis warning: same
+    /// code at the same location. Used to avoid reporting a problem twice
+    /// when the analyzer already caught it before execution.
+    pub fn repeats(&self, warning: &ParseError) -> bool {
+        self.source_id == warning.file_id && self.code() == warning.code && self.span.as_ref() == Some(&warning.span)
+    }
 }
 
 impl From<RuntimeError> for DiagnosticError {
     fn from(error: RuntimeError) -> Self {
         DiagnosticError {
-            error,
+            error: Box::new(error),
             span: None,
             source_id: 0,
             is_warning: false,
+            secondary: Vec::new(),
+            notes: Vec::new(),
+            trace: Vec::new(),
         }
     }
 }
@@ -83,3 +244,29 @@ impl fmt::Display for DiagnosticError {
 }
 
 impl std::error::Error for DiagnosticError {}
+
+/// Drop runtime warnings allowed on the command line or by an `mdl-allow`
+/// comment in `program`, and report denied ones as errors.
+pub fn apply_lint_levels(
+    levels: &LintLevels,
+    program: &Program,
+    diagnostics: Vec<DiagnosticError>,
+) -> Vec<DiagnosticError> {
+    diagnostics
+        .into_iter()
+        .filter_map(|mut diagnostic| {
+            if !diagnostic.is_warning {
+                return Some(diagnostic);
+            }
+            let code = diagnostic.code();
+            match levels.level_in(program, code, diagnostic.span.as_ref()) {
+                Level::Allow => None,
+                Level::Warn => Some(diagnostic),
+                Level::Deny => {
+                    diagnostic.is_warning = false;
+                    Some(diagnostic.with_note(denied_note(code)))
+                }
+            }
+        })
+        .collect()
+}
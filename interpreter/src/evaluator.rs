@@ -1,10 +1,12 @@
+use std::collections::HashMap;
 use std::io::Write;
-use std::ops::Range;
 
+use mdl::codes::Code;
 use mdl::instruction::template::template_string::{TemplateString, TemplateStringPart};
 use mdl::instruction::value::{BinaryOperator, UnaryOperator, Value};
+use mdl::suggest::did_you_mean;
 
-use crate::environment::{Environment, VariableLookup};
+use crate::environment::{Arguments, Environment, VariableLookup};
 use crate::error::{DiagnosticError, RuntimeError};
 use crate::executor::BlockRegistry;
 use crate::pattern;
@@ -13,6 +15,8 @@ use crate::runtime_value::RuntimeValue;
 pub const MAX_DEPTH: usize = 128;
 
 /// Evaluate a Value AST node to produce a RuntimeValue.
+/// Errors that do not carry a span yet get the span of the innermost node
+/// that produced them.
 pub fn evaluate(
     value: &Value,
     env: &mut Environment,
@@ -21,7 +25,24 @@ pub fn evaluate(
     depth: usize,
     diagnostics: &mut Vec<DiagnosticError>,
     source_id: usize,
-    instruction_span: &Range<usize>,
+) -> Result<RuntimeValue, DiagnosticError> {
+    evaluate_value(value, env, blocks, output, depth, diagnostics, source_id).map_err(|mut e| {
+        if e.span.is_none() {
+            e.span = Some(value.span());
+            e.source_id = source_id;
+        }
+        e
+    })
+}
+
+fn evaluate_value(
+    value: &Value,
+    env: &mut Environment,
+    blocks: &mut BlockRegistry,
+    output: &mut dyn Write,
+    depth: usize,
+    diagnostics: &mut Vec<DiagnosticError>,
+    source_id: usize,
 ) -> Result<RuntimeValue, DiagnosticError> {
     if depth > MAX_DEPTH {
         return Err(RuntimeError::StackOverflow.into());
@@ -29,19 +50,20 @@ pub fn evaluate(
 
     match value {
         // --- Literals ---
-        Value::NumberLiteral(n) => Ok(RuntimeValue::Number(*n)),
-        Value::StringLiteral(s) => Ok(RuntimeValue::String(s.clone())),
-        Value::BooleanLiteral(b) => Ok(RuntimeValue::Boolean(*b)),
-        Value::UnitLiteral => Ok(RuntimeValue::Unit),
+        Value::NumberLiteral(n, _) => Ok(RuntimeValue::Number(*n)),
+        Value::StringLiteral(s, _) => Ok(RuntimeValue::String(s.clone())),
+        Value::BooleanLiteral(b, _) => Ok(RuntimeValue::Boolean(*b)),
+        Value::UnitLiteral(_) => Ok(RuntimeValue::Unit),
 
         // --- References ---
-        Value::VariableReference(name, value_span) => {
-            let span = if value_span.is_empty() { instruction_span.clone() } else { value_span.clone() };
+        Value::VariableReference(name, span) => {
+            let span = span.clone();
             match env.get_variable_info(name) {
                 VariableLookup::Found { value, cross_scope, non_lexical_scope } => {
                     let value = value.clone();
                     if non_lexical_scope {
                         diagnostics.push(DiagnosticError::warning(
+                            Code::NonLexicalRead,
                             format!(
                                 "reading variable '{}' from a non-lexical scope (undefined behavior)",
                                 name
@@ -55,10 +77,13 @@ pub fn evaluate(
                     }
                     Ok(value)
                 }
+                // Pattern bindings are arm-local, so they never race with fence writes
+                VariableLookup::Binding(value) => Ok(value.clone()),
                 VariableLookup::HoistedUnassigned => {
                     // Track for same-fence UB detection even when unassigned
                     env.record_fence_read(name, span.clone());
                     diagnostics.push(DiagnosticError::warning(
+                        Code::ReadBeforeAssignment,
                         format!(
                             "reading variable '{}' before assignment (undefined behavior)",
                             name
@@ -72,13 +97,15 @@ pub fn evaluate(
                     let mut err = DiagnosticError::from(RuntimeError::UndefinedVariable(name.clone()));
                     err.span = Some(span);
                     err.source_id = source_id;
+                    if let Some(note) = did_you_mean(name, env.visible_variables()) {
+                        err = err.with_note(note);
+                    }
                     Err(err)
                 }
             }
         },
 
-        Value::PositionalArgumentReference(idx, value_span) => {
-            let span = if value_span.is_empty() { instruction_span.clone() } else { value_span.clone() };
+        Value::PositionalArgumentReference(idx, span) => {
             env.get_argument(*idx)
                 .cloned()
                 .ok_or_else(|| {
@@ -89,7 +116,31 @@ pub fn evaluate(
                 })
         },
 
-        Value::SpreadArgumentReference => {
+        Value::NamedArgumentReference(name, value_span) => {
+            env.get_named_argument(name)
+                .cloned()
+                .ok_or_else(|| {
+                    let mut err = DiagnosticError::from(RuntimeError::UndefinedNamedArgument(name.clone()));
+                    err.span = Some(value_span.clone());
+                    err.source_id = source_id;
+                    err
+                })
+        }
+
+        Value::NamedArgumentRecord(_) => {
+            let (headers, row) = env.get_named_arguments().iter().cloned().unzip();
+            Ok(RuntimeValue::Table {
+                headers,
+                rows: vec![row],
+            })
+        }
+
+        // Only meaningful inside an argument list; see `evaluate_arguments`
+        Value::NamedArgument(_, inner, _) => evaluate(
+            inner, env, blocks, output, depth, diagnostics, source_id,
+        ),
+
+        Value::SpreadArgumentReference(_) => {
             let args = env.get_all_arguments();
             Ok(RuntimeValue::String(format!(
                 "[{}]",
@@ -101,7 +152,7 @@ pub fn evaluate(
         }
 
         // --- Operations ---
-        Value::UnaryOperation { operator, operand } => {
+        Value::UnaryOperation { operator, operand, .. } => {
             let val = evaluate(
                 operand,
                 env,
@@ -110,7 +161,6 @@ pub fn evaluate(
                 depth + 1,
                 diagnostics,
                 source_id,
-                instruction_span,
             )?;
             // Demand-evaluate Strikethrough operands for operations that need concrete types
             let val = match operator {
@@ -132,6 +182,8 @@ pub fn evaluate(
             operator,
             left,
             right,
+            operator_span,
+            ..
         } => {
             let l = evaluate(
                 left,
@@ -141,7 +193,6 @@ pub fn evaluate(
                 depth + 1,
                 diagnostics,
                 source_id,
-                instruction_span,
             )?;
             let r = evaluate(
                 right,
@@ -151,7 +202,6 @@ pub fn evaluate(
                 depth + 1,
                 diagnostics,
                 source_id,
-                instruction_span,
             )?;
             // Demand-evaluate Strikethrough operands for arithmetic/comparison ops
             let needs_demand = !matches!(
@@ -171,7 +221,12 @@ pub fn evaluate(
             } else {
                 r
             };
-            Ok(eval_binary_op(operator, &l, &r)?)
+            eval_binary_op(operator, &l, &r).map_err(|e| {
+                let mut err = DiagnosticError::from(e);
+                err.span = Some(operator_span.clone());
+                err.source_id = source_id;
+                err
+            })
         }
 
         // --- Print ---
@@ -184,7 +239,6 @@ pub fn evaluate(
                 depth + 1,
                 diagnostics,
                 source_id,
-                instruction_span,
             )?;
             writeln!(output, "{}", s)
                 .map_err(|e| DiagnosticError::from(RuntimeError::IoError(e.to_string())))?;
@@ -201,7 +255,6 @@ pub fn evaluate(
                 depth + 1,
                 diagnostics,
                 source_id,
-                instruction_span,
             )?;
             Ok(RuntimeValue::String(s))
         }
@@ -212,6 +265,8 @@ pub fn evaluate(
             let has_invocations = template.parts.iter().any(|p| matches!(p,
                 TemplateStringPart::Expression(Value::BlockInvocation(..))
                 | TemplateStringPart::Expression(Value::EvaluatedBlockInvocation(..))
+                | TemplateStringPart::Expression(Value::DynamicBlockInvocation(..))
+                | TemplateStringPart::Expression(Value::EvaluatedDynamicBlockInvocation(..))
             ));
 
             if has_invocations {
@@ -222,7 +277,7 @@ pub fn evaluate(
             } else {
                 // No invocations: eagerly evaluate interpolations
                 let inner = evaluate_template_to_value(
-                    template, env, blocks, output, depth + 1, diagnostics, source_id, instruction_span,
+                    template, env, blocks, output, depth + 1, diagnostics, source_id,
                 )?;
                 Ok(RuntimeValue::Strikethrough(
                     crate::runtime_value::StrikethroughPayload::Eager(Box::new(inner)),
@@ -235,6 +290,7 @@ pub fn evaluate(
             condition,
             true_branch,
             false_branch,
+            ..
         } => {
             let cond_val = evaluate(
                 condition,
@@ -244,7 +300,6 @@ pub fn evaluate(
                 depth + 1,
                 diagnostics,
                 source_id,
-                instruction_span,
             )?;
             if cond_val.is_truthy() {
                 evaluate(
@@ -255,7 +310,6 @@ pub fn evaluate(
                     depth + 1,
                     diagnostics,
                     source_id,
-                    instruction_span,
                 )
             } else {
                 match false_branch {
@@ -267,7 +321,6 @@ pub fn evaluate(
                         depth + 1,
                         diagnostics,
                         source_id,
-                        instruction_span,
                     ),
                     None => {
                         // Two-operand conditional: falsy → Strikethrough of unevaluated expression
@@ -286,6 +339,7 @@ pub fn evaluate(
             value: scrutinee,
             arms,
             otherwise,
+            ..
         } => {
             let val = evaluate(
                 scrutinee,
@@ -295,63 +349,54 @@ pub fn evaluate(
                 depth + 1,
                 diagnostics,
                 source_id,
-                instruction_span,
             )?;
 
-            for (template, result) in arms {
-                if let Some(bindings) = pattern::match_pattern(template, &val) {
-                    for (name, bound_val) in bindings {
-                        env.set_variable(&name, bound_val);
-                    }
-                    return evaluate(
-                        result,
+            for arm in arms {
+                if let Some(bindings) = pattern::match_pattern(&arm.pattern, &val) {
+                    // Bindings live only for the duration of the arm result
+                    env.push_bindings(bindings);
+                    let result = evaluate(
+                        &arm.result,
                         env,
                         blocks,
                         output,
                         depth + 1,
                         diagnostics,
                         source_id,
-                        instruction_span,
                     );
+                    env.pop_bindings();
+                    return result;
                 }
             }
 
-            if let Some((binding, result)) = otherwise {
-                if let Some(name) = binding {
-                    env.set_variable(name, val);
+            if let Some(fallback) = otherwise {
+                let mut bindings = HashMap::new();
+                if let Some(name) = &fallback.binding {
+                    bindings.insert(name.clone(), val);
                 }
-                return evaluate(
-                    result,
+                env.push_bindings(bindings);
+                let result = evaluate(
+                    &fallback.result,
                     env,
                     blocks,
                     output,
                     depth + 1,
                     diagnostics,
                     source_id,
-                    instruction_span,
                 );
+                env.pop_bindings();
+                return result;
             }
 
             Err(RuntimeError::NonExhaustiveMatch.into())
         }
 
         // --- Block invocation ---
-        Value::BlockInvocation(args, block_ref) => {
-            let evaluated_args: Vec<RuntimeValue> = args
-                .iter()
-                .map(|a| {
-                    evaluate(
-                        a,
-                        env,
-                        blocks,
-                        output,
-                        depth + 1,
-                        diagnostics,
-                        source_id,
-                        instruction_span,
-                    )
-                })
-                .collect::<Result<_, _>>()?;
+        Value::BlockInvocation(args, block_ref, span)
+        | Value::EvaluatedBlockInvocation(args, block_ref, span) => {
+            let evaluated_args = evaluate_arguments(
+                args, env, blocks, output, depth, diagnostics, source_id,
+            )?;
 
             crate::executor::invoke_block(
                 block_ref,
@@ -359,40 +404,54 @@ pub fn evaluate(
                 env,
                 blocks,
                 output,
-                false,
+                matches!(value, Value::EvaluatedBlockInvocation(..)),
                 depth + 1,
                 diagnostics,
+                Some(span.clone()),
             )
         }
 
-        Value::EvaluatedBlockInvocation(args, block_ref) => {
-            let evaluated_args: Vec<RuntimeValue> = args
-                .iter()
-                .map(|a| {
-                    evaluate(
-                        a,
-                        env,
-                        blocks,
-                        output,
-                        depth + 1,
-                        diagnostics,
-                        source_id,
-                        instruction_span,
-                    )
-                })
-                .collect::<Result<_, _>>()?;
+        Value::DynamicBlockInvocation(args, target, span)
+        | Value::EvaluatedDynamicBlockInvocation(args, target, span) => {
+            let evaluated_args = evaluate_arguments(
+                args, env, blocks, output, depth, diagnostics, source_id,
+            )?;
+            let target = evaluate_template_to_value(
+                target, env, blocks, output, depth + 1, diagnostics, source_id,
+            )?;
+            // A block reference value is invoked directly; anything else is
+            // rendered and resolved like a link destination.
+            let block_ref = match target {
+                RuntimeValue::BlockRef(block_ref) => block_ref,
+                other => crate::evaluator_helpers::parse_runtime_block_ref(&other.to_string()),
+            };
 
             crate::executor::invoke_block(
-                block_ref,
+                &block_ref,
                 evaluated_args,
                 env,
                 blocks,
                 output,
-                true,
+                matches!(value, Value::EvaluatedDynamicBlockInvocation(..)),
                 depth + 1,
                 diagnostics,
+                Some(span.clone()),
             )
         }
+
+        Value::BlockRef(block_ref, _) => Ok(RuntimeValue::BlockRef(block_ref.clone())),
+
+        // Only reachable through an AST built by hand: the parser never
+        // returns a program containing error nodes.
+        Value::Error(span) => {
+            let mut err = DiagnosticError::from(RuntimeError::Custom(
+                Code::InvalidSyntax,
+                "cannot evaluate an expression that failed to parse".to_string(),
+            ));
+            err.span = Some(span.clone());
+            err.source_id = source_id;
+            Err(err)
+        }
     }
 }
 
@@ -400,10 +459,65 @@ pub fn evaluate(
 // Helpers
 // ---------------------------------------------------------------------------
 
+/// Evaluate invocation arguments left to right.
+/// A bare `#:` argument forwards the current block's named arguments.
+fn evaluate_arguments(
+    args: &[Value],
+    env: &mut Environment,
+    blocks: &mut BlockRegistry,
+    output: &mut dyn Write,
+    depth: usize,
+    diagnostics: &mut Vec<DiagnosticError>,
+    source_id: usize,
+) -> Result<Arguments, DiagnosticError> {
+    let mut arguments = Arguments::default();
+    for arg in args {
+        match arg {
+            Value::NamedArgument(name, inner, _) => {
+                let value = evaluate(
+                    inner,
+                    env,
+                    blocks,
+                    output,
+                    depth + 1,
+                    diagnostics,
+                    source_id,
+                )?;
+                match arguments.named.iter_mut().find(|(n, _)| n == name) {
+                    Some(forwarded) => forwarded.1 = value,
+                    None => arguments.named.push((name.clone(), value)),
+                }
+            }
+            Value::NamedArgumentRecord(_) => {
+                for (name, value) in env.get_named_arguments() {
+                    // Explicit named arguments take precedence over forwarded ones
+                    if arguments.get_named(name).is_none() {
+                        arguments.named.push((name.clone(), value.clone()));
+                    }
+                }
+            }
+            _ => {
+                let value = evaluate(
+                    arg,
+                    env,
+                    blocks,
+                    output,
+                    depth + 1,
+                    diagnostics,
+                    source_id,
+                )?;
+                arguments.positional.push(value);
+            }
+        }
+    }
+    Ok(arguments)
+}
+
 /// Demand-evaluate a Strikethrough value.
 /// - Eager: return the already-evaluated inner value.
 /// - Lazy: evaluate the stored AST expression now.
 /// - Template: evaluate the stored template now (including invocations).
+///
 /// If the value is not a Strikethrough, returns it unchanged.
 fn demand(
     val: RuntimeValue,
@@ -418,15 +532,11 @@ fn demand(
         RuntimeValue::Strikethrough(StrikethroughPayload::Eager(inner)) => Ok(*inner),
         RuntimeValue::Strikethrough(StrikethroughPayload::Lazy(ast)) => {
             let source_id = blocks.source_id;
-            let span = 0..0;
-            evaluate(&ast, env, blocks, output, depth, diagnostics, source_id, &span)
+            evaluate(&ast, env, blocks, output, depth, diagnostics, source_id)
         }
         RuntimeValue::Strikethrough(StrikethroughPayload::Template(ts)) => {
             let source_id = blocks.source_id;
-            let span = 0..0;
-            evaluate_template_to_value(
-                &ts, env, blocks, output, depth, diagnostics, source_id, &span,
-            )
+            evaluate_template_to_value(&ts, env, blocks, output, depth, diagnostics, source_id)
         }
         other => Ok(other),
     }
@@ -521,7 +631,6 @@ pub fn eval_template_string(
     depth: usize,
     diagnostics: &mut Vec<DiagnosticError>,
     source_id: usize,
-    instruction_span: &Range<usize>,
 ) -> Result<String, DiagnosticError> {
     let mut result = String::new();
     for part in &ts.parts {
@@ -536,7 +645,6 @@ pub fn eval_template_string(
                     depth,
                     diagnostics,
                     source_id,
-                    instruction_span,
                 )?;
                 result.push_str(&val.to_string());
             }
@@ -556,15 +664,14 @@ fn evaluate_template_to_value(
     depth: usize,
     diagnostics: &mut Vec<DiagnosticError>,
     source_id: usize,
-    instruction_span: &Range<usize>,
 ) -> Result<RuntimeValue, DiagnosticError> {
     // Single expression with no surrounding text: return as its native type
-    if ts.parts.len() == 1 {
-        if let TemplateStringPart::Expression(expr) = &ts.parts[0] {
-            return evaluate(
-                expr, env, blocks, output, depth, diagnostics, source_id, instruction_span,
-            );
-        }
+    if ts.parts.len() == 1
+        && let TemplateStringPart::Expression(expr) = &ts.parts[0]
+    {
+        return evaluate(
+            expr, env, blocks, output, depth, diagnostics, source_id,
+        );
     }
 
     // Mixed parts or pure literal: concatenate to string
@@ -574,7 +681,7 @@ fn evaluate_template_to_value(
             TemplateStringPart::Literal(s) => result.push_str(s),
             TemplateStringPart::Expression(expr) => {
                 let val = evaluate(
-                    expr, env, blocks, output, depth, diagnostics, source_id, instruction_span,
+                    expr, env, blocks, output, depth, diagnostics, source_id,
                 )?;
                 result.push_str(&val.to_string());
             }
@@ -586,30 +693,82 @@ fn evaluate_template_to_value(
 /// Render a Value AST node as a Markdown-like string for struck representation.
 pub fn value_to_markdown_text(value: &Value) -> String {
     match value {
-        Value::StringLiteral(s) => format!("\"{}\"", s),
-        Value::NumberLiteral(n) => {
+        Value::StringLiteral(s, _) => format!("\"{}\"", s),
+        Value::NumberLiteral(n, _) => {
             if n.is_finite() && *n == n.floor() && n.abs() < 1e15 {
                 format!("{}", *n as i64)
             } else {
                 format!("{}", n)
             }
         }
-        Value::BooleanLiteral(b) => format!("{}", b),
-        Value::UnitLiteral => "()".to_string(),
+        Value::BooleanLiteral(b, _) => format!("{}", b),
+        Value::UnitLiteral(_) => "()".to_string(),
         Value::VariableReference(name, _) => name.clone(),
         Value::PositionalArgumentReference(idx, _) => format!("#{}", idx),
-        Value::SpreadArgumentReference => "#*".to_string(),
-        Value::BlockInvocation(_, block_ref) => {
+        Value::SpreadArgumentReference(_) => "#*".to_string(),
+        Value::NamedArgumentReference(name, _) => format!("#{}", name),
+        Value::NamedArgumentRecord(_) => "#:".to_string(),
+        Value::NamedArgument(name, inner, _) => format!("{}: {}", name, value_to_markdown_text(inner)),
+        Value::BlockInvocation(_, block_ref, _) => {
             format!("[](#{})", block_ref.block_name())
         }
-        Value::EvaluatedBlockInvocation(_, block_ref) => {
+        Value::EvaluatedBlockInvocation(_, block_ref, _) => {
             format!("![](#{})", block_ref.block_name())
         }
+        Value::DynamicBlockInvocation(_, target, _) => {
+            format!("[]({})", template_to_text(target))
+        }
+        Value::EvaluatedDynamicBlockInvocation(_, target, _) => {
+            format!("![]({})", template_to_text(target))
+        }
+        Value::BlockRef(block_ref, _) => format!("&[](#{})", block_ref.block_name()),
         Value::Print(ts) => {
             let inner = template_to_text(ts);
             format!("**{}**", inner)
         }
-        _ => format!("{:?}", value),
+        Value::Interpolation(ts) => format!("\"{}\"", template_to_text(ts)),
+        Value::Strikethrough(ts) => format!("~~{}~~", template_to_text(ts)),
+        Value::UnaryOperation { operator, operand, .. } => format!("{}{}", operator, operand_to_markdown_text(operand)),
+        Value::BinaryOperation { operator, left, right, .. } => format!(
+            "{} {} {}",
+            operand_to_markdown_text(left),
+            operator,
+            operand_to_markdown_text(right)
+        ),
+        Value::Conditional { condition, true_branch, false_branch, .. } => {
+            let mut text = format!(
+                "{} ? {}",
+                operand_to_markdown_text(condition),
+                operand_to_markdown_text(true_branch)
+            );
+            if let Some(false_branch) = false_branch {
+                text.push_str(&format!(" : {}", operand_to_markdown_text(false_branch)));
+            }
+            text
+        }
+        Value::Match { value, arms, otherwise, .. } => {
+            let mut text = format!("match {}", value_to_markdown_text(value));
+            for arm in arms {
+                text.push_str(&format!("\n    - {}: {}", arm.pattern, value_to_markdown_text(&arm.result)));
+            }
+            if let Some(otherwise) = otherwise {
+                let binding = otherwise.binding.as_deref().map(|name| format!(" {}", name)).unwrap_or_default();
+                text.push_str(&format!("\n    - otherwise{}: {}", binding, value_to_markdown_text(&otherwise.result)));
+            }
+            text
+        }
+        Value::Error(_) => String::new(),
+    }
+}
+
+/// Like [`value_to_markdown_text`], parenthesizing operations so the text
+/// reads back with the same grouping.
+fn operand_to_markdown_text(value: &Value) -> String {
+    match value {
+        Value::BinaryOperation { .. } | Value::Conditional { .. } | Value::Match { .. } => {
+            format!("({})", value_to_markdown_text(value))
+        }
+        _ => value_to_markdown_text(value),
     }
 }
 
@@ -1,22 +1,29 @@
 use std::collections::HashMap;
 use std::io::Write;
+use std::ops::Range;
 use std::path::PathBuf;
 
-use mdl::block::Block;
-use mdl::block::reference::BlockReference;
+use mdl::block::{Block, Parameter};
+use mdl::block::reference::{BlockReference, resolve_import_path};
+use mdl::block::scope::{self, BlockPath};
 use mdl::chain::Chain;
+use mdl::codes::{Code, same_fence_read_write, same_fence_write_write};
 use mdl::instruction::Instruction;
+use mdl::instruction::types::TypeAnnotation;
+use mdl::suggest::did_you_mean;
+use mdl::visit::Visit;
 
-use crate::environment::{Environment, Scope};
+use crate::environment::{Arguments, Environment, FenceConflict, Scope};
 use crate::error::{DiagnosticError, RuntimeError};
 use crate::evaluator::evaluate;
 use crate::runtime_value::RuntimeValue;
 
-/// Registry of all blocks in the program, indexed by name.
+/// Registry of all blocks in the program, looked up by lexical scope.
 /// Supports loading blocks from imported files.
 pub struct BlockRegistry {
-    blocks: HashMap<String, Block>,
-    /// Maps each block name to its lexical parent's name (None for top-level blocks).
+    /// The program's blocks, as nested in the source.
+    blocks: Vec<Block>,
+    /// Maps each imported block name to its lexical parent's name (None for top-level blocks).
     parent_map: HashMap<String, Option<String>>,
     /// Base directory for resolving relative imports.
     base_dir: PathBuf,
@@ -32,69 +39,63 @@ impl BlockRegistry {
     }
 
     pub fn from_program_with_base(program: &mdl::Program, base_dir: PathBuf) -> Self {
-        let mut blocks = HashMap::new();
-        let mut parent_map = HashMap::new();
-        for block in &program.blocks {
-            register_block(&mut blocks, &mut parent_map, block, None);
-        }
         BlockRegistry {
-            blocks,
-            parent_map,
+            blocks: program.blocks.clone(),
+            parent_map: HashMap::new(),
             base_dir,
             import_cache: HashMap::new(),
             source_id: program.source_id,
         }
     }
 
-    pub fn get(&self, name: &str) -> Option<&Block> {
-        self.blocks.get(name)
+    /// Resolve a local reference made from the block at `from`, returning the block and its path.
+    pub fn get(&self, from: &[usize], name: &str) -> Option<(BlockPath, &Block)> {
+        let path = scope::resolve_local(&self.blocks, from, name)?;
+        let block = scope::block_at(&self.blocks, &path)?;
+        Some((path, block))
     }
 
     /// Case-insensitive block lookup. Tries exact match first, then case-insensitive.
-    pub fn get_entry(&self, name: &str) -> Option<&Block> {
-        self.blocks.get(name).or_else(|| {
-            let lower = name.to_lowercase();
-            self.blocks
-                .iter()
-                .find(|(k, _)| k.to_lowercase() == lower)
-                .map(|(_, v)| v)
-        })
+    pub fn get_entry(&self, name: &str) -> Option<(BlockPath, &Block)> {
+        let path = scope::resolve_entry(&self.blocks, name)?;
+        let block = scope::block_at(&self.blocks, &path)?;
+        Some((path, block))
     }
 
-    /// Return all top-level block names (for --list-blocks).
+    /// Return all block names, at every depth (for --list-blocks).
     pub fn block_names(&self) -> Vec<&str> {
-        self.blocks.keys().map(|s| s.as_str()).collect()
+        let mut names = Vec::new();
+        collect_names(&self.blocks, &mut names);
+        names
     }
 
     /// Load and cache blocks from a local import file.
     fn load_import(&mut self, path: &str) -> Result<(), RuntimeError> {
-        let resolved = self.base_dir.join(path);
-        // Try with .md extension if no extension present
-        let resolved = if resolved.extension().is_none() {
-            resolved.with_extension("md")
-        } else {
-            resolved
-        };
-
-        let canonical = resolved
+        let canonical = resolve_import_path(&self.base_dir, path)
             .canonicalize()
-            .map_err(|e| RuntimeError::IoError(format!("cannot resolve '{}': {}", path, e)))?;
+            .map_err(|e| RuntimeError::ImportFailed {
+                path: path.to_string(),
+                reason: e.to_string(),
+            })?;
 
         if self.import_cache.contains_key(&canonical) {
             return Ok(());
         }
 
         let source = std::fs::read_to_string(&canonical)
-            .map_err(|e| RuntimeError::IoError(format!("cannot read '{}': {}", path, e)))?;
+            .map_err(|e| RuntimeError::ImportFailed {
+                path: path.to_string(),
+                reason: e.to_string(),
+            })?;
 
         let parser = mdl::parser::Parser::new(source, 0);
-        let program = parser.parse().map_err(|errs| {
+        // Warnings in imported files are not reported
+        let (program, _) = parser.parse().map_err(|errs| {
             let messages: Vec<String> = errs.iter().map(|e| e.message.clone()).collect();
-            RuntimeError::Custom(format!(
-                "parse errors in '{}': {}",
-                path,
-                messages.join(", ")
-            ))
+            RuntimeError::ImportFailed {
+                path: path.to_string(),
+                reason: format!("parse errors: {}", messages.join(", ")),
+            }
         })?;
 
         let mut import_blocks = HashMap::new();
@@ -107,31 +108,43 @@ impl BlockRegistry {
     }
 
     /// Get a block from an imported file.
-    fn get_imported(&mut self, path: &str, block_name: &str) -> Result<Block, RuntimeError> {
+    fn get_imported(&mut self, path: &str, block_name: &str) -> Result<Block, DiagnosticError> {
         self.load_import(path)?;
 
-        let resolved = self.base_dir.join(path);
-        let resolved = if resolved.extension().is_none() {
-            resolved.with_extension("md")
-        } else {
-            resolved
-        };
-        let canonical = resolved
+        let canonical = resolve_import_path(&self.base_dir, path)
             .canonicalize()
-            .map_err(|e| RuntimeError::IoError(format!("cannot resolve '{}': {}", path, e)))?;
-
-        self.import_cache
-            .get(&canonical)
-            .and_then(|blocks| blocks.get(block_name))
-            .cloned()
-            .ok_or(RuntimeError::UndefinedBlock(format!(
-                "{}#{}",
-                path, block_name
-            )))
+            .map_err(|e| RuntimeError::ImportFailed {
+                path: path.to_string(),
+                reason: e.to_string(),
+            })?;
+
+        let blocks = &self.import_cache[&canonical];
+        match blocks.get(block_name) {
+            Some(block) => Ok(block.clone()),
+            None => {
+                let mut err = DiagnosticError::from(RuntimeError::UndefinedBlock(format!(
+                    "{}#{}",
+                    path, block_name
+                )));
+                if let Some(note) = did_you_mean(block_name, blocks.keys().map(String::as_str)) {
+                    err = err.with_note(note);
+                }
+                Err(err)
+            }
+        }
     }
 
-    /// Compute the list of lexical ancestor block names for a given block.
-    pub fn lexical_ancestors(&self, block_name: &str) -> Vec<String> {
+    /// A "did you mean" note for a block name missing from the program.
+    fn suggest_block(&self, name: &str) -> Option<String> {
+        did_you_mean(name, self.block_names())
+    }
+
+    /// Compute the list of lexical ancestor block names for the block at `path`,
+    /// or for the imported block `block_name` when `path` is empty.
+    pub fn lexical_ancestors(&self, path: &[usize], block_name: &str) -> Vec<String> {
+        if !path.is_empty() {
+            return scope::ancestor_names(&self.blocks, path);
+        }
         let mut ancestors = Vec::new();
         let mut current = block_name.to_string();
         while let Some(Some(parent)) = self.parent_map.get(&current) {
@@ -142,6 +155,13 @@ impl BlockRegistry {
     }
 }
 
+fn collect_names<'a>(blocks: &'a [Block], names: &mut Vec<&'a str>) {
+    for block in blocks {
+        names.push(&block.name);
+        collect_names(&block.children, names);
+    }
+}
+
 fn register_block(
     registry: &mut HashMap<String, Block>,
     parent_map: &mut HashMap<String, Option<String>>,
@@ -179,15 +199,17 @@ pub fn execute_program_with_base(
         .ok_or(DiagnosticError::from(RuntimeError::NoEntryPoint))?
         .clone();
 
+    env.push_frame(&entry.name, vec![0], None, None);
     let result = execute_block(
         &entry,
-        vec![],
+        Arguments::default(),
         &mut env,
         &mut registry,
         output,
         0,
         &mut diagnostics,
-    )?;
+    )
+    .map_err(|e| attach_trace(e, &env))?;
     Ok((result, diagnostics))
 }
 
@@ -208,44 +230,48 @@ pub fn execute_program_entry(
         return Err(DiagnosticError::from(RuntimeError::NoEntryPoint));
     }
 
-    let entry = registry
-        .get_entry(entry_name)
-        .ok_or_else(|| {
-            let available: Vec<&str> = registry.block_names();
-            DiagnosticError::from(RuntimeError::UndefinedBlock(format!(
-                "'{}' (available blocks: {})",
-                entry_name,
-                if available.is_empty() {
-                    "none".to_string()
-                } else {
-                    available.join(", ")
-                }
-            )))
-        })?
-        .clone();
+    let Some((path, entry)) = registry.get_entry(entry_name).map(|(path, block)| (path, block.clone())) else {
+        let mut err = DiagnosticError::from(RuntimeError::UndefinedBlock(entry_name.to_string()));
+        // Entry lookup ignores case, so any suggestion differs by more than that
+        let mut available = registry.block_names();
+        available.sort_unstable();
+        err = match did_you_mean(entry_name, available.iter().copied()) {
+            Some(note) => err.with_note(note),
+            None => err.with_note(format!("available blocks: {}", available.join(", "))),
+        };
+        return Err(err);
+    };
 
+    env.push_frame(&entry.name, path, None, None);
     let result = execute_block(
         &entry,
-        arguments,
+        arguments.into(),
         &mut env,
         &mut registry,
         output,
         0,
         &mut diagnostics,
-    )?;
+    )
+    .map_err(|e| attach_trace(e, &env))?;
     Ok((result, diagnostics))
 }
 
 /// Execute a block with given arguments.
 pub fn execute_block(
     block: &Block,
-    arguments: Vec<RuntimeValue>,
+    arguments: Arguments,
     env: &mut Environment,
     registry: &mut BlockRegistry,
     output: &mut dyn Write,
     depth: usize,
     diagnostics: &mut Vec<DiagnosticError>,
 ) -> Result<RuntimeValue, DiagnosticError> {
+    let declared = block.parameters.as_deref().unwrap_or_default();
+    let parameters: Vec<String> = declared.iter().map(|p| p.name.clone()).collect();
+    if block.parameters.is_some() {
+        check_arguments(block, declared, &arguments)?;
+    }
+
     // If block has no chain (no ordered list), return its body as a Document.
     if block.chain.is_empty() {
         let doc = block.body.clone();
@@ -255,11 +281,42 @@ pub fn execute_block(
 
     // Hoist variables: scan all instructions for assignment targets
     let hoisted = collect_hoisted_variables(&block.chain);
-    let ancestors = registry.lexical_ancestors(&block.name);
-    let scope = Scope::new(arguments, hoisted, block.name.clone(), ancestors);
+    let ancestors = registry.lexical_ancestors(env.current_path(), &block.name);
+    let mut scope = Scope::new(arguments, &parameters, hoisted, block.name.clone(), ancestors);
+    for (name, annotation) in collect_annotations(block) {
+        scope.declare_type(&name, annotation);
+    }
     env.push_scope(scope);
 
     let source_id = registry.source_id;
+
+    // Fill the remaining parameters from named arguments or their defaults,
+    // in declaration order
+    let supplied = env.get_all_arguments().len();
+    for param in declared.iter().skip(supplied) {
+        if let Some(value) = env.get_named_argument(&param.name).cloned() {
+            env.current_scope_mut().push_argument(&param.name, value);
+        } else if let Some(default) = &param.default {
+            let value = evaluate(
+                default,
+                env,
+                registry,
+                output,
+                depth,
+                diagnostics,
+                source_id,
+            )?;
+            if let Some(annotation) = param.annotation
+                && !value.has_type(annotation)
+            {
+                let mut err = DiagnosticError::from(parameter_mismatch(block, param, &value));
+                err.span = Some(param.span.clone());
+                err.source_id = source_id;
+                return Err(err);
+            }
+            env.current_scope_mut().push_argument(&param.name, value);
+        }
+    }
     let mut last_value = RuntimeValue::Unit;
 
     // Execute fence groups in order
@@ -280,19 +337,30 @@ pub fn execute_block(
             )?;
         }
 
-        // Check for same-fence UB: variable read and written by different instructions
-        let violations = env.pop_fence_context();
-        for (var_name, read_spans) in violations {
-            for span in read_spans {
-                diagnostics.push(DiagnosticError::warning(
-                    format!(
-                        "reading variable '{}' at the same fence as its assignment (undefined behavior)",
-                        var_name
-                    ),
-                    span,
-                    source_id,
-                ));
-            }
+        // Check for same-fence UB: a variable read and written, or written
+        // twice, by different instructions of the group
+        for conflict in env.pop_fence_context() {
+            let warning = match conflict {
+                FenceConflict::ReadWrite { variable, read, writes } => {
+                    let (message, note) = same_fence_read_write(&variable, group.index);
+                    let mut warning = DiagnosticError::warning(Code::SameFenceReadWrite, message, read, source_id);
+                    for write in writes {
+                        warning = warning.with_secondary(write, "assigned here");
+                    }
+                    warning.with_note(note)
+                }
+                FenceConflict::WriteWrite { variable, writes } => {
+                    let (message, note) = same_fence_write_write(&variable, group.index);
+                    let mut warning =
+                        DiagnosticError::warning(Code::SameFenceWriteWrite, message, writes[1].clone(), source_id)
+                            .with_secondary(writes[0].clone(), "first assigned here");
+                    for write in &writes[2..] {
+                        warning = warning.with_secondary(write.clone(), "also assigned here");
+                    }
+                    warning.with_note(note)
+                }
+            };
+            diagnostics.push(warning);
         }
     }
 
@@ -300,6 +368,76 @@ pub fn execute_block(
     Ok(last_value)
 }
 
+/// Check invocation arguments against a block's declared parameters.
+/// Positional arguments fill parameters in order; named arguments fill the
+/// parameter of the same name; parameters left over must have a default.
+fn check_arguments(
+    block: &Block,
+    declared: &[Parameter],
+    arguments: &Arguments,
+) -> Result<(), DiagnosticError> {
+    // No span here: the caller's instruction span is attached on the way up
+    let given = arguments.positional.len() + arguments.named.len();
+    let required = declared.iter().filter(|p| p.default.is_none()).count();
+    if arguments.positional.len() > declared.len() || given < required {
+        return Err(RuntimeError::ArityMismatch {
+            block: block.name.clone(),
+            required,
+            declared: declared.len(),
+            got: given,
+        }
+        .into());
+    }
+
+    for (name, _) in &arguments.named {
+        match declared.iter().position(|p| &p.name == name) {
+            None => {
+                return Err(RuntimeError::UnknownNamedArgument {
+                    block: block.name.clone(),
+                    name: name.clone(),
+                }
+                .into());
+            }
+            Some(idx) if idx < arguments.positional.len() => {
+                return Err(RuntimeError::DuplicateArgument {
+                    block: block.name.clone(),
+                    name: name.clone(),
+                }
+                .into());
+            }
+            Some(_) => {}
+        }
+    }
+
+    let supplied = declared.iter().zip(&arguments.positional).chain(
+        arguments
+            .named
+            .iter()
+            .filter_map(|(name, value)| Some((declared.iter().find(|p| &p.name == name)?, value))),
+    );
+    for (param, value) in supplied {
+        if let Some(annotation) = param.annotation
+            && !value.has_type(annotation)
+        {
+            return Err(parameter_mismatch(block, param, value).into());
+        }
+    }
+
+    let missing = declared
+        .iter()
+        .skip(arguments.positional.len())
+        .find(|p| p.default.is_none() && arguments.get_named(&p.name).is_none());
+    if let Some(param) = missing {
+        return Err(RuntimeError::MissingArgument {
+            block: block.name.clone(),
+            name: param.name.clone(),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
 fn execute_instruction(
     instruction: &Instruction,
     env: &mut Environment,
@@ -323,11 +461,22 @@ fn execute_instruction(
                 depth,
                 diagnostics,
                 source_id,
-                &span,
             )?;
-            env.set_variable(variable, val.clone());
-            env.record_fence_write(variable);
-            Ok(val)
+            match env.declared_type(variable) {
+                Some(annotation) if !val.has_type(annotation) => {
+                    Err(RuntimeError::AnnotationMismatch {
+                        subject: format!("variable '{}'", variable),
+                        expected: annotation.name().to_string(),
+                        got: val.type_name().to_string(),
+                    }
+                    .into())
+                }
+                _ => {
+                    env.set_variable(variable, val.clone());
+                    env.record_fence_write(variable, span.clone());
+                    Ok(val)
+                }
+            }
         }
         Instruction::Expression { value, .. } => evaluate(
             value,
@@ -337,7 +486,6 @@ fn execute_instruction(
             depth,
             diagnostics,
             source_id,
-            &span,
         ),
     };
 
@@ -351,16 +499,19 @@ fn execute_instruction(
     })
 }
 
-/// Invoke a block by reference.
+/// Invoke a block by reference. `call_site` is the span of the invoking
+/// expression, recorded in the call stack for error traces.
+#[allow(clippy::too_many_arguments)]
 pub fn invoke_block(
     block_ref: &BlockReference,
-    arguments: Vec<RuntimeValue>,
+    arguments: Arguments,
     env: &mut Environment,
     registry: &mut BlockRegistry,
     output: &mut dyn Write,
     evaluate_result: bool,
     depth: usize,
     diagnostics: &mut Vec<DiagnosticError>,
+    call_site: Option<Range<usize>>,
 ) -> Result<RuntimeValue, DiagnosticError> {
     let block_name = block_ref.block_name();
 
@@ -368,51 +519,51 @@ pub fn invoke_block(
         return Err(RuntimeError::StackOverflow.into());
     }
 
-    match block_ref {
+    let (block, path, file) = match block_ref {
         BlockReference::Local(_) => {
-            let block = registry
-                .get(block_name)
-                .ok_or(RuntimeError::UndefinedBlock(block_name.to_string()))?
-                .clone();
-
-            let result =
-                execute_block(&block, arguments, env, registry, output, depth + 1, diagnostics)?;
-
-            if evaluate_result {
-                // ![args](#block): evaluate the Document result
-                match result {
-                    RuntimeValue::Document(doc) => {
-                        evaluate_document(&doc, env, registry, output, depth + 1, diagnostics)
-                    }
-                    other => Ok(other),
+            let Some((path, block)) = registry.get(env.current_path(), block_name) else {
+                let mut err = DiagnosticError::from(RuntimeError::UndefinedBlock(block_name.to_string()));
+                if let Some(note) = registry.suggest_block(block_name) {
+                    err = err.with_note(note);
                 }
-            } else {
-                Ok(result)
-            }
+                return Err(err);
+            };
+            (block.clone(), path, None)
         }
         BlockReference::LocalImport { path, .. } => {
-            let block = registry.get_imported(path, block_name)?;
-            let result =
-                execute_block(&block, arguments, env, registry, output, depth + 1, diagnostics)?;
-
-            if evaluate_result {
-                match result {
-                    RuntimeValue::Document(doc) => {
-                        evaluate_document(&doc, env, registry, output, depth + 1, diagnostics)
-                    }
-                    other => Ok(other),
-                }
-            } else {
-                Ok(result)
-            }
+            (registry.get_imported(path, block_name)?, Vec::new(), Some(path.clone()))
         }
         BlockReference::RemoteImport { url, .. } => {
-            Err(RuntimeError::ImportNotImplemented(url.clone()).into())
+            return Err(RuntimeError::ImportNotImplemented(url.clone()).into());
         }
+    };
+
+    // Expressions in an evaluated Document are reported at the invocation
+    let document_span = call_site.clone().unwrap_or_else(|| block.span.clone());
+    env.push_frame(&block.name, path, call_site, file);
+    let result = execute_block(&block, arguments, env, registry, output, depth + 1, diagnostics)
+        .and_then(|result| match result {
+            // ![args](#block): evaluate the Document result
+            RuntimeValue::Document(doc) if evaluate_result => {
+                evaluate_document(&doc, env, registry, output, depth + 1, diagnostics, &document_span)
+            }
+            other => Ok(other),
+        })
+        .map_err(|e| attach_trace(e, env));
+    env.pop_frame();
+    result
+}
+
+/// Record the active call stack on an error, unless a deeper frame already did.
+fn attach_trace(mut error: DiagnosticError, env: &Environment) -> DiagnosticError {
+    if error.trace.is_empty() {
+        error.trace = env.call_stack().to_vec();
     }
+    error
 }
 
 /// Evaluate a Document by interpreting its Markdown content as expressions.
+/// Document text has no place in the source, so errors in it point at `span`.
 pub(crate) fn evaluate_document(
     doc: &mdl::document::Document,
     env: &mut Environment,
@@ -420,6 +571,7 @@ pub(crate) fn evaluate_document(
     output: &mut dyn Write,
     depth: usize,
     diagnostics: &mut Vec<DiagnosticError>,
+    span: &Range<usize>,
 ) -> Result<RuntimeValue, DiagnosticError> {
     use mdl::document::DocumentNode;
 
@@ -429,7 +581,7 @@ pub(crate) fn evaluate_document(
         match node {
             DocumentNode::Paragraph(inlines) => {
                 for inline in inlines {
-                    last = evaluate_inline(inline, env, registry, output, depth, diagnostics)?;
+                    last = evaluate_inline(inline, env, registry, output, depth, diagnostics, span)?;
                 }
             }
             _ => {
@@ -444,7 +596,7 @@ pub(crate) fn evaluate_document(
     Ok(last)
 }
 
-/// Evaluate an inline node from a Document.
+/// Evaluate an inline node from a Document, reporting errors at `span`.
 fn evaluate_inline(
     inline: &mdl::document::InlineNode,
     env: &mut Environment,
@@ -452,6 +604,7 @@ fn evaluate_inline(
     output: &mut dyn Write,
     depth: usize,
     diagnostics: &mut Vec<DiagnosticError>,
+    span: &Range<usize>,
 ) -> Result<RuntimeValue, DiagnosticError> {
     use mdl::document::InlineNode;
 
@@ -465,11 +618,10 @@ fn evaluate_inline(
                     InlineNode::Text(s) if s.contains('{') => {
                         // Parse as template and evaluate expressions
                         let source_id = registry.source_id;
-                        let span = 0..0;
-                        match mdl::parser::expression::parse_text_template(s, source_id) {
+                        match mdl::parser::expression::parse_detached_template(s, span.clone(), source_id) {
                             Ok(ts) => {
                                 let val = crate::evaluator::eval_template_string(
-                                    &ts, env, registry, output, depth, diagnostics, source_id, &span,
+                                    &ts, env, registry, output, depth, diagnostics, source_id,
                                 )?;
                                 text.push_str(&val);
                             }
@@ -477,7 +629,7 @@ fn evaluate_inline(
                         }
                     }
                     _ => {
-                        let val = evaluate_inline(child, env, registry, output, depth, diagnostics)?;
+                        let val = evaluate_inline(child, env, registry, output, depth, diagnostics, span)?;
                         text.push_str(&val.to_string());
                     }
                 }
@@ -491,7 +643,7 @@ fn evaluate_inline(
             let inner_doc = mdl::document::Document {
                 nodes: vec![mdl::document::DocumentNode::Paragraph(children.clone())],
             };
-            let inner = evaluate_document(&inner_doc, env, registry, output, depth, diagnostics)?;
+            let inner = evaluate_document(&inner_doc, env, registry, output, depth, diagnostics, span)?;
             Ok(RuntimeValue::Strikethrough(
                 crate::runtime_value::StrikethroughPayload::Eager(Box::new(inner)),
             ))
@@ -499,7 +651,7 @@ fn evaluate_inline(
         InlineNode::Link { dest, .. } => {
             // Link = block invocation
             let block_ref = crate::evaluator_helpers::parse_runtime_block_ref(dest);
-            let args = Vec::new(); // TODO: parse link text as arguments
+            let args = Arguments::default(); // TODO: parse link text as arguments
             invoke_block(
                 &block_ref,
                 args,
@@ -509,12 +661,13 @@ fn evaluate_inline(
                 false,
                 depth,
                 diagnostics,
+                None,
             )
         }
         InlineNode::Image { dest, .. } => {
             // Image = evaluated block invocation
             let block_ref = crate::evaluator_helpers::parse_runtime_block_ref(dest);
-            let args = Vec::new();
+            let args = Arguments::default();
             invoke_block(
                 &block_ref,
                 args,
@@ -524,25 +677,63 @@ fn evaluate_inline(
                 true,
                 depth,
                 diagnostics,
+                None,
             )
         }
         _ => Ok(RuntimeValue::Unit),
     }
 }
 
+fn parameter_mismatch(block: &Block, param: &Parameter, value: &RuntimeValue) -> RuntimeError {
+    RuntimeError::AnnotationMismatch {
+        subject: format!("parameter '{}' of block '{}'", param.name, block.name),
+        expected: param.annotation.map(|a| a.name()).unwrap_or_default().to_string(),
+        got: value.type_name().to_string(),
+    }
+}
+
+/// Collect the annotated types of a block's parameters and assigned variables.
+/// The first annotation of a name wins; later conflicting ones are reported statically.
+fn collect_annotations(block: &Block) -> Vec<(String, TypeAnnotation)> {
+    let mut annotations: Vec<(String, TypeAnnotation)> = block
+        .parameters
+        .iter()
+        .flatten()
+        .filter_map(|p| Some((p.name.clone(), p.annotation?)))
+        .collect();
+    for group in &block.chain.groups {
+        for instruction in &group.instructions {
+            if let Instruction::Assignment {
+                variable,
+                annotation: Some(annotation),
+                ..
+            } = instruction
+                && !annotations.iter().any(|(name, _)| name == variable)
+            {
+                annotations.push((variable.clone(), *annotation));
+            }
+        }
+    }
+    annotations
+}
+
 /// Collect all variable names assigned within a chain (for hoisting).
 fn collect_hoisted_variables(chain: &Chain) -> Vec<String> {
-    let mut vars = Vec::new();
-    for group in &chain.groups {
-        for instruction in &group.instructions {
-            if let Instruction::Assignment { variable, .. } = instruction {
-                if !vars.contains(variable) {
-                    vars.push(variable.clone());
-                }
+    struct Assigned(Vec<String>);
+
+    impl<'ast> Visit<'ast> for Assigned {
+        fn visit_instruction(&mut self, instruction: &'ast Instruction) {
+            if let Instruction::Assignment { variable, .. } = instruction
+                && !self.0.contains(variable)
+            {
+                self.0.push(variable.clone());
             }
         }
     }
-    vars
+
+    let mut assigned = Assigned(Vec::new());
+    assigned.visit_chain(chain);
+    assigned.0
 }
 
 /// Auto-unwrap a Document with a single element.
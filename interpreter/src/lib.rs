@@ -6,6 +6,6 @@ pub mod executor;
 pub mod pattern;
 pub mod runtime_value;
 
-pub use error::{DiagnosticError, RuntimeError};
+pub use error::{DiagnosticError, Frame, RuntimeError, SecondaryLabel, apply_lint_levels};
 pub use executor::{execute_program, execute_program_entry, execute_program_with_base};
 pub use runtime_value::RuntimeValue;
@@ -114,10 +114,10 @@ fn match_document_pattern(
             for node in &doc.nodes {
                 if let DocumentNode::Paragraph(inlines) = node {
                     // Single inline in paragraph: match directly
-                    if inlines.len() == 1 {
-                        if match_inline_pattern(inline_pat, &inlines[0], bindings) {
-                            return true;
-                        }
+                    if inlines.len() == 1
+                        && match_inline_pattern(inline_pat, &inlines[0], bindings)
+                    {
+                        return true;
                     }
                 }
             }
@@ -164,12 +164,12 @@ fn match_inline_children(
     bindings: &mut HashMap<String, RuntimeValue>,
 ) -> bool {
     // Special case: single Capture pattern matches all children as concatenated text
-    if patterns.len() == 1 {
-        if let InlinePattern::Capture(name) = &patterns[0] {
-            let text: String = children.iter().map(|c| inline_node_to_string(c)).collect();
-            bindings.insert(name.clone(), RuntimeValue::String(text));
-            return true;
-        }
+    if patterns.len() == 1
+        && let InlinePattern::Capture(name) = &patterns[0]
+    {
+        let text: String = children.iter().map(inline_node_to_string).collect();
+        bindings.insert(name.clone(), RuntimeValue::String(text));
+        return true;
     }
 
     // Otherwise, match positionally
@@ -197,7 +197,7 @@ fn inline_node_to_string(node: &InlineNode) -> String {
     match node {
         InlineNode::Text(s) => s.clone(),
         InlineNode::Strong(children) | InlineNode::Emphasis(children) | InlineNode::Strikethrough(children) => {
-            children.iter().map(|c| inline_node_to_string(c)).collect()
+            children.iter().map(inline_node_to_string).collect()
         }
         InlineNode::CodeSpan(s) => s.clone(),
         InlineNode::SoftBreak => " ".to_string(),
@@ -1,7 +1,9 @@
 use std::fmt;
 
+use mdl::block::reference::BlockReference;
 use mdl::document::Document;
 use mdl::instruction::template::template_string::TemplateString;
+use mdl::instruction::types::TypeAnnotation;
 use mdl::instruction::value::Value;
 
 /// The inner payload of a Strikethrough (null/falsy) value.
@@ -33,6 +35,8 @@ pub enum RuntimeValue {
         headers: Vec<String>,
         rows: Vec<Vec<RuntimeValue>>,
     },
+    /// A reference to a block, produced by `&[](#block)`. Invoked via `[args]({ref})`.
+    BlockRef(BlockReference),
 }
 
 impl RuntimeValue {
@@ -56,8 +60,14 @@ impl RuntimeValue {
             RuntimeValue::Document(_) => "Document",
             RuntimeValue::Strikethrough(_) => "Strikethrough",
             RuntimeValue::Table { .. } => "Table",
+            RuntimeValue::BlockRef(_) => "Block",
         }
     }
+
+    /// Whether this value satisfies a type annotation.
+    pub fn has_type(&self, annotation: TypeAnnotation) -> bool {
+        self.type_name() == annotation.name()
+    }
 }
 
 impl fmt::Display for RuntimeValue {
@@ -106,6 +116,11 @@ impl fmt::Display for RuntimeValue {
                 }
                 Ok(())
             }
+            RuntimeValue::BlockRef(block_ref) => match block_ref {
+                BlockReference::Local(name) => write!(f, "#{}", name),
+                BlockReference::LocalImport { path, block } => write!(f, "{}#{}", path, block),
+                BlockReference::RemoteImport { url, block } => write!(f, "{}#{}", url, block),
+            },
         }
     }
 }
@@ -132,6 +147,7 @@ impl PartialEq for RuntimeValue {
                     rows: r2,
                 },
             ) => h1 == h2 && r1 == r2,
+            (RuntimeValue::BlockRef(a), RuntimeValue::BlockRef(b)) => a == b,
             _ => false,
         }
     }
@@ -2,7 +2,7 @@ use std::io::Write;
 
 fn run(source: &str) -> String {
     let parser = mdl::parser::Parser::new(source.to_string(), 0);
-    let program = parser.parse().expect("parse failed");
+    let (program, _) = parser.parse().expect("parse failed");
     let mut output = Vec::new();
     interpreter::execute_program(&program, &mut output)
         .map(|(_, _)| ())
@@ -194,7 +194,7 @@ fn local_import_basic() {
     // Write the main file
     let main_source = "# Main\n1. [5](math#Double)\n";
     let parser = mdl::parser::Parser::new(main_source.to_string(), 0);
-    let program = parser.parse().expect("parse failed");
+    let (program, _) = parser.parse().expect("parse failed");
     let mut output = Vec::new();
     interpreter::execute_program_with_base(&program, &mut output, dir.path().to_path_buf())
         .map(|(_, _)| ())
@@ -215,7 +215,7 @@ fn local_import_with_extension() {
     // Main file imports with explicit .md extension
     let main_source = "# Main\n1. **{[](utils.md#Greet)}**\n";
     let parser = mdl::parser::Parser::new(main_source.to_string(), 0);
-    let program = parser.parse().expect("parse failed");
+    let (program, _) = parser.parse().expect("parse failed");
     let mut output = Vec::new();
     interpreter::execute_program_with_base(&program, &mut output, dir.path().to_path_buf())
         .map(|(_, _)| ())
@@ -236,7 +236,7 @@ fn local_import_caching() {
     // Main file calls two different blocks from the same import
     let main_source = "# Main\n1. [3, 4](lib#Add)\n2. [5, 6](lib#Mul)\n";
     let parser = mdl::parser::Parser::new(main_source.to_string(), 0);
-    let program = parser.parse().expect("parse failed");
+    let (program, _) = parser.parse().expect("parse failed");
     let mut output = Vec::new();
     interpreter::execute_program_with_base(&program, &mut output, dir.path().to_path_buf())
         .map(|(_, _)| ())
@@ -392,3 +392,235 @@ fn match_alternation_booleans() {
 2. **{x}**"#;
     assert_eq!(run_trimmed(src), "bool");
 }
+
+#[test]
+fn block_reference_callback() {
+    let src = r#"# Main
+1. f = &[](#Square)
+2. **{[f, 4](#Apply)}**
+
+## Apply
+1. [#1]({#0})
+
+## Square
+1. #0 * #0"#;
+    assert_eq!(run_trimmed(src), "16");
+}
+
+#[test]
+fn same_fence_write_write_warns_at_runtime() {
+    let src = "# Main\n1. x = 1\n1. x = 2\n2. **{x}**";
+    let (program, _) = mdl::parser::Parser::new(src.to_string(), 0).parse().expect("parse failed");
+    let mut output = Vec::new();
+    let (_, warnings) = interpreter::execute_program(&program, &mut output).expect("execution failed");
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].to_string().contains("variable 'x' is assigned more than once"));
+    assert_eq!(warnings[0].span, Some(19..24));
+}
+
+#[test]
+fn same_fence_read_warning_labels_the_write() {
+    let src = "# Main\n1. x = 1\n1. **{x}**";
+    let (program, _) = mdl::parser::Parser::new(src.to_string(), 0).parse().expect("parse failed");
+    let mut output = Vec::new();
+    let (_, warnings) = interpreter::execute_program(&program, &mut output).expect("execution failed");
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].to_string().contains("reading variable 'x' at the same fence"));
+    assert_eq!(
+        warnings[0].secondary,
+        vec![interpreter::SecondaryLabel {
+            span: 10..15,
+            message: "assigned here".to_string(),
+        }]
+    );
+    assert_eq!(warnings[0].notes.len(), 1);
+}
+
+#[test]
+fn same_fence_warnings_are_in_source_order() {
+    let src = "# Main\n1. d = 1\n1. c = d\n1. b = c\n1. a = b\n1. a = 2\n1. e = a\n2. **{e}**";
+    let (program, _) = mdl::parser::Parser::new(src.to_string(), 0).parse().expect("parse failed");
+    let mut output = Vec::new();
+    let (_, warnings) = interpreter::execute_program(&program, &mut output).expect("execution failed");
+    let starts: Vec<usize> = warnings.iter().map(|w| w.span.clone().unwrap().start).collect();
+    assert_eq!(warnings.len(), 5);
+    assert!(starts.is_sorted(), "{:?}", starts);
+}
+
+#[test]
+fn runtime_error_carries_call_stack() {
+    let src = "# Main\n1. [10](#Outer)\n\n# Outer\n1. [#0](#Inner)\n\n# Inner\n1. #0 / 0";
+    let (program, _) = mdl::parser::Parser::new(src.to_string(), 0).parse().expect("parse failed");
+    let mut output = Vec::new();
+    let error = interpreter::execute_program(&program, &mut output).expect_err("expected an error");
+    let frames: Vec<(&str, Option<std::ops::Range<usize>>)> = error
+        .trace
+        .iter()
+        .map(|frame| (frame.block.as_str(), frame.call_site.clone()))
+        .collect();
+    assert_eq!(
+        frames,
+        vec![("Main", None), ("Outer", Some(10..22)), ("Inner", Some(35..47))]
+    );
+    assert!(error.trace.iter().all(|frame| frame.file.is_none()));
+}
+
+#[test]
+fn runtime_error_points_at_operator() {
+    let src = "# Main\n1. x = 4\n2. **{x + 10 / (x - 4)}**";
+    let (program, _) = mdl::parser::Parser::new(src.to_string(), 0).parse().expect("parse failed");
+    let mut output = Vec::new();
+    let error = interpreter::execute_program(&program, &mut output).expect_err("expected an error");
+    let slash = src.find('/').unwrap();
+    assert_eq!(error.span, Some(slash..slash + 1));
+}
+
+#[test]
+fn expression_nodes_have_exact_spans() {
+    use mdl::instruction::Instruction;
+    use mdl::instruction::value::Value;
+
+    let src = "# Main\n1. y = -#0 * [#1, n: 2](#F)\n\n# F\n1. #0";
+    let (program, _) = mdl::parser::Parser::new(src.to_string(), 0).parse().expect("parse failed");
+    let Instruction::Assignment { value, .. } = &program.blocks[0].chain.groups[0].instructions[0] else {
+        panic!("expected an assignment");
+    };
+    let text = |span: std::ops::Range<usize>| &src[span];
+    let Value::BinaryOperation { left, right, operator_span, span, .. } = value else {
+        panic!("expected a multiplication, got {:?}", value);
+    };
+    assert_eq!(text(span.clone()), "-#0 * [#1, n: 2](#F)");
+    assert_eq!(text(operator_span.clone()), "*");
+    assert_eq!(text(left.span()), "-#0");
+    let Value::UnaryOperation { operand, .. } = &**left else {
+        panic!("expected a negation, got {:?}", left);
+    };
+    assert_eq!(text(operand.span()), "#0");
+    let Value::BlockInvocation(args, _, _) = &**right else {
+        panic!("expected an invocation, got {:?}", right);
+    };
+    assert_eq!(text(right.span()), "[#1, n: 2](#F)");
+    let spans: Vec<&str> = args.iter().map(|arg| text(arg.span())).collect();
+    assert_eq!(spans, ["#1", "n: 2"]);
+}
+
+#[test]
+fn call_stack_records_imported_files() {
+    let dir = tempfile::tempdir().expect("failed to create temp dir");
+    let lib_path = dir.path().join("math.md");
+    let mut lib_file = std::fs::File::create(&lib_path).unwrap();
+    write!(lib_file, "# Half\n1. [#0, 0](math#Divide)\n\n# Divide\n1. #0 / #1\n").unwrap();
+
+    let main_source = "# Main\n1. [1](math#Half)\n";
+    let (program, _) = mdl::parser::Parser::new(main_source.to_string(), 0).parse().expect("parse failed");
+    let mut output = Vec::new();
+    let error = interpreter::execute_program_with_base(&program, &mut output, dir.path().to_path_buf())
+        .expect_err("expected an error");
+    assert_eq!(
+        error.trace,
+        vec![
+            interpreter::Frame {
+                block: "Main".to_string(),
+                call_site: None,
+                file: None,
+            },
+            interpreter::Frame {
+                block: "Half".to_string(),
+                call_site: Some(10..24),
+                file: None,
+            },
+            interpreter::Frame {
+                block: "Divide".to_string(),
+                call_site: Some(10..30),
+                file: Some("math".to_string()),
+            },
+        ]
+    );
+}
+
+#[test]
+fn lint_levels_apply_to_runtime_warnings() {
+    use mdl::codes::Code;
+    use mdl::lint::{Level, LintLevels};
+
+    let source = "# Main\n1. **{x}**\n2. x = 1\n\n# Quiet\n<!-- mdl-allow: W0203 -->\n1. **{y}**\n2. y = 1\n";
+    let (program, _) = mdl::parser::Parser::new(source.to_string(), 0).parse().expect("parse failed");
+    let run_entry = |entry: &str| {
+        let mut output = Vec::new();
+        let (_, warnings) = interpreter::execute_program_entry(
+            &program,
+            &mut output,
+            std::path::PathBuf::from("."),
+            entry,
+            Vec::new(),
+        )
+        .expect("execution failed");
+        warnings
+    };
+
+    let mut levels = LintLevels::default();
+    levels.set(Code::ReadBeforeAssignment, Level::Deny);
+    let denied = interpreter::apply_lint_levels(&levels, &program, run_entry("Main"));
+    assert_eq!(denied.len(), 1);
+    assert!(!denied[0].is_warning);
+    assert_eq!(denied[0].code(), Code::ReadBeforeAssignment);
+    assert!(denied[0].notes.iter().any(|note| note.contains("denied")));
+
+    // The in-source comment wins over the command line
+    let quiet = run_entry("Quiet");
+    assert_eq!(quiet.len(), 1);
+    assert!(interpreter::apply_lint_levels(&levels, &program, quiet).is_empty());
+}
+
+#[test]
+fn parse_returns_warnings_with_program() {
+    let source = "Intro text.\n\n# Main\n1. **{1}**\n";
+    let (program, warnings) = mdl::parser::Parser::new(source.to_string(), 0).parse().expect("parse failed");
+    assert_eq!(program.blocks.len(), 1);
+    assert_eq!(warnings.len(), 1);
+    assert!(warnings[0].is_warning());
+    assert_eq!(warnings[0].code, mdl::codes::Code::ContentOutsideBlock);
+    assert_eq!(&source[warnings[0].span.clone()], "Intro text.");
+
+    // With an error as well, parsing fails with both
+    let source = format!("{}2. x = 1 +\n", source);
+    let diagnostics = mdl::parser::Parser::new(source, 0).parse().expect_err("expected a parse error");
+    assert_eq!(diagnostics.len(), 2);
+}
+
+/// The value of the first instruction of a one-block program.
+fn first_value(source: &str) -> mdl::instruction::value::Value {
+    let (program, _) = mdl::parser::Parser::new(source.to_string(), 0).parse().expect("parse failed");
+    match &program.blocks[0].chain.groups[0].instructions[0] {
+        mdl::instruction::Instruction::Assignment { value, .. }
+        | mdl::instruction::Instruction::Expression { value, .. } => value.clone(),
+    }
+}
+
+#[test]
+fn markdown_text_parenthesizes_nested_operations() {
+    use interpreter::evaluator::value_to_markdown_text;
+
+    let cases = [
+        ("(1 + y) * -y", "(1 + y) * -y"),
+        ("1 + y * 2", "1 + (y * 2)"),
+        ("y > 1 ? \"a\" : \"b\"", "(y > 1) ? \"a\" : \"b\""),
+        ("a ? (b ? 1 : 2)", "a ? (b ? 1 : 2)"),
+        ("!(a && b)", "!(a && b)"),
+    ];
+    for (source, text) in cases {
+        let value = first_value(&format!("# Main\n1. x = {source}"));
+        assert_eq!(value_to_markdown_text(&value), text, "{source}");
+    }
+}
+
+#[test]
+fn markdown_text_writes_match_arms_as_list_items() {
+    use interpreter::evaluator::value_to_markdown_text;
+
+    let value = first_value("# Main\n1. x = match y\n    - 1: \"one\"\n    - otherwise n: n + 1");
+    assert_eq!(value_to_markdown_text(&value), "match y\n    - 1: \"one\"\n    - otherwise n: n + 1");
+
+    let value = first_value("# Main\n1. x = match y\n    - \"a\": 1\n    - otherwise: 0");
+    assert_eq!(value_to_markdown_text(&value), "match y\n    - \"a\": 1\n    - otherwise: 0");
+}
@@ -0,0 +1,137 @@
+use std::ops::Range;
+
+use crate::Program;
+use crate::analysis::{for_each_child, template_bindings};
+use crate::codes::{Code, same_fence_read_write, same_fence_write_write};
+use crate::block::Block;
+use crate::chain::fence_group::FenceGroup;
+use crate::instruction::Instruction;
+use crate::instruction::value::Value;
+use crate::parser::ParseError;
+
+/// Report accesses that race within a fence group.
+/// Instructions sharing a fence index run in no defined order, so reading a
+/// variable that another instruction of the group assigns, or assigning it
+/// from two instructions, is undefined behavior. Unlike the runtime check,
+/// every branch of every conditional and match is considered, whether or not
+/// it would be taken.
+pub fn check_fences(program: &Program) -> Vec<ParseError> {
+    let mut warnings = Vec::new();
+    for block in &program.blocks {
+        check_block(block, program.source_id, &mut warnings);
+    }
+    warnings
+}
+
+fn check_block(block: &Block, file_id: usize, warnings: &mut Vec<ParseError>) {
+    for group in &block.chain.groups {
+        check_group(group, file_id, warnings);
+    }
+    for child in &block.children {
+        check_block(child, file_id, warnings);
+    }
+}
+
+fn check_group(group: &FenceGroup, file_id: usize, warnings: &mut Vec<ParseError>) {
+    // Writes of the group: (instruction index, variable, span)
+    let writes: Vec<(usize, &str, &Range<usize>)> = group
+        .instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, instruction)| match instruction {
+            Instruction::Assignment { variable, span, .. } => Some((idx, variable.as_str(), span)),
+            Instruction::Expression { .. } => None,
+        })
+        .collect();
+    if writes.is_empty() {
+        return;
+    }
+
+    for (idx, instruction) in group.instructions.iter().enumerate() {
+        let (Instruction::Assignment { value, span, .. } | Instruction::Expression { value, span }) =
+            instruction;
+        let mut reads = Vec::new();
+        collect_reads(value, span, &mut Vec::new(), &mut reads);
+
+        for (name, read_span) in reads {
+            let conflicting: Vec<&Range<usize>> = writes
+                .iter()
+                .filter(|(write_idx, variable, _)| *write_idx != idx && *variable == name)
+                .map(|(_, _, span)| *span)
+                .collect();
+            if conflicting.is_empty() {
+                continue;
+            }
+            let (message, note) = same_fence_read_write(&name, group.index);
+            let mut warning =
+                ParseError::warning(Code::SameFenceReadWrite, message, read_span, file_id).with_label("read here");
+            for write_span in conflicting {
+                warning = warning.with_secondary(write_span.clone(), "assigned here");
+            }
+            warnings.push(warning.with_note(note));
+        }
+    }
+
+    // Write/write: report each variable once, at its second assignment
+    let mut reported: Vec<&str> = Vec::new();
+    for (position, (_, variable, span)) in writes.iter().enumerate() {
+        if reported.contains(variable) {
+            continue;
+        }
+        let mut others = writes[position + 1..].iter().filter(|(_, other, _)| other == variable);
+        let Some((_, _, second)) = others.next() else {
+            continue;
+        };
+        reported.push(variable);
+
+        let (message, note) = same_fence_write_write(variable, group.index);
+        let mut warning = ParseError::warning(Code::SameFenceWriteWrite, message, (*second).clone(), file_id)
+            .with_label("assigned again here")
+        .with_secondary((*span).clone(), "first assigned here");
+        for (_, _, other) in others {
+            warning = warning.with_secondary((*other).clone(), "also assigned here");
+        }
+        warnings.push(warning.with_note(note));
+    }
+}
+
+/// Collect every variable read in `value`, skipping names bound by an
+/// enclosing match arm (bindings are arm-local and never race with writes).
+fn collect_reads(
+    value: &Value,
+    instruction_span: &Range<usize>,
+    bound: &mut Vec<String>,
+    reads: &mut Vec<(String, Range<usize>)>,
+) {
+    match value {
+        Value::VariableReference(name, span) => {
+            if !bound.contains(name) {
+                let span = if span.is_empty() { instruction_span.clone() } else { span.clone() };
+                reads.push((name.clone(), span));
+            }
+        }
+        Value::Match {
+            value: scrutinee,
+            arms,
+            otherwise,
+            ..
+        } => {
+            collect_reads(scrutinee, instruction_span, bound, reads);
+            for arm in arms {
+                let outer_len = bound.len();
+                template_bindings(&arm.pattern, bound);
+                collect_reads(&arm.result, instruction_span, bound, reads);
+                bound.truncate(outer_len);
+            }
+            if let Some(fallback) = otherwise {
+                let outer_len = bound.len();
+                bound.extend(fallback.binding.clone());
+                collect_reads(&fallback.result, instruction_span, bound, reads);
+                bound.truncate(outer_len);
+            }
+        }
+        _ => for_each_child(value, &mut |child| {
+            collect_reads(child, instruction_span, bound, reads)
+        }),
+    }
+}
@@ -0,0 +1,158 @@
+use std::ops::Range;
+
+use crate::Program;
+use crate::analysis::for_each_value;
+use crate::codes::Code;
+use crate::instruction::template::Template;
+use crate::instruction::value::{BinaryOperator, MatchArm, OtherwiseArm, UnaryOperator, Value};
+use crate::parser::ParseError;
+
+/// Static checks for match expressions:
+/// - Boolean matches that cover only one of `true` / `false`.
+/// - Arms that can never be reached because an earlier catch-all covers them.
+/// - Alternations that list the same alternative more than once.
+pub fn check_matches(program: &Program) -> Vec<ParseError> {
+    let file_id = program.source_id;
+    let mut warnings = Vec::new();
+
+    for_each_value(program, &mut |value| {
+        if let Value::Match {
+            value: scrutinee,
+            arms,
+            otherwise,
+            span,
+        } = value
+        {
+            check_match(scrutinee, arms, otherwise.as_ref(), span, file_id, &mut warnings);
+        }
+    });
+
+    warnings
+}
+
+fn check_match(
+    scrutinee: &Value,
+    arms: &[MatchArm],
+    otherwise: Option<&OtherwiseArm>,
+    span: &Range<usize>,
+    file_id: usize,
+    warnings: &mut Vec<ParseError>,
+) {
+    // `otherwise` is tried after every other arm, wherever it is written
+    let mut catch_all = false;
+    for arm in arms {
+        if catch_all {
+            warnings.push(
+                ParseError::warning(Code::UnreachableArm, "unreachable match arm", arm.span.clone(), file_id)
+                    .with_note("an earlier `_` or binding arm matches every value"),
+            );
+        }
+        if let Template::Alternation(alternatives) = &arm.pattern {
+            check_duplicate_alternatives(alternatives, &arm.span, file_id, warnings);
+        }
+        catch_all |= is_catch_all(&arm.pattern);
+    }
+
+    if let Some(fallback) = otherwise {
+        if catch_all {
+            warnings.push(
+                ParseError::warning(Code::UnreachableArm, "unreachable `otherwise` arm", fallback.span.clone(), file_id)
+                    .with_note("an earlier `_` or binding arm matches every value"),
+            );
+        }
+        return;
+    }
+
+    if catch_all || !is_boolean_match(scrutinee, arms) {
+        return;
+    }
+
+    for missing in [true, false] {
+        let covered = arms
+            .iter()
+            .any(|arm| covers_boolean(&arm.pattern, missing));
+        if !covered {
+            warnings.push(
+                ParseError::warning(
+                    Code::NonExhaustiveBooleanMatch,
+                    format!("non-exhaustive boolean match: `{}` is not covered", missing),
+                    span.clone(),
+                    file_id,
+                )
+                .with_note(format!("add a `{}` arm or an `otherwise` arm", missing)),
+            );
+        }
+    }
+}
+
+fn check_duplicate_alternatives(
+    alternatives: &[Template],
+    span: &Range<usize>,
+    file_id: usize,
+    warnings: &mut Vec<ParseError>,
+) {
+    for (i, alt) in alternatives.iter().enumerate() {
+        if alternatives[..i].contains(alt) {
+            warnings.push(
+                ParseError::warning(Code::DuplicateAlternative, "duplicate alternative in pattern", span.clone(), file_id)
+                    .with_note(format!("`{}` is listed more than once", alt)),
+            );
+        }
+    }
+}
+
+/// Whether a pattern matches every value.
+fn is_catch_all(template: &Template) -> bool {
+    match template {
+        Template::Wildcard | Template::Binding(_) => true,
+        Template::Alternation(alternatives) => alternatives.iter().any(is_catch_all),
+        _ => false,
+    }
+}
+
+fn covers_boolean(template: &Template, b: bool) -> bool {
+    match template {
+        Template::BooleanLiteral(v) => *v == b,
+        Template::Alternation(alternatives) => alternatives.iter().any(|alt| covers_boolean(alt, b)),
+        _ => false,
+    }
+}
+
+/// A match is considered Boolean if its scrutinee is statically Boolean,
+/// or if every literal arm pattern is a Boolean literal.
+fn is_boolean_match(scrutinee: &Value, arms: &[MatchArm]) -> bool {
+    if is_boolean_expr(scrutinee) {
+        return true;
+    }
+    !arms.is_empty() && arms.iter().all(|arm| is_boolean_pattern(&arm.pattern))
+}
+
+fn is_boolean_pattern(template: &Template) -> bool {
+    match template {
+        Template::BooleanLiteral(_) => true,
+        Template::Alternation(alternatives) => alternatives.iter().all(is_boolean_pattern),
+        _ => false,
+    }
+}
+
+fn is_boolean_expr(value: &Value) -> bool {
+    match value {
+        Value::BooleanLiteral(..) => true,
+        Value::UnaryOperation {
+            operator: UnaryOperator::LogicalNot,
+            ..
+        } => true,
+        Value::BinaryOperation { operator, .. } => matches!(
+            operator,
+            BinaryOperator::LogicalAnd
+                | BinaryOperator::LogicalOr
+                | BinaryOperator::Equality
+                | BinaryOperator::Inequality
+                | BinaryOperator::GreaterThan
+                | BinaryOperator::LessThan
+                | BinaryOperator::GreaterThanOrEqual
+                | BinaryOperator::LessThanOrEqual
+        ),
+        _ => false,
+    }
+}
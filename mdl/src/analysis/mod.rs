@@ -0,0 +1,89 @@
+pub mod fences;
+pub mod matches;
+pub mod resolve;
+pub mod types;
+
+use std::path::Path;
+
+use crate::Program;
+use crate::block::Parameter;
+use crate::instruction::template::{InlinePattern, Template};
+use crate::instruction::value::Value;
+use crate::parser::ParseError;
+use crate::visit::{self, Visit};
+
+/// Run all static analysis passes over a parsed program.
+/// Returns warnings only; a program that parses is always runnable.
+pub fn analyze(program: &Program) -> Vec<ParseError> {
+    let mut warnings = Vec::new();
+    warnings.extend(matches::check_matches(program));
+    warnings.extend(fences::check_fences(program));
+    warnings.extend(types::check_types(program));
+    warnings
+}
+
+/// Run every static check, including name resolution (`mdl check`).
+/// Unlike [`analyze`], this can report errors: references that are certain
+/// to fail if they are ever evaluated. Local imports resolve against `base_dir`.
+pub fn check(program: &Program, base_dir: &Path) -> Vec<ParseError> {
+    let mut diagnostics = analyze(program);
+    diagnostics.extend(resolve::check_references(program, base_dir));
+    diagnostics
+}
+
+/// Call `f` on every expression in the program, in source order.
+pub(crate) fn for_each_value(program: &Program, f: &mut dyn FnMut(&Value)) {
+    Values { f, descend: true }.visit_program(program);
+}
+
+/// Call `f` on `value` and then on each of its sub-expressions (pre-order).
+pub(crate) fn walk_value(value: &Value, f: &mut dyn FnMut(&Value)) {
+    Values { f, descend: true }.visit_value(value);
+}
+
+/// Call `f` on each direct sub-expression of `value`, in source order.
+pub(crate) fn for_each_child(value: &Value, f: &mut dyn FnMut(&Value)) {
+    visit::walk_value(&mut Values { f, descend: false }, value);
+}
+
+/// Calls `f` on each expression it reaches, descending into sub-expressions
+/// only if `descend` is set.
+struct Values<'f> {
+    f: &'f mut dyn FnMut(&Value),
+    descend: bool,
+}
+
+impl<'ast> Visit<'ast> for Values<'_> {
+    // Only instructions are expressions here, not parameter defaults
+    fn visit_parameter(&mut self, _parameter: &'ast Parameter) {}
+
+    fn visit_value(&mut self, value: &'ast Value) {
+        (self.f)(value);
+        if self.descend {
+            visit::walk_value(self, value);
+        }
+    }
+}
+
+/// Collect the names a match pattern binds.
+pub(crate) fn template_bindings(template: &Template, names: &mut Vec<String>) {
+    Bindings(names).visit_template(template);
+}
+
+struct Bindings<'a>(&'a mut Vec<String>);
+
+impl<'ast> Visit<'ast> for Bindings<'_> {
+    fn visit_template(&mut self, template: &'ast Template) {
+        if let Template::Binding(name) = template {
+            self.0.push(name.clone());
+        }
+        visit::walk_template(self, template);
+    }
+
+    fn visit_inline_pattern(&mut self, pattern: &'ast InlinePattern) {
+        if let InlinePattern::Capture(name) = pattern {
+            self.0.push(name.clone());
+        }
+        visit::walk_inline_pattern(self, pattern);
+    }
+}
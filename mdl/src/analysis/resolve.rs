@@ -0,0 +1,394 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::Program;
+use crate::analysis::{for_each_child, template_bindings};
+use crate::block::reference::{BlockReference, resolve_import_path};
+use crate::block::scope::{self, BlockPath};
+use crate::block::{Block, Parameter};
+use crate::codes::Code;
+use crate::instruction::Instruction;
+use crate::instruction::value::Value;
+use crate::parser::{ParseError, Parser};
+use crate::suggest::did_you_mean;
+
+/// Name resolution checks for `mdl check`:
+/// - Every static block reference names an existing block, including blocks
+///   in local imports (resolved relative to `base_dir`).
+/// - Every variable read is assigned (or is a parameter) in its block or one
+///   of its lexical ancestors, or is bound by an enclosing match arm.
+/// - Call sites agree with declared parameter lists, and `#n` reads do not go
+///   past the arguments a block receives.
+pub fn check_references(program: &Program, base_dir: &Path) -> Vec<ParseError> {
+    let mut names = Vec::new();
+    collect_names(&program.blocks, &mut names);
+
+    let mut resolver = Resolver {
+        blocks: &program.blocks,
+        names,
+        base_dir,
+        file_id: program.source_id,
+        imports: HashMap::new(),
+        call_sites: HashMap::new(),
+        argument_reads: Vec::new(),
+        escaped: HashSet::new(),
+        dynamic_calls: false,
+        diagnostics: Vec::new(),
+    };
+
+    for (index, block) in program.blocks.iter().enumerate() {
+        resolver.check_block(block, &mut vec![index], &mut Vec::new());
+    }
+    resolver.check_argument_reads();
+    resolver.diagnostics
+}
+
+fn collect_names<'a>(blocks: &'a [Block], names: &mut Vec<&'a str>) {
+    for block in blocks {
+        names.push(&block.name);
+        collect_names(&block.children, names);
+    }
+}
+
+/// Variables a block defines: its parameters and every assignment target.
+fn block_variables(block: &Block) -> Vec<String> {
+    let mut vars: Vec<String> = block
+        .parameters
+        .iter()
+        .flatten()
+        .map(|p| p.name.clone())
+        .collect();
+    for group in &block.chain.groups {
+        for instruction in &group.instructions {
+            if let Instruction::Assignment { variable, .. } = instruction {
+                vars.push(variable.clone());
+            }
+        }
+    }
+    vars
+}
+
+/// A static call site of a local block.
+struct CallSite {
+    positional: usize,
+    named: Vec<String>,
+    span: Range<usize>,
+}
+
+struct Resolver<'a> {
+    blocks: &'a [Block],
+    /// Every block name in the program, for suggestions.
+    names: Vec<&'a str>,
+    base_dir: &'a Path,
+    file_id: usize,
+    /// Block names of each local import, or None if the file could not be loaded.
+    imports: HashMap<String, Option<HashSet<String>>>,
+    call_sites: HashMap<BlockPath, Vec<CallSite>>,
+    /// `#n` reads: (reading block, index, span).
+    argument_reads: Vec<(BlockPath, usize, Range<usize>)>,
+    /// Blocks referenced as values (`&[](#block)`); their call sites are unknown.
+    escaped: HashSet<BlockPath>,
+    /// Whether the program invokes blocks through computed destinations.
+    dynamic_calls: bool,
+    diagnostics: Vec<ParseError>,
+}
+
+impl Resolver<'_> {
+    /// Check the block at `path`.
+    fn check_block(&mut self, block: &Block, path: &mut BlockPath, visible: &mut Vec<String>) {
+        let outer_len = visible.len();
+        visible.extend(block_variables(block));
+
+        for group in &block.chain.groups {
+            for instruction in &group.instructions {
+                let (Instruction::Assignment { value, span, .. }
+                | Instruction::Expression { value, span }) = instruction;
+                let mut ctx = ValueContext {
+                    block: &block.name,
+                    path,
+                    visible,
+                    bound: Vec::new(),
+                    span,
+                };
+                self.check_value(value, &mut ctx);
+            }
+        }
+
+        for (index, child) in block.children.iter().enumerate() {
+            path.push(index);
+            self.check_block(child, path, visible);
+            path.pop();
+        }
+        visible.truncate(outer_len);
+    }
+
+    fn check_value(&mut self, value: &Value, ctx: &mut ValueContext<'_>) {
+        match value {
+            Value::VariableReference(name, span) => {
+                if !ctx.bound.contains(name) && !ctx.visible.contains(name) {
+                    let span = if span.is_empty() { ctx.span.clone() } else { span.clone() };
+                    let mut error = ParseError::error(
+                        Code::UndefinedVariable,
+                        format!("undefined variable '{}'", name),
+                        span,
+                        self.file_id,
+                    )
+                    .with_note(format!(
+                        "'{}' is never assigned in block '{}' or any enclosing block",
+                        name, ctx.block
+                    ));
+                    let candidates = ctx.bound.iter().chain(ctx.visible).map(String::as_str);
+                    if let Some(note) = did_you_mean(name, candidates) {
+                        error = error.with_note(note);
+                    }
+                    self.diagnostics.push(error);
+                }
+            }
+            Value::PositionalArgumentReference(index, span) => {
+                let span = if span.is_empty() { ctx.span.clone() } else { span.clone() };
+                self.argument_reads.push((ctx.path.to_vec(), *index, span));
+            }
+            Value::BlockRef(block_ref, _) => {
+                if self.resolve(block_ref, ctx.path, ctx.span)
+                    && let BlockReference::Local(name) = block_ref
+                    && let Some(callee) = scope::resolve_local(self.blocks, ctx.path, name)
+                {
+                    self.escaped.insert(callee);
+                }
+            }
+            Value::BlockInvocation(args, block_ref, _) | Value::EvaluatedBlockInvocation(args, block_ref, _) => {
+                if self.resolve(block_ref, ctx.path, ctx.span)
+                    && let BlockReference::Local(name) = block_ref
+                    && let Some(callee) = scope::resolve_local(self.blocks, ctx.path, name)
+                {
+                    let call = CallSite {
+                        positional: args
+                            .iter()
+                            .filter(|a| !matches!(a, Value::NamedArgument(..) | Value::NamedArgumentRecord(_)))
+                            .count(),
+                        named: args
+                            .iter()
+                            .filter_map(|a| match a {
+                                Value::NamedArgument(name, ..) => Some(name.clone()),
+                                _ => None,
+                            })
+                            .collect(),
+                        span: ctx.span.clone(),
+                    };
+                    // `#:` forwards an unknown set of named arguments
+                    if !args.iter().any(|a| matches!(a, Value::NamedArgumentRecord(_))) {
+                        self.check_call_site(&callee, &call);
+                    }
+                    self.call_sites.entry(callee).or_default().push(call);
+                }
+                for_each_child(value, &mut |child| self.check_value(child, ctx));
+            }
+            Value::DynamicBlockInvocation(..) | Value::EvaluatedDynamicBlockInvocation(..) => {
+                self.dynamic_calls = true;
+                for_each_child(value, &mut |child| self.check_value(child, ctx));
+            }
+            Value::Match {
+                value: scrutinee,
+                arms,
+                otherwise,
+                ..
+            } => {
+                self.check_value(scrutinee, ctx);
+                for arm in arms {
+                    let outer_len = ctx.bound.len();
+                    template_bindings(&arm.pattern, &mut ctx.bound);
+                    self.check_value(&arm.result, ctx);
+                    ctx.bound.truncate(outer_len);
+                }
+                if let Some(fallback) = otherwise {
+                    let outer_len = ctx.bound.len();
+                    ctx.bound.extend(fallback.binding.clone());
+                    self.check_value(&fallback.result, ctx);
+                    ctx.bound.truncate(outer_len);
+                }
+            }
+            _ => for_each_child(value, &mut |child| self.check_value(child, ctx)),
+        }
+    }
+
+    /// Report a reference made from the block at `from` to a block that does not exist.
+    /// Returns whether it resolved.
+    fn resolve(&mut self, block_ref: &BlockReference, from: &[usize], span: &Range<usize>) -> bool {
+        match block_ref {
+            BlockReference::Local(name) => {
+                if scope::resolve_local(self.blocks, from, name).is_some() {
+                    return true;
+                }
+                let mut error = ParseError::error(
+                    Code::UndefinedBlock,
+                    format!("undefined block '{}'", name),
+                    span.clone(),
+                    self.file_id,
+                );
+                if let Some(note) = did_you_mean(name, self.names.iter().copied()) {
+                    error = error.with_note(note);
+                }
+                self.diagnostics.push(error);
+                false
+            }
+            BlockReference::LocalImport { path, block } => {
+                let base_dir = self.base_dir;
+                let imported = self
+                    .imports
+                    .entry(path.clone())
+                    .or_insert_with(|| load_import_names(base_dir, path));
+                match imported {
+                    Some(names) if names.contains(block) => true,
+                    Some(names) => {
+                        let mut error = ParseError::error(
+                            Code::UndefinedBlock,
+                            format!("undefined block '{}' in '{}'", block, path),
+                            span.clone(),
+                            self.file_id,
+                        );
+                        if let Some(note) = did_you_mean(block, names.iter().map(String::as_str)) {
+                            error = error.with_note(note);
+                        }
+                        self.diagnostics.push(error);
+                        false
+                    }
+                    None => {
+                        self.diagnostics.push(ParseError::error(
+                            Code::ImportFailed,
+                            format!("cannot load import '{}'", path),
+                            span.clone(),
+                            self.file_id,
+                        ));
+                        false
+                    }
+                }
+            }
+            // Remote imports are resolved at runtime only
+            BlockReference::RemoteImport { .. } => true,
+        }
+    }
+
+    /// Check a call site against the declared parameters of the block at `callee`.
+    fn check_call_site(&mut self, callee: &[usize], call: &CallSite) {
+        let Some(block) = scope::block_at(self.blocks, callee) else {
+            return;
+        };
+        let Some(params) = &block.parameters else {
+            return;
+        };
+        let name = &block.name;
+
+        let required = params.iter().filter(|p| p.default.is_none()).count();
+        let given = call.positional + call.named.len();
+        if call.positional > params.len() || given < required {
+            self.diagnostics.push(ParseError::error(
+                Code::ArityMismatch,
+                arity_message(name, required, params.len(), given),
+                call.span.clone(),
+                self.file_id,
+            ));
+            return;
+        }
+
+        for arg_name in &call.named {
+            if !params.iter().any(|p| &p.name == arg_name) {
+                self.diagnostics.push(
+                    ParseError::error(
+                        Code::UnknownNamedArgument,
+                        format!("block '{}' has no parameter named '{}'", name, arg_name),
+                        call.span.clone(),
+                        self.file_id,
+                    )
+                    .with_note(parameter_list_note(params)),
+                );
+            }
+        }
+    }
+
+    /// Warn about `#n` reads past every argument the block can receive.
+    fn check_argument_reads(&mut self) {
+        for (path, index, span) in std::mem::take(&mut self.argument_reads) {
+            let Some(block) = scope::block_at(self.blocks, &path) else {
+                continue;
+            };
+            let block_name = &block.name;
+            if let Some(params) = &block.parameters {
+                if index >= params.len() {
+                    self.diagnostics.push(
+                        ParseError::warning(
+                            Code::ArgumentPastParameters,
+                            format!("`#{}` reads past the declared parameters of block '{}'", index, block_name),
+                            span,
+                            self.file_id,
+                        )
+                        .with_note(parameter_list_note(params)),
+                    );
+                }
+                continue;
+            }
+
+            // Without declared parameters, arity is only known from static call sites
+            if self.dynamic_calls || self.escaped.contains(&path) {
+                continue;
+            }
+            let Some(calls) = self.call_sites.get(&path) else {
+                continue;
+            };
+            let max = calls.iter().map(|c| c.positional).max().unwrap_or(0);
+            if index >= max {
+                self.diagnostics.push(
+                    ParseError::warning(
+                        Code::ArgumentPastCallSites,
+                        format!("`#{}` reads past the arguments passed at every call site", index),
+                        span,
+                        self.file_id,
+                    )
+                    .with_note(format!("block '{}' is called with at most {} argument(s)", block_name, max)),
+                );
+            }
+        }
+    }
+}
+
+/// Per-instruction state while checking an expression.
+struct ValueContext<'v> {
+    block: &'v str,
+    /// Path of the current block, the scope its local references resolve from.
+    path: &'v [usize],
+    /// Variables visible from the current block (its own and its lexical ancestors').
+    visible: &'v [String],
+    /// Pattern bindings of the enclosing match arms.
+    bound: Vec<String>,
+    /// Span of the instruction being checked.
+    span: &'v Range<usize>,
+}
+
+fn arity_message(block: &str, required: usize, declared: usize, got: usize) -> String {
+    if required == declared {
+        format!("arity mismatch: block '{}' expects {} argument(s), got {}", block, declared, got)
+    } else {
+        format!(
+            "arity mismatch: block '{}' expects {} to {} arguments, got {}",
+            block, required, declared, got
+        )
+    }
+}
+
+fn parameter_list_note(params: &[Parameter]) -> String {
+    if params.is_empty() {
+        return "the block declares no parameters".to_string();
+    }
+    let names: Vec<&str> = params.iter().map(|p| p.name.as_str()).collect();
+    format!("declared parameters: {}", names.join(", "))
+}
+
+/// Load the names of every block in a local import, or None if it cannot be read or parsed.
+fn load_import_names(base_dir: &Path, path: &str) -> Option<HashSet<String>> {
+    let source = std::fs::read_to_string(resolve_import_path(base_dir, path)).ok()?;
+    let (program, _) = Parser::new(source, 0).parse().ok()?;
+
+    let mut names = Vec::new();
+    collect_names(&program.blocks, &mut names);
+    Some(names.into_iter().map(str::to_string).collect())
+}
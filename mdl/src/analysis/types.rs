@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use crate::Program;
+use crate::analysis::walk_value;
+use crate::block::Block;
+use crate::block::scope::{self, BlockPath};
+use crate::codes::Code;
+use crate::block::reference::BlockReference;
+use crate::instruction::Instruction;
+use crate::instruction::types::TypeAnnotation;
+use crate::instruction::value::{BinaryOperator, UnaryOperator, Value};
+use crate::parser::ParseError;
+
+/// Static checks for type annotations. Only values whose type is evident
+/// from their syntax (literals and operators) are checked; everything else
+/// is left to the runtime checks.
+/// - Annotated assignments and parameter defaults whose value has another type.
+/// - Arguments to annotated parameters of local blocks whose value has another type.
+/// - The same variable annotated with different types in one block.
+pub fn check_types(program: &Program) -> Vec<ParseError> {
+    let mut checker = TypeChecker {
+        blocks: &program.blocks,
+        file_id: program.source_id,
+        warnings: Vec::new(),
+    };
+    for (index, block) in program.blocks.iter().enumerate() {
+        checker.check_block(block, &mut vec![index]);
+    }
+    checker.warnings
+}
+
+struct TypeChecker<'a> {
+    blocks: &'a [Block],
+    file_id: usize,
+    warnings: Vec<ParseError>,
+}
+
+impl TypeChecker<'_> {
+    /// Check the block at `path`.
+    fn check_block(&mut self, block: &Block, path: &mut BlockPath) {
+        let mut declared: HashMap<&str, TypeAnnotation> = HashMap::new();
+
+        for param in block.parameters.iter().flatten() {
+            let Some(annotation) = param.annotation else {
+                continue;
+            };
+            declared.insert(&param.name, annotation);
+            if let Some(found) = param.default.as_ref().and_then(static_type)
+                && found != annotation
+            {
+                self.warnings.push(ParseError::warning(
+                    Code::StaticTypeMismatch,
+                    format!(
+                        "type mismatch: default value of parameter '{}' is {}, but it is declared as {}",
+                        param.name, found, annotation
+                    ),
+                    param.span.clone(),
+                    self.file_id,
+                ));
+            }
+        }
+
+        let instructions = block.chain.groups.iter().flat_map(|g| &g.instructions);
+        for instruction in instructions.clone() {
+            if let Instruction::Assignment {
+                variable,
+                annotation: Some(annotation),
+                span,
+                ..
+            } = instruction
+            {
+                match declared.get(variable.as_str()) {
+                    Some(previous) if previous != annotation => {
+                        self.warnings.push(
+                            ParseError::warning(
+                                Code::ConflictingAnnotations,
+                                format!("conflicting type annotations for '{}'", variable),
+                                span.clone(),
+                                self.file_id,
+                            )
+                            .with_note(format!("'{}' was already declared as {}", variable, previous)),
+                        );
+                    }
+                    Some(_) => {}
+                    None => {
+                        declared.insert(variable, *annotation);
+                    }
+                }
+            }
+        }
+
+        for instruction in instructions {
+            let (value, span) = match instruction {
+                Instruction::Assignment {
+                    variable,
+                    value,
+                    span,
+                    ..
+                } => {
+                    if let Some(annotation) = declared.get(variable.as_str())
+                        && let Some(found) = static_type(value)
+                        && found != *annotation
+                    {
+                        self.warnings.push(ParseError::warning(
+                            Code::StaticTypeMismatch,
+                            format!(
+                                "type mismatch: variable '{}' is declared as {}, but is assigned {}",
+                                variable, annotation, found
+                            ),
+                            span.clone(),
+                            self.file_id,
+                        ));
+                    }
+                    (value, span)
+                }
+                Instruction::Expression { value, span } => (value, span),
+            };
+            walk_value(value, &mut |v| self.check_invocation(v, span, path));
+        }
+
+        for (index, child) in block.children.iter().enumerate() {
+            path.push(index);
+            self.check_block(child, path);
+            path.pop();
+        }
+    }
+
+    /// Check the arguments of an invocation made from the block at `from`.
+    fn check_invocation(&mut self, value: &Value, span: &Range<usize>, from: &[usize]) {
+        let (Value::BlockInvocation(args, BlockReference::Local(name), _)
+        | Value::EvaluatedBlockInvocation(args, BlockReference::Local(name), _)) = value
+        else {
+            return;
+        };
+        let callee = scope::resolve_local(self.blocks, from, name).and_then(|path| scope::block_at(self.blocks, &path));
+        let Some(params) = callee.and_then(|b| b.parameters.as_ref()) else {
+            return;
+        };
+
+        let mut position = 0;
+        for arg in args {
+            let param = match arg {
+                Value::NamedArgument(arg_name, ..) => params.iter().find(|p| &p.name == arg_name),
+                Value::NamedArgumentRecord(_) => continue,
+                _ => {
+                    position += 1;
+                    params.get(position - 1)
+                }
+            };
+            if let Some(param) = param
+                && let Some(annotation) = param.annotation
+                && let Some(found) = static_type(arg)
+                && found != annotation
+            {
+                self.warnings.push(ParseError::warning(
+                    Code::StaticTypeMismatch,
+                    format!(
+                        "type mismatch: parameter '{}' of block '{}' is declared as {}, but this argument is {}",
+                        param.name, name, annotation, found
+                    ),
+                    span.clone(),
+                    self.file_id,
+                ));
+            }
+        }
+    }
+}
+
+/// The type of a value when it is evident from syntax alone.
+fn static_type(value: &Value) -> Option<TypeAnnotation> {
+    match value {
+        Value::NumberLiteral(..) => Some(TypeAnnotation::Number),
+        Value::StringLiteral(..) | Value::Interpolation(_) => Some(TypeAnnotation::String),
+        Value::BooleanLiteral(..) => Some(TypeAnnotation::Boolean),
+        Value::UnitLiteral(_) => Some(TypeAnnotation::Unit),
+        Value::BlockRef(..) => Some(TypeAnnotation::Block),
+        Value::NamedArgument(_, inner, _) => static_type(inner),
+        Value::UnaryOperation { operator, .. } => Some(match operator {
+            UnaryOperator::Negation => TypeAnnotation::Number,
+            UnaryOperator::LogicalNot => TypeAnnotation::Boolean,
+        }),
+        Value::BinaryOperation {
+            operator,
+            left,
+            right,
+            ..
+        } => match operator {
+            BinaryOperator::Addition => {
+                let left = static_type(left)?;
+                (left == static_type(right)?).then_some(left)
+            }
+            BinaryOperator::Subtraction
+            | BinaryOperator::Multiplication
+            | BinaryOperator::Division
+            | BinaryOperator::Modulo => Some(TypeAnnotation::Number),
+            BinaryOperator::LogicalAnd
+            | BinaryOperator::LogicalOr
+            | BinaryOperator::Equality
+            | BinaryOperator::Inequality
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::LessThan
+            | BinaryOperator::GreaterThanOrEqual
+            | BinaryOperator::LessThanOrEqual => Some(TypeAnnotation::Boolean),
+        },
+        Value::Conditional {
+            true_branch,
+            false_branch: Some(false_branch),
+            ..
+        } => {
+            let found = static_type(true_branch)?;
+            (found == static_type(false_branch)?).then_some(found)
+        }
+        _ => None,
+    }
+}
@@ -1,18 +1,26 @@
 pub mod reference;
+pub mod scope;
 
 use std::ops::Range;
 
 use crate::chain::Chain;
 use crate::document::Document;
+use crate::instruction::types::TypeAnnotation;
+use crate::instruction::value::Value;
 
 /// A named block defined by a Markdown heading.
 /// Blocks are the fundamental unit of execution in markdownlang.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Block {
     /// The block name (from heading text), case-sensitive, whitespace-normalized.
     pub name: String,
     /// Heading level: 1 = top-level (#), 2-6 = sub-blocks (##-######).
     pub level: u8,
+    /// Declared parameters, from the heading (`# Add (a, b = 1)`) or a
+    /// `Parameter | Default` table in the body.
+    /// None if the block declares no parameter list (positional arguments only).
+    pub parameters: Option<Vec<Parameter>>,
     /// The execution chain (ordered instructions grouped by fence index).
     pub chain: Chain,
     /// Sub-blocks defined lexically within this block's scope.
@@ -23,3 +31,17 @@ pub struct Block {
     /// Byte span in source for error reporting.
     pub span: Range<usize>,
 }
+
+/// A named parameter declared by a block.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Parameter {
+    pub name: String,
+    /// Declared type (`a: Number`), checked when the block is entered.
+    pub annotation: Option<TypeAnnotation>,
+    /// Value used when the invocation supplies fewer arguments.
+    /// Evaluated in the callee's scope, so it may refer to earlier parameters.
+    pub default: Option<Value>,
+    /// Byte span of the parameter name.
+    pub span: Range<usize>,
+}
@@ -1,5 +1,8 @@
+use std::path::{Path, PathBuf};
+
 /// A reference to a block, used in invocations like [args](#block).
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BlockReference {
     /// Reference to a block by name within the current document: #block-name
     Local(String),
@@ -18,3 +21,14 @@ impl BlockReference {
         }
     }
 }
+
+/// The file a local import `path` refers to, relative to `base_dir`.
+/// A path without an extension means a `.md` file.
+pub fn resolve_import_path(base_dir: &Path, path: &str) -> PathBuf {
+    let resolved = base_dir.join(path);
+    if resolved.extension().is_none() {
+        resolved.with_extension("md")
+    } else {
+        resolved
+    }
+}
@@ -0,0 +1,79 @@
+//! Lexical lookup of blocks. Local references (`[](#name)`), the runtime
+//! and the static passes all resolve block names through these functions,
+//! so they agree on which block a name means when several share it.
+
+use super::Block;
+
+/// Where a block sits in a program: the index of each block on the way down
+/// from the top level. `[]` is the top-level scope itself.
+pub type BlockPath = Vec<usize>;
+
+/// Resolve a local reference to `name` made from the block at `from`.
+///
+/// The nearest enclosing scope wins: the sub-blocks of the calling block,
+/// then those of each of its lexical ancestors, then the top-level blocks.
+/// A block nested anywhere else is found only if no scope on that chain has
+/// one, taking the first in source order.
+pub fn resolve_local(blocks: &[Block], from: &[usize], name: &str) -> Option<BlockPath> {
+    for depth in (0..=from.len()).rev() {
+        let Some(scope) = children_at(blocks, &from[..depth]) else {
+            continue;
+        };
+        if let Some(index) = scope.iter().position(|block| block.name == name) {
+            let mut path = from[..depth].to_vec();
+            path.push(index);
+            return Some(path);
+        }
+    }
+    position(blocks, &|block| block.name == name)
+}
+
+/// Resolve an entry point name, as given on the command line: the first
+/// block in source order with that exact name, or else with that name in
+/// any case.
+pub fn resolve_entry(blocks: &[Block], name: &str) -> Option<BlockPath> {
+    position(blocks, &|block| block.name == name)
+        .or_else(|| position(blocks, &|block| block.name.eq_ignore_ascii_case(name)))
+}
+
+/// Path of the first block in source order, at any depth, that `matches`.
+pub fn position(blocks: &[Block], matches: &dyn Fn(&Block) -> bool) -> Option<BlockPath> {
+    find_first(blocks, &mut Vec::new(), matches)
+}
+
+/// The block at `path`.
+pub fn block_at<'a>(blocks: &'a [Block], path: &[usize]) -> Option<&'a Block> {
+    let (last, parents) = path.split_last()?;
+    children_at(blocks, parents)?.get(*last)
+}
+
+/// Names of the lexical ancestors of the block at `path`, innermost first.
+pub fn ancestor_names(blocks: &[Block], path: &[usize]) -> Vec<String> {
+    (1..path.len())
+        .rev()
+        .filter_map(|depth| block_at(blocks, &path[..depth]))
+        .map(|block| block.name.clone())
+        .collect()
+}
+
+/// The blocks directly inside the scope at `path`.
+fn children_at<'a>(blocks: &'a [Block], path: &[usize]) -> Option<&'a [Block]> {
+    match path {
+        [] => Some(blocks),
+        _ => block_at(blocks, path).map(|block| block.children.as_slice()),
+    }
+}
+
+fn find_first(blocks: &[Block], path: &mut BlockPath, matches: &dyn Fn(&Block) -> bool) -> Option<BlockPath> {
+    for (index, block) in blocks.iter().enumerate() {
+        path.push(index);
+        if matches(block) {
+            return Some(path.clone());
+        }
+        if let Some(found) = find_first(&block.children, path, matches) {
+            return Some(found);
+        }
+        path.pop();
+    }
+    None
+}
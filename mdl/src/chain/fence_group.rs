@@ -4,6 +4,7 @@ use crate::instruction::Instruction;
 /// Instructions within a FenceGroup have no defined relative execution order
 /// (they may run concurrently). All must complete before the next FenceGroup starts.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct FenceGroup {
     /// The fence index (from the ordered list item number).
     pub index: u64,
@@ -5,7 +5,12 @@ use crate::chain::fence_group::FenceGroup;
 /// An ordered sequence of fence groups representing a block's execution plan.
 /// FenceGroups execute in order: all instructions in group N complete before
 /// any instruction in group N+1 begins.
+///
+/// Groups are in source order, whatever their indices: only adjacent items
+/// of one ordered list with the same number share a group. A block with
+/// several ordered lists chains them in source order.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Chain {
     pub groups: Vec<FenceGroup>,
 }
@@ -0,0 +1,443 @@
+use std::fmt;
+
+/// A stable identifier for every kind of diagnostic the parser, the static
+/// checker and the interpreter report. `E` codes are errors, `W` codes are
+/// warnings; the first two digits group related diagnostics.
+/// Codes never change meaning once released; `mdl explain <code>` prints the
+/// long-form explanation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Code {
+    // --- Syntax (E00xx, W00xx) ---
+    InvalidSyntax,
+    InvalidArgumentList,
+    InvalidBlockReference,
+    InvalidMatch,
+    UnknownType,
+    InvalidParameter,
+    InvalidSuppression,
+    ContentOutsideBlock,
+    FenceOrder,
+    SplitInstructionList,
+
+    // --- Names and imports (E01xx) ---
+    UndefinedBlock,
+    UndefinedVariable,
+    ImportFailed,
+    RemoteImport,
+    NoEntryPoint,
+
+    // --- Undefined behavior (W02xx) ---
+    SameFenceReadWrite,
+    SameFenceWriteWrite,
+    ReadBeforeAssignment,
+    NonLexicalRead,
+
+    // --- Types (E03xx, W03xx) ---
+    TypeError,
+    AnnotationMismatch,
+    StaticTypeMismatch,
+    ConflictingAnnotations,
+
+    // --- Arguments (E04xx, W04xx) ---
+    ArityMismatch,
+    UnknownNamedArgument,
+    DuplicateArgument,
+    MissingArgument,
+    ArgumentOutOfBounds,
+    UndefinedNamedArgument,
+    ArgumentPastParameters,
+    ArgumentPastCallSites,
+
+    // --- Pattern matching (E05xx, W05xx) ---
+    NonExhaustiveMatch,
+    UnreachableArm,
+    DuplicateAlternative,
+    NonExhaustiveBooleanMatch,
+
+    // --- Execution (E06xx) ---
+    DivisionByZero,
+    StackOverflow,
+    IoError,
+}
+
+impl Code {
+    pub const ALL: [Code; 38] = [
+        Code::InvalidSyntax,
+        Code::InvalidArgumentList,
+        Code::InvalidBlockReference,
+        Code::InvalidMatch,
+        Code::UnknownType,
+        Code::InvalidParameter,
+        Code::InvalidSuppression,
+        Code::ContentOutsideBlock,
+        Code::FenceOrder,
+        Code::SplitInstructionList,
+        Code::UndefinedBlock,
+        Code::UndefinedVariable,
+        Code::ImportFailed,
+        Code::RemoteImport,
+        Code::NoEntryPoint,
+        Code::SameFenceReadWrite,
+        Code::SameFenceWriteWrite,
+        Code::ReadBeforeAssignment,
+        Code::NonLexicalRead,
+        Code::TypeError,
+        Code::AnnotationMismatch,
+        Code::StaticTypeMismatch,
+        Code::ConflictingAnnotations,
+        Code::ArityMismatch,
+        Code::UnknownNamedArgument,
+        Code::DuplicateArgument,
+        Code::MissingArgument,
+        Code::ArgumentOutOfBounds,
+        Code::UndefinedNamedArgument,
+        Code::ArgumentPastParameters,
+        Code::ArgumentPastCallSites,
+        Code::NonExhaustiveMatch,
+        Code::UnreachableArm,
+        Code::DuplicateAlternative,
+        Code::NonExhaustiveBooleanMatch,
+        Code::DivisionByZero,
+        Code::StackOverflow,
+        Code::IoError,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::InvalidSyntax => "E0001",
+            Code::InvalidArgumentList => "E0002",
+            Code::InvalidBlockReference => "E0003",
+            Code::InvalidMatch => "E0004",
+            Code::UnknownType => "E0005",
+            Code::InvalidParameter => "E0006",
+            Code::InvalidSuppression => "E0007",
+            Code::ContentOutsideBlock => "W0001",
+            Code::FenceOrder => "W0002",
+            Code::SplitInstructionList => "W0003",
+            Code::UndefinedBlock => "E0101",
+            Code::UndefinedVariable => "E0102",
+            Code::ImportFailed => "E0103",
+            Code::RemoteImport => "E0104",
+            Code::NoEntryPoint => "E0105",
+            Code::SameFenceReadWrite => "W0201",
+            Code::SameFenceWriteWrite => "W0202",
+            Code::ReadBeforeAssignment => "W0203",
+            Code::NonLexicalRead => "W0204",
+            Code::TypeError => "E0301",
+            Code::AnnotationMismatch => "E0302",
+            Code::StaticTypeMismatch => "W0301",
+            Code::ConflictingAnnotations => "W0302",
+            Code::ArityMismatch => "E0401",
+            Code::UnknownNamedArgument => "E0402",
+            Code::DuplicateArgument => "E0403",
+            Code::MissingArgument => "E0404",
+            Code::ArgumentOutOfBounds => "E0405",
+            Code::UndefinedNamedArgument => "E0406",
+            Code::ArgumentPastParameters => "W0401",
+            Code::ArgumentPastCallSites => "W0402",
+            Code::NonExhaustiveMatch => "E0501",
+            Code::UnreachableArm => "W0501",
+            Code::DuplicateAlternative => "W0502",
+            Code::NonExhaustiveBooleanMatch => "W0503",
+            Code::DivisionByZero => "E0601",
+            Code::StackOverflow => "E0602",
+            Code::IoError => "E0603",
+        }
+    }
+
+    /// Look up a code by its identifier, ignoring case (`w0201` finds `W0201`).
+    pub fn lookup(code: &str) -> Option<Code> {
+        Code::ALL
+            .into_iter()
+            .find(|c| c.as_str().eq_ignore_ascii_case(code))
+    }
+
+    /// Whether diagnostics with this code are warnings rather than errors.
+    pub fn is_warning(&self) -> bool {
+        self.as_str().starts_with('W')
+    }
+
+    /// One-line description, shown by `mdl explain` listings.
+    pub fn summary(&self) -> &'static str {
+        match self {
+            Code::InvalidSyntax => "invalid expression syntax",
+            Code::InvalidArgumentList => "malformed argument list",
+            Code::InvalidBlockReference => "malformed block reference",
+            Code::InvalidMatch => "malformed match expression",
+            Code::UnknownType => "unknown type name",
+            Code::InvalidParameter => "invalid parameter declaration",
+            Code::InvalidSuppression => "malformed `mdl-allow` comment",
+            Code::ContentOutsideBlock => "content before the first heading is ignored",
+            Code::FenceOrder => "instruction numbers out of order",
+            Code::SplitInstructionList => "instruction list split by other content",
+            Code::UndefinedBlock => "undefined block",
+            Code::UndefinedVariable => "undefined variable",
+            Code::ImportFailed => "cannot load local import",
+            Code::RemoteImport => "remote imports are not supported",
+            Code::NoEntryPoint => "no entry point",
+            Code::SameFenceReadWrite => "variable read at the same fence as its assignment",
+            Code::SameFenceWriteWrite => "variable assigned twice at the same fence",
+            Code::ReadBeforeAssignment => "variable read before assignment",
+            Code::NonLexicalRead => "variable read from a non-lexical scope",
+            Code::TypeError => "operand of the wrong type",
+            Code::AnnotationMismatch => "value does not match its type annotation",
+            Code::StaticTypeMismatch => "value can never match its type annotation",
+            Code::ConflictingAnnotations => "conflicting type annotations",
+            Code::ArityMismatch => "wrong number of arguments",
+            Code::UnknownNamedArgument => "named argument matches no parameter",
+            Code::DuplicateArgument => "parameter given both positionally and by name",
+            Code::MissingArgument => "required parameter not given",
+            Code::ArgumentOutOfBounds => "positional argument index out of bounds",
+            Code::UndefinedNamedArgument => "named argument not given",
+            Code::ArgumentPastParameters => "`#n` reads past the declared parameters",
+            Code::ArgumentPastCallSites => "`#n` reads past the arguments of every call site",
+            Code::NonExhaustiveMatch => "no match arm matched",
+            Code::UnreachableArm => "unreachable match arm",
+            Code::DuplicateAlternative => "duplicate alternative in pattern",
+            Code::NonExhaustiveBooleanMatch => "boolean match does not cover both values",
+            Code::DivisionByZero => "division by zero",
+            Code::StackOverflow => "stack overflow",
+            Code::IoError => "I/O error",
+        }
+    }
+
+    /// Long-form explanation with an example, printed by `mdl explain`.
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            Code::InvalidSyntax => {
+                "An instruction or `{expr}` template could not be parsed as an expression,\n\
+                 e.g. an unclosed parenthesis or string, or an operator missing an operand:\n\n\
+                 \x20   1. x = (1 + 2\n"
+            }
+            Code::InvalidArgumentList => {
+                "The arguments of an invocation are malformed: positional arguments must\n\
+                 come before named ones, and each name may be given only once:\n\n\
+                 \x20   1. [a: 1, 2](#Add)\n"
+            }
+            Code::InvalidBlockReference => {
+                "A block reference `&[](#Block)` takes no arguments and needs a static\n\
+                 destination; `&` must be followed by a link:\n\n\
+                 \x20   1. f = &[1](#Square)\n"
+            }
+            Code::InvalidMatch => {
+                "A `match` expression must be followed by its arms as an unordered list,\n\
+                 and an `otherwise` arm may only bind a plain name:\n\n\
+                 \x20   1. label = match n\n\
+                 \x20       - 0: \"zero\"\n\
+                 \x20       - otherwise: \"other\"\n"
+            }
+            Code::UnknownType => {
+                "A type annotation names a type that does not exist. Type names are\n\
+                 case-sensitive: Number, Boolean, String, Unit, Document, Strikethrough,\n\
+                 Table and Block.\n\n\
+                 \x20   1. x: Integer = 1\n"
+            }
+            Code::InvalidParameter => {
+                "A block's parameter list is invalid: a name is not an identifier or is\n\
+                 repeated, the block declares parameters both in its heading and in a\n\
+                 parameter table, or a required parameter follows one with a default:\n\n\
+                 \x20   # Greet (greeting = \"Hello\", name)\n"
+            }
+            Code::InvalidSuppression => {
+                "An `<!-- mdl-allow: ... -->` comment names a code that does not exist or\n\
+                 is not a warning (errors cannot be silenced), or it is not directly\n\
+                 followed by the instruction or heading it applies to:\n\n\
+                 \x20   <!-- mdl-allow: E0102 -->\n\
+                 \x20   1. **{missing}**\n"
+            }
+            Code::ContentOutsideBlock => {
+                "Everything in a program belongs to the block of the heading above it.\n\
+                 Instructions and text before the first heading belong to no block, so\n\
+                 they never run:\n\n\
+                 \x20   1. **{\"lost\"}**\n\
+                 \x20   # Main\n"
+            }
+            Code::FenceOrder => {
+                "Instructions run in source order, and adjacent items with the same number\n\
+                 form one fence group. A number lower than the one before it does not move\n\
+                 the instruction earlier, and repeating an earlier number does not join\n\
+                 that fence:\n\n\
+                 \x20   1. x = 1\n\
+                 \x20   2. **{x}**\n\
+                 \x20   1. y = 2      <- runs last, in a fence of its own\n"
+            }
+            Code::SplitInstructionList => {
+                "A block's instructions are split into several ordered lists by other\n\
+                 content. The lists run as one chain in source order, and a fence group\n\
+                 never continues from one list into the next. Keep a block's instructions\n\
+                 in a single list:\n\n\
+                 \x20   # Main\n\
+                 \x20   1. x = 1\n\n\
+                 \x20   Some text.\n\n\
+                 \x20   2. **{x}**\n"
+            }
+            Code::UndefinedBlock => {
+                "An invocation names a block that does not exist, in this file or in the\n\
+                 imported file. Block names are case-sensitive, except for the entry\n\
+                 block chosen with `--entry`.\n\n\
+                 \x20   1. [](#Helpr)\n"
+            }
+            Code::UndefinedVariable => {
+                "A variable is read that is not assigned (nor a parameter) in the current\n\
+                 block or any enclosing block, and is not bound by a match arm. Variables\n\
+                 of sibling blocks and callers are not visible.\n\n\
+                 \x20   1. **{totl}**\n"
+            }
+            Code::ImportFailed => {
+                "A local import `[](file#Block)` could not be read or parsed. Paths are\n\
+                 relative to the importing program; `.md` is added when there is no\n\
+                 extension.\n\n\
+                 \x20   1. [](missing#Helper)\n"
+            }
+            Code::RemoteImport => {
+                "Invoking a block from an http(s) URL is not supported yet.\n\n\
+                 \x20   1. [](https://example.com/lib.md#Helper)\n"
+            }
+            Code::NoEntryPoint => {
+                "The program has no top-level (`#`) heading, so there is no block to run.\n"
+            }
+            Code::SameFenceReadWrite => {
+                "Instructions with the same number form a fence group and run in no\n\
+                 defined order. Reading a variable that another instruction of the group\n\
+                 assigns is undefined behavior; move the read to a later fence:\n\n\
+                 \x20   1. x = 10\n\
+                 \x20   1. **{x}**      <- should be numbered 2\n"
+            }
+            Code::SameFenceWriteWrite => {
+                "Two instructions of the same fence group assign the same variable, so its\n\
+                 value afterwards depends on their (undefined) order:\n\n\
+                 \x20   1. x = 1\n\
+                 \x20   1. x = 2\n"
+            }
+            Code::ReadBeforeAssignment => {
+                "A variable is read before the instruction assigning it has run. Every\n\
+                 assigned name exists from the start of its block, but reading it early\n\
+                 is undefined behavior and yields unit.\n\n\
+                 \x20   1. **{x}**\n\
+                 \x20   2. x = 1\n"
+            }
+            Code::NonLexicalRead => {
+                "A block read a variable that exists only in a block that invoked it,\n\
+                 not in the block itself or an enclosing block. Pass the value as an\n\
+                 argument instead.\n"
+            }
+            Code::TypeError => {
+                "An operator was applied to a value of the wrong type, e.g. subtracting\n\
+                 a number from a string:\n\n\
+                 \x20   1. **{\"a\" - 1}**\n"
+            }
+            Code::AnnotationMismatch => {
+                "A value assigned to an annotated variable or parameter does not have the\n\
+                 declared type:\n\n\
+                 \x20   1. n: Number = \"one\"\n"
+            }
+            Code::StaticTypeMismatch => {
+                "The checker can tell before running that a literal value does not match\n\
+                 the annotated type of the variable or parameter it is given to. Running\n\
+                 the program reports E0302 when the value is reached.\n\n\
+                 \x20   1. [1, \"two\"](#Add)   <- Add (a: Number, b: Number)\n"
+            }
+            Code::ConflictingAnnotations => {
+                "The same variable is annotated with two different types in one block.\n\
+                 The first annotation is the one checked at runtime.\n\n\
+                 \x20   1. x: Number = 1\n\
+                 \x20   2. x: String = \"one\"\n"
+            }
+            Code::ArityMismatch => {
+                "A block with declared parameters was invoked with too few or too many\n\
+                 arguments. Parameters with defaults may be left out.\n\n\
+                 \x20   1. [1](#Add)   <- # Add (a, b)\n"
+            }
+            Code::UnknownNamedArgument => {
+                "A named argument `name: value` does not match any declared parameter of\n\
+                 the invoked block.\n\n\
+                 \x20   1. [nmae: \"Ada\"](#Greet)   <- # Greet (name)\n"
+            }
+            Code::DuplicateArgument => {
+                "A parameter received both a positional argument and a named argument.\n\n\
+                 \x20   1. [1, a: 2](#Add)   <- # Add (a, b)\n"
+            }
+            Code::MissingArgument => {
+                "A required parameter (one without a default) received no argument,\n\
+                 positionally or by name.\n\n\
+                 \x20   1. [width: 2](#Box)   <- # Box (width, height)\n"
+            }
+            Code::ArgumentOutOfBounds => {
+                "`#n` reads the n-th positional argument (from 0), but the block received\n\
+                 fewer arguments.\n\n\
+                 \x20   1. **{#5}**\n"
+            }
+            Code::UndefinedNamedArgument => {
+                "`#name` reads a named argument that the invocation did not pass.\n\n\
+                 \x20   1. **{#color}**\n"
+            }
+            Code::ArgumentPastParameters => {
+                "`#n` reads a positional argument past the block's declared parameters,\n\
+                 which can never be passed.\n\n\
+                 \x20   # Show (x)\n\
+                 \x20   1. **{#1}**\n"
+            }
+            Code::ArgumentPastCallSites => {
+                "`#n` reads a positional argument that no call site of the block passes,\n\
+                 so it fails whenever it is reached.\n"
+            }
+            Code::NonExhaustiveMatch => {
+                "No arm of a `match` expression matched the value. Add an `otherwise` arm\n\
+                 to handle every remaining value.\n"
+            }
+            Code::UnreachableArm => {
+                "A match arm can never be selected because an earlier `_` or binding arm\n\
+                 already matches every value it would. `otherwise` is tried after every\n\
+                 other arm, so it is unreachable after such an arm wherever it is written.\n\n\
+                 \x20   1. label = match n\n\
+                 \x20       - x: \"any\"\n\
+                 \x20       - 0: \"zero\"\n"
+            }
+            Code::DuplicateAlternative => {
+                "A pattern lists the same alternative twice, e.g. `1 | 1`.\n"
+            }
+            Code::NonExhaustiveBooleanMatch => {
+                "A match on a boolean covers only one of `true` and `false`, and has no\n\
+                 `otherwise` arm.\n"
+            }
+            Code::DivisionByZero => "A division or remainder had zero as its right operand.\n",
+            Code::StackOverflow => {
+                "Block invocations nested too deeply, usually because of recursion without\n\
+                 a base case.\n"
+            }
+            Code::IoError => "Writing program output failed.\n",
+        }
+    }
+}
+
+/// Message and note of a [`Code::SameFenceReadWrite`] conflict on `variable`
+/// in fence `fence`. The analyzer and the interpreter report it alike.
+pub fn same_fence_read_write(variable: &str, fence: u64) -> (String, String) {
+    (
+        format!("reading variable '{}' at the same fence as its assignment (undefined behavior)", variable),
+        format!("instructions numbered {} may run in any order; move the read to a later fence", fence),
+    )
+}
+
+/// Message and note of a [`Code::SameFenceWriteWrite`] conflict on `variable`
+/// in fence `fence`. The analyzer and the interpreter report it alike.
+pub fn same_fence_write_write(variable: &str, fence: u64) -> (String, String) {
+    (
+        format!("variable '{}' is assigned more than once at the same fence (undefined behavior)", variable),
+        format!("instructions numbered {} may run in any order, so the final value is unspecified", fence),
+    )
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Codes serialize as their string form, e.g. `"W0201"`.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Code {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
@@ -0,0 +1,656 @@
+use std::fmt;
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Options, Parser as CmarkParser, Tag};
+
+use crate::analysis::walk_value;
+use crate::block::Block;
+use crate::block::reference::BlockReference;
+use crate::block::scope::{self, BlockPath};
+use crate::instruction::Instruction;
+use crate::instruction::value::Value;
+use crate::parser::Parser;
+use crate::parser::structural::{
+    is_blank_or_comments, normalize_block_name, parameter_list_start, strip_closing_hashes,
+};
+
+/// A lossless concrete syntax tree of a markdownlang source file.
+///
+/// Every byte of the source belongs to exactly one node, so printing the tree
+/// (`to_string()`) reproduces the input byte for byte. Headings and the items
+/// of top-level ordered lists are broken into their parts; everything else
+/// (paragraphs, tables, comments, blank lines) is kept verbatim as trivia.
+/// Edits made through the tree only touch the text they change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceFile {
+    /// Content before the first heading, then the top-level blocks.
+    pub nodes: Vec<Node>,
+}
+
+/// A node of the concrete syntax tree.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    /// A heading and everything up to the next heading of the same or a
+    /// higher level.
+    Block(BlockNode),
+    /// An ordered list: the instructions of the enclosing block.
+    Instructions(InstructionList),
+    /// Source text with no structure of its own, kept as written.
+    Trivia(String),
+}
+
+/// A block: its heading split into parts, followed by its contents.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockNode {
+    /// Heading level (1 for `#`).
+    pub level: u8,
+    /// Indentation, the `#` marks and the spacing before the name; only the
+    /// indentation for a setext heading.
+    pub prefix: String,
+    /// The block name as written.
+    pub name: String,
+    /// Everything after the name: the parameter list, a closing `#` sequence,
+    /// the line ending and, for a setext heading, its underline.
+    pub suffix: String,
+    /// Body contents and nested blocks, in source order.
+    pub children: Vec<Node>,
+}
+
+/// The items of one ordered list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionList {
+    pub items: Vec<Item>,
+}
+
+/// One list item: `1. x = 1`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Item {
+    /// Whitespace before the number.
+    pub indent: String,
+    /// The number as written, leading zeros included.
+    pub number: String,
+    /// `.` or `)`.
+    pub delimiter: char,
+    /// Whitespace between the delimiter and the content.
+    pub spacing: String,
+    /// The rest of the item: its first line, continuation lines and any
+    /// blank lines up to the next item, line endings included.
+    pub content: String,
+}
+
+impl SourceFile {
+    /// Parse `source` into a concrete syntax tree. Parsing never fails: text
+    /// that is not a heading or an ordered list is kept as trivia.
+    pub fn parse(source: &str) -> SourceFile {
+        let mut segments = Vec::new();
+        let mut cursor = 0;
+        for (kind, range) in top_level_elements(source) {
+            let start = line_start(source, range.start).max(cursor);
+            let end = line_end(source, range.end.max(start + 1) - 1).max(start);
+            let node = match kind {
+                Element::Heading(level) => Some(Node::Block(parse_heading(level, &source[start..end]))),
+                Element::List(items) => parse_list(source, start, end, &items).map(Node::Instructions),
+            };
+            let Some(node) = node else { continue };
+            if cursor < start {
+                segments.push(Node::Trivia(source[cursor..start].to_string()));
+            }
+            segments.push(node);
+            cursor = end;
+        }
+        if cursor < source.len() {
+            segments.push(Node::Trivia(source[cursor..].to_string()));
+        }
+        SourceFile { nodes: nest(segments) }
+    }
+
+    /// Top-level blocks, in source order.
+    pub fn blocks(&self) -> impl Iterator<Item = &BlockNode> {
+        self.nodes.iter().filter_map(|node| match node {
+            Node::Block(block) => Some(block),
+            _ => None,
+        })
+    }
+
+    /// The first block at any depth named `name` (compared like block
+    /// references: surrounding and repeated whitespace is ignored).
+    pub fn find_block(&self, name: &str) -> Option<&BlockNode> {
+        find_in(&self.nodes, &normalize_block_name(name))
+    }
+
+    /// Mutable access to the first block at any depth named `name`.
+    pub fn find_block_mut(&mut self, name: &str) -> Option<&mut BlockNode> {
+        find_in_mut(&mut self.nodes, &normalize_block_name(name))
+    }
+
+    /// Rename block `from` to `to` and rewrite the `[...](#from)` links that
+    /// invoke it. Only links in instructions (and parameter defaults) that
+    /// resolve to the renamed block change: a link meant for another block of
+    /// the same name, or one in body text or code, is left as written.
+    /// Returns `false`, changing nothing, if no block is named `from`.
+    pub fn rename_block(&mut self, from: &str, to: &str) -> bool {
+        let old = normalize_block_name(from);
+        let Some(heading) = block_offset(&self.nodes, &old, &mut 0) else {
+            return false;
+        };
+
+        let mut source = self.to_string();
+        let (program, _) = Parser::new(source.clone(), 0).parse_recovering();
+        let mut spans = Vec::new();
+        let renamed = scope::position(&program.blocks, &|block| line_start(&source, block.span.start) == heading);
+        if let Some(target) = renamed {
+            let mut references = References { blocks: &program.blocks, target: &target, name: &old, spans: &mut spans };
+            references.collect(&program.blocks, &mut Vec::new());
+        }
+        let (old_link, new_link) = (format!("](#{old})"), format!("](#{to})"));
+        // The destination is the last link in an invocation's span; the
+        // links before it belong to its arguments
+        let mut starts: Vec<usize> =
+            spans.iter().filter_map(|span| Some(span.start + source.get(span.clone())?.rfind(&old_link)?)).collect();
+        starts.sort_unstable();
+        starts.dedup();
+        for start in starts.into_iter().rev() {
+            source.replace_range(start..start + old_link.len(), &new_link);
+        }
+
+        *self = SourceFile::parse(&source);
+        if let Some(block) = self.find_block_mut(&old) {
+            block.name = to.to_string();
+        }
+        true
+    }
+
+    /// Renumber the fences of every block, see [`BlockNode::renumber`].
+    /// Returns whether any number changed.
+    pub fn renumber(&mut self) -> bool {
+        renumber_all(&mut self.nodes)
+    }
+}
+
+impl BlockNode {
+    /// The block name with whitespace normalized, as blocks are referenced.
+    pub fn block_name(&self) -> String {
+        normalize_block_name(&self.name)
+    }
+
+    /// Nested blocks, in source order.
+    pub fn blocks(&self) -> impl Iterator<Item = &BlockNode> {
+        self.children.iter().filter_map(|node| match node {
+            Node::Block(block) => Some(block),
+            _ => None,
+        })
+    }
+
+    /// The block's own instructions, in source order (nested blocks excluded).
+    pub fn instructions(&self) -> impl Iterator<Item = &Item> {
+        self.children
+            .iter()
+            .filter_map(|node| match node {
+                Node::Instructions(list) => Some(list.items.iter()),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    /// Mutable access to the block's own instructions, in source order.
+    pub fn instructions_mut(&mut self) -> impl Iterator<Item = &mut Item> {
+        self.children
+            .iter_mut()
+            .filter_map(|node| match node {
+                Node::Instructions(list) => Some(list.items.iter_mut()),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    /// Append an instruction in a new fence after the block's last one.
+    /// `content` is the instruction without its number, e.g. `**{x}**`.
+    pub fn push_instruction(&mut self, content: &str) {
+        let next = self.instructions().last().map_or(1, |item| item.index().saturating_add(1));
+        self.append_instruction(next, content);
+    }
+
+    /// Insert an instruction as a new fence `index`, in front of the first
+    /// instruction numbered `index` or higher. Every instruction numbered
+    /// `index` or higher moves up by one, so existing fences stay apart.
+    pub fn insert_fence(&mut self, index: u64, content: &str) {
+        let mut position = None;
+        for (child, node) in self.children.iter_mut().enumerate() {
+            let Node::Instructions(list) = node else { continue };
+            for (at, item) in list.items.iter_mut().enumerate() {
+                if item.index() >= index {
+                    position.get_or_insert((child, at));
+                    item.set_index(item.index().saturating_add(1));
+                }
+            }
+        }
+        let Some((mut child, mut at)) = position else {
+            self.append_instruction(index, content);
+            return;
+        };
+        let Node::Instructions(list) = &self.children[child] else {
+            unreachable!("position points at an instruction list");
+        };
+        let next = &list.items[at];
+        let item = Item {
+            indent: next.indent.clone(),
+            number: index.to_string(),
+            delimiter: next.delimiter,
+            spacing: next.spacing.clone(),
+            content: format!("{content}\n"),
+        };
+        // Stay clear of comments in front of the list: an `mdl-allow`
+        // comment belongs to the instruction after it
+        if at == 0
+            && child >= 2
+            && matches!(&self.children[child - 1], Node::Trivia(text) if is_blank_or_comments(text))
+            && matches!(&self.children[child - 2], Node::Instructions(previous) if !previous.items.is_empty())
+        {
+            child -= 2;
+            let Node::Instructions(previous) = &self.children[child] else {
+                unreachable!("checked to be an instruction list");
+            };
+            at = previous.items.len();
+        }
+        let Node::Instructions(list) = &mut self.children[child] else {
+            unreachable!("position points at an instruction list");
+        };
+        if at > 0 && !list.items[at - 1].content.ends_with('\n') {
+            list.items[at - 1].content.push('\n');
+        }
+        list.items.insert(at, item);
+        self.separate_lists();
+    }
+
+    /// Number the block's fences 1, 2, 3... in source order, keeping the
+    /// instructions that share a fence together. Returns whether any number
+    /// changed.
+    pub fn renumber(&mut self) -> bool {
+        let groups = self.fence_groups();
+        let mut changed = false;
+        for (item, group) in self.instructions_mut().zip(groups) {
+            let index = group as u64 + 1;
+            changed |= item.number != index.to_string();
+            item.set_index(index);
+        }
+        self.separate_lists();
+        changed
+    }
+
+    /// Put a blank line between paragraph text and a list after it that no
+    /// longer starts at 1: only such a list can interrupt a paragraph, any
+    /// other would be read as more paragraph text.
+    fn separate_lists(&mut self) {
+        for at in 1..self.children.len() {
+            let starts_at_one = match &self.children[at] {
+                Node::Instructions(list) => list.items.first().is_none_or(|item| item.index() == 1),
+                _ => true,
+            };
+            if let (false, Node::Trivia(text)) = (starts_at_one, &mut self.children[at - 1]) {
+                let last_line = text.trim_end_matches('\n').rsplit('\n').next().unwrap_or("");
+                if !text.ends_with("\n\n") && !is_blank_or_comments(last_line) {
+                    text.push('\n');
+                }
+            }
+        }
+    }
+
+    /// The fence of each of the block's instructions, counted from 0 in
+    /// source order. As in the parser, an instruction shares the previous
+    /// one's fence when it has the same number and nothing but blank lines
+    /// and comments separates them.
+    fn fence_groups(&self) -> Vec<usize> {
+        let mut groups: Vec<usize> = Vec::new();
+        let mut previous = None;
+        for node in &self.children {
+            match node {
+                Node::Instructions(list) => {
+                    for item in &list.items {
+                        let group = match groups.last() {
+                            Some(&last) if previous == Some(item.index()) => last,
+                            Some(&last) => last + 1,
+                            None => 0,
+                        };
+                        groups.push(group);
+                        previous = Some(item.index());
+                    }
+                }
+                Node::Trivia(text) if !is_blank_or_comments(text) => previous = None,
+                _ => {}
+            }
+        }
+        groups
+    }
+
+    /// Append an instruction numbered `number`. The new item copies the
+    /// indentation and delimiter of the previous one; a block without
+    /// instructions gets a new list after its body.
+    fn append_instruction(&mut self, number: u64, content: &str) {
+        let last_list = self.children.iter_mut().rev().find_map(|node| match node {
+            Node::Instructions(list) if !list.items.is_empty() => Some(list),
+            _ => None,
+        });
+        if let Some(list) = last_list {
+            let last = list.items.last_mut().expect("list is not empty");
+            if !last.content.ends_with('\n') {
+                last.content.push('\n');
+            }
+            let item = Item {
+                indent: last.indent.clone(),
+                number: number.to_string(),
+                delimiter: last.delimiter,
+                spacing: last.spacing.clone(),
+                content: format!("{content}\n"),
+            };
+            list.items.push(item);
+            return;
+        }
+
+        // Own content ends where the first nested block starts.
+        let at = self.children.iter().position(|node| matches!(node, Node::Block(_))).unwrap_or(self.children.len());
+        let mut separator = String::new();
+        match self.children[..at].last_mut() {
+            Some(Node::Trivia(text)) => {
+                if !text.ends_with('\n') {
+                    text.push('\n');
+                }
+                if !text.trim().is_empty() && !text.ends_with("\n\n") {
+                    separator.push('\n');
+                }
+            }
+            None if !self.suffix.ends_with('\n') => self.suffix.push('\n'),
+            _ => {}
+        }
+        let item = Item {
+            indent: String::new(),
+            number: number.to_string(),
+            delimiter: '.',
+            spacing: " ".to_string(),
+            content: format!("{content}\n"),
+        };
+        let list = Node::Instructions(InstructionList { items: vec![item] });
+        if separator.is_empty() {
+            self.children.insert(at, list);
+        } else {
+            self.children.splice(at..at, [Node::Trivia(separator), list]);
+        }
+    }
+}
+
+impl Item {
+    /// The fence index this item belongs to.
+    pub fn index(&self) -> u64 {
+        self.number.parse().unwrap_or(u64::MAX)
+    }
+
+    /// Change the item's number, moving it to fence `index`.
+    pub fn set_index(&mut self, index: u64) {
+        self.number = index.to_string();
+    }
+}
+
+impl fmt::Display for SourceFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.nodes.iter().try_for_each(|node| write!(f, "{node}"))
+    }
+}
+
+impl fmt::Display for Node {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Node::Block(block) => write!(f, "{block}"),
+            Node::Instructions(list) => write!(f, "{list}"),
+            Node::Trivia(text) => f.write_str(text),
+        }
+    }
+}
+
+impl fmt::Display for BlockNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}", self.prefix, self.name, self.suffix)?;
+        self.children.iter().try_for_each(|node| write!(f, "{node}"))
+    }
+}
+
+impl fmt::Display for InstructionList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.items.iter().try_for_each(|item| write!(f, "{item}"))
+    }
+}
+
+impl fmt::Display for Item {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}{}{}{}", self.indent, self.number, self.delimiter, self.spacing, self.content)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Parsing
+// ---------------------------------------------------------------------------
+
+/// A structural element found at the top level of the Markdown document.
+enum Element {
+    Heading(u8),
+    /// An ordered list and the start offsets of its items.
+    List(Vec<usize>),
+}
+
+/// Headings and ordered lists that are not nested in another element,
+/// with their source ranges, using the same Markdown options as the parser.
+fn top_level_elements(source: &str) -> Vec<(Element, Range<usize>)> {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut elements = Vec::new();
+    let mut depth = 0usize;
+    for (event, range) in CmarkParser::new_ext(source, options).into_offset_iter() {
+        match event {
+            Event::Start(tag) => {
+                match tag {
+                    Tag::Heading { level, .. } if depth == 0 => {
+                        elements.push((Element::Heading(level as u8), range));
+                    }
+                    Tag::List(Some(_)) if depth == 0 => elements.push((Element::List(Vec::new()), range)),
+                    Tag::Item if depth == 1 => {
+                        if let Some((Element::List(items), _)) = elements.last_mut() {
+                            items.push(range.start);
+                        }
+                    }
+                    _ => {}
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+    }
+    elements
+}
+
+/// Split the source of a heading into its parts.
+fn parse_heading(level: u8, text: &str) -> BlockNode {
+    let line = text.split('\n').next().unwrap_or("");
+    let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+    let after_indent = &line[indent..];
+    let (prefix_len, content) = if after_indent.starts_with('#') {
+        let hashes = after_indent.len() - after_indent.trim_start_matches('#').len();
+        let rest = &after_indent[hashes..];
+        let spacing = rest.len() - rest.trim_start_matches([' ', '\t']).len();
+        let prefix_len = indent + hashes + spacing;
+        (prefix_len, strip_closing_hashes(&line[prefix_len..]))
+    } else {
+        (indent, after_indent.trim_end())
+    };
+    let name_len = parameter_list_start(content).map_or(content.len(), |open| content[..open].trim_end().len());
+    BlockNode {
+        level,
+        prefix: text[..prefix_len].to_string(),
+        name: text[prefix_len..prefix_len + name_len].to_string(),
+        suffix: text[prefix_len + name_len..].to_string(),
+        children: Vec::new(),
+    }
+}
+
+/// Split the source of an ordered list (`source[start..end]`) into items.
+/// Returns `None` if an item does not start with a number where expected,
+/// in which case the list is kept as trivia.
+fn parse_list(source: &str, start: usize, end: usize, item_starts: &[usize]) -> Option<InstructionList> {
+    let mut bounds: Vec<usize> = item_starts.iter().map(|&offset| line_start(source, offset).max(start)).collect();
+    if bounds.first() != Some(&start) || bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+        return None;
+    }
+    bounds.push(end);
+    let items = bounds.windows(2).map(|pair| parse_item(&source[pair[0]..pair[1]])).collect::<Option<Vec<_>>>()?;
+    Some(InstructionList { items })
+}
+
+/// Split the source of one list item into its parts.
+fn parse_item(text: &str) -> Option<Item> {
+    let indent = text.len() - text.trim_start_matches([' ', '\t']).len();
+    let rest = &text[indent..];
+    let digits = rest.len() - rest.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+    let delimiter = rest[digits..].chars().next().filter(|c| matches!(c, '.' | ')'))?;
+    if digits == 0 {
+        return None;
+    }
+    let after = &rest[digits + 1..];
+    let spacing = after.len() - after.trim_start_matches([' ', '\t']).len();
+    Some(Item {
+        indent: text[..indent].to_string(),
+        number: rest[..digits].to_string(),
+        delimiter,
+        spacing: after[..spacing].to_string(),
+        content: after[spacing..].to_string(),
+    })
+}
+
+/// Turn the flat sequence of headings and content into nested blocks: a
+/// block holds everything up to the next heading of the same or a higher level.
+fn nest(segments: Vec<Node>) -> Vec<Node> {
+    let mut root = Vec::new();
+    let mut open: Vec<BlockNode> = Vec::new();
+    for segment in segments {
+        if let Node::Block(block) = segment {
+            while open.last().is_some_and(|top| top.level >= block.level) {
+                close_block(&mut open, &mut root);
+            }
+            open.push(block);
+        } else {
+            match open.last_mut() {
+                Some(top) => top.children.push(segment),
+                None => root.push(segment),
+            }
+        }
+    }
+    while !open.is_empty() {
+        close_block(&mut open, &mut root);
+    }
+    root
+}
+
+fn close_block(open: &mut Vec<BlockNode>, root: &mut Vec<Node>) {
+    let block = Node::Block(open.pop().expect("a block is open"));
+    match open.last_mut() {
+        Some(parent) => parent.children.push(block),
+        None => root.push(block),
+    }
+}
+
+fn renumber_all(nodes: &mut [Node]) -> bool {
+    let mut changed = false;
+    for node in nodes {
+        if let Node::Block(block) = node {
+            changed |= block.renumber();
+            changed |= renumber_all(&mut block.children);
+        }
+    }
+    changed
+}
+
+fn find_in<'a>(nodes: &'a [Node], name: &str) -> Option<&'a BlockNode> {
+    nodes.iter().find_map(|node| match node {
+        Node::Block(block) if block.block_name() == name => Some(block),
+        Node::Block(block) => find_in(&block.children, name),
+        _ => None,
+    })
+}
+
+fn find_in_mut<'a>(nodes: &'a mut [Node], name: &str) -> Option<&'a mut BlockNode> {
+    for node in nodes {
+        if let Node::Block(block) = node {
+            if block.block_name() == name {
+                return Some(block);
+            }
+            if let Some(found) = find_in_mut(&mut block.children, name) {
+                return Some(found);
+            }
+        }
+    }
+    None
+}
+
+/// Source offset of the heading of the first block named `name`, counting
+/// from `offset` for the text before `nodes`.
+fn block_offset(nodes: &[Node], name: &str, offset: &mut usize) -> Option<usize> {
+    for node in nodes {
+        if let Node::Block(block) = node {
+            if block.block_name() == name {
+                return Some(*offset);
+            }
+            *offset += block.prefix.len() + block.name.len() + block.suffix.len();
+            if let Some(found) = block_offset(&block.children, name, offset) {
+                return Some(found);
+            }
+        } else {
+            *offset += node.to_string().len();
+        }
+    }
+    None
+}
+
+/// Collects the spans of the local references to the block at `target`.
+struct References<'a> {
+    blocks: &'a [Block],
+    target: &'a [usize],
+    name: &'a str,
+    spans: &'a mut Vec<Range<usize>>,
+}
+
+impl References<'_> {
+    /// Collect from `blocks`, the blocks inside the scope at `path`.
+    fn collect(&mut self, blocks: &[Block], path: &mut BlockPath) {
+        for (index, block) in blocks.iter().enumerate() {
+            path.push(index);
+            let defaults = block.parameters.iter().flatten().filter_map(|parameter| parameter.default.as_ref());
+            let instructions = block.chain.groups.iter().flat_map(|group| &group.instructions).map(|instruction| {
+                let (Instruction::Assignment { value, .. } | Instruction::Expression { value, .. }) = instruction;
+                value
+            });
+            for value in defaults.chain(instructions) {
+                walk_value(value, &mut |value| {
+                    let (Value::BlockInvocation(_, BlockReference::Local(name), span)
+                    | Value::EvaluatedBlockInvocation(_, BlockReference::Local(name), span)
+                    | Value::BlockRef(BlockReference::Local(name), span)) = value
+                    else {
+                        return;
+                    };
+                    let resolved = || scope::resolve_local(self.blocks, path, name);
+                    if name == self.name && resolved().as_deref() == Some(self.target) {
+                        self.spans.push(span.clone());
+                    }
+                });
+            }
+            self.collect(&block.children, path);
+            path.pop();
+        }
+    }
+}
+
+/// Offset of the start of the line containing `offset`.
+pub(crate) fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |nl| nl + 1)
+}
+
+/// Offset just past the line ending of the line containing `offset`.
+pub(crate) fn line_end(source: &str, offset: usize) -> usize {
+    let offset = offset.min(source.len());
+    source[offset..].find('\n').map_or(source.len(), |nl| offset + nl + 1)
+}
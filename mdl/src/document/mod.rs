@@ -3,6 +3,7 @@ use std::fmt;
 /// A Document is a sequence of document nodes representing Markdown content.
 /// This is the first-class Markdown AST type in markdownlang.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Document {
     pub nodes: Vec<DocumentNode>,
 }
@@ -19,6 +20,7 @@ impl Document {
 
 /// A single node in the Markdown AST.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum DocumentNode {
     // Block-level
     Paragraph(Vec<InlineNode>),
@@ -51,6 +53,7 @@ pub enum DocumentNode {
 /// Inline elements that appear within a line of text.
 /// Inline types nest freely within one another.
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum InlineNode {
     Text(String),
     Strong(Vec<InlineNode>),
@@ -72,6 +75,7 @@ pub enum InlineNode {
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ColumnAlignment {
     None,
     Left,
@@ -0,0 +1,541 @@
+use pulldown_cmark::{Alignment, Event, Options, Parser as CmarkParser, Tag, TagEnd};
+
+use crate::cst::{BlockNode, Item, Node, SourceFile, line_end, line_start};
+use crate::parser::Parser;
+use crate::parser::structural::strip_closing_hashes;
+
+/// Rewrite a markdownlang source file in the canonical style:
+///
+/// - headings as `# Name (a, b = 1)`, without a closing `#` sequence;
+/// - instructions as `1. x = a + b`: no indentation, `.` after the number,
+///   one space around binary operators, none inside parentheses and
+///   template braces (`**{x + 1}**`), `, ` between arguments;
+/// - match arms as `- pattern: result`, indented under their instruction;
+/// - tables with a pipe at both ends of every row and aligned columns;
+/// - exactly one line ending at the end of the file.
+///
+/// Fence numbers are written without leading zeros but otherwise kept, so
+/// fence grouping never changes. Every rewrite is checked by parsing the
+/// result: one that would change the parsed program (e.g. spacing out
+/// `a*b*c`, which Markdown reads as emphasis) is left out, as is any text
+/// the formatter does not understand.
+pub fn format_source(source: &str) -> String {
+    let meaning = fingerprint(source);
+    let preserves = |candidate: &str| fingerprint(candidate) == meaning;
+
+    let with_tables = format_tables(source);
+    let text = if preserves(&with_tables) { with_tables } else { source.to_string() };
+
+    let file = SourceFile::parse(&text);
+    let mut formatted = format_units(&file, &|_| true);
+    if !preserves(&formatted.to_string()) {
+        // Find the rewrites that are safe one by one
+        let mut accepted = Vec::new();
+        for unit in 0..count_units(&file.nodes) {
+            accepted.push(unit);
+            if !preserves(&format_units(&file, &|u| accepted.contains(&u)).to_string()) {
+                accepted.pop();
+            }
+        }
+        formatted = format_units(&file, &|u| accepted.contains(&u));
+    }
+
+    let mut text = formatted.to_string();
+    let content_len = text.trim_end().len();
+    text.truncate(content_len);
+    if !text.is_empty() {
+        text.push('\n');
+    }
+    text
+}
+
+/// What the parser makes of `source`, spans left out: two sources with the
+/// same fingerprint are the same program.
+fn fingerprint(source: &str) -> String {
+    let (program, diagnostics) = Parser::new(source.to_string(), 0).parse_recovering();
+    let messages: Vec<_> = diagnostics.iter().map(|d| (d.code, &d.message)).collect();
+    strip_ranges(&format!("{:?}{:?}{:?}", program.blocks, program.suppressions, messages))
+}
+
+/// Remove `start..end` ranges from Debug output.
+fn strip_ranges(debug: &str) -> String {
+    let bytes = debug.as_bytes();
+    let mut out = String::with_capacity(debug.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let digits = |from: usize| bytes[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+        let start_len = digits(i);
+        if start_len > 0 && bytes[i + start_len..].starts_with(b"..") {
+            let end_len = digits(i + start_len + 2);
+            if end_len > 0 {
+                i += start_len + 2 + end_len;
+                continue;
+            }
+        }
+        let c = debug[i..].chars().next().expect("in bounds");
+        out.push(c);
+        i += c.len_utf8();
+    }
+    out
+}
+
+// ---------------------------------------------------------------------------
+// Headings and instructions
+// ---------------------------------------------------------------------------
+
+/// Number of headings and instructions in `nodes`: the units the formatter
+/// rewrites, numbered in source order.
+fn count_units(nodes: &[Node]) -> usize {
+    nodes
+        .iter()
+        .map(|node| match node {
+            Node::Block(block) => 1 + count_units(&block.children),
+            Node::Instructions(list) => list.items.len(),
+            Node::Trivia(_) => 0,
+        })
+        .sum()
+}
+
+/// A copy of `file` with the units selected by `include` formatted.
+fn format_units(file: &SourceFile, include: &dyn Fn(usize) -> bool) -> SourceFile {
+    let mut file = file.clone();
+    let mut unit = 0;
+    format_nodes(&mut file.nodes, &mut unit, include);
+    file
+}
+
+fn format_nodes(nodes: &mut [Node], unit: &mut usize, include: &dyn Fn(usize) -> bool) {
+    for node in nodes {
+        match node {
+            Node::Block(block) => {
+                if include(*unit) {
+                    format_heading(block);
+                }
+                *unit += 1;
+                format_nodes(&mut block.children, unit, include);
+            }
+            Node::Instructions(list) => {
+                for item in &mut list.items {
+                    if include(*unit) {
+                        format_item(item);
+                    }
+                    *unit += 1;
+                }
+            }
+            Node::Trivia(_) => {}
+        }
+    }
+}
+
+/// `#  Add(a,b=1) ##` → `# Add (a, b = 1)`. Setext headings are kept.
+fn format_heading(block: &mut BlockNode) {
+    if !block.prefix.contains('#') || block.name.trim().is_empty() {
+        return;
+    }
+    let (line, ending, rest) = split_line(&block.suffix);
+    let params = strip_closing_hashes(line).trim();
+    let params = match params {
+        "" => String::new(),
+        params => format!(" {}", format_expression(params).unwrap_or_else(|| params.to_string())),
+    };
+    block.suffix = format!("{params}{ending}{rest}");
+    block.prefix = format!("{} ", "#".repeat(block.level as usize));
+    block.name = block.name.split_whitespace().collect::<Vec<_>>().join(" ");
+}
+
+/// Format one list item: its marker, its first line and its match arms.
+/// Other continuation lines are kept as written.
+fn format_item(item: &mut Item) {
+    let index = item.index();
+    if index != u64::MAX {
+        item.set_index(index);
+    }
+    item.indent.clear();
+    item.delimiter = '.';
+
+    let (first, ending, rest) = split_line(&item.content);
+    let first = first.trim_end();
+    item.spacing = if first.is_empty() { String::new() } else { " ".to_string() };
+    let mut content = format_expression(first).unwrap_or_else(|| first.to_string());
+    content.push_str(ending);
+
+    // Arms nest under the item's content, and at least four columns deep
+    let arm_column = (item.number.len() + 2).max(4);
+    let mut arm_base = None;
+    let mut rest = rest;
+    while !rest.is_empty() {
+        let (line, ending, next) = split_line(rest);
+        rest = next;
+        let Some((indent, text)) = match_arm(line) else {
+            content.push_str(line);
+            content.push_str(ending);
+            continue;
+        };
+        let depth = indent.saturating_sub(*arm_base.get_or_insert(indent));
+        let text = text.trim_end();
+        let text = format_expression(text).unwrap_or_else(|| text.to_string());
+        let marker = if text.is_empty() { "-" } else { "- " };
+        content.push_str(&format!("{}{}{}{}", " ".repeat(arm_column + depth), marker, text, ending));
+    }
+    item.content = content;
+}
+
+/// The indentation and text of a `- pattern: result` line.
+fn match_arm(line: &str) -> Option<(usize, &str)> {
+    let text = line.trim_start_matches(' ');
+    let indent = line.len() - text.len();
+    let rest = text.strip_prefix(['-', '*', '+'])?;
+    if indent == 0 {
+        return None;
+    }
+    match rest.strip_prefix([' ', '\t']) {
+        Some(rest) => Some((indent, rest.trim_start())),
+        None if rest.trim().is_empty() => Some((indent, "")),
+        None => None,
+    }
+}
+
+/// Split off the first line of `text`: its content, its line ending and the rest.
+fn split_line(text: &str) -> (&str, &str, &str) {
+    let (line, rest) = match text.find('\n') {
+        Some(nl) => (&text[..=nl], &text[nl + 1..]),
+        None => (text, ""),
+    };
+    let content = line.trim_end_matches(['\n', '\r']);
+    (content, &line[content.len()..], rest)
+}
+
+// ---------------------------------------------------------------------------
+// Expressions
+// ---------------------------------------------------------------------------
+
+/// The role of a piece of an expression, which decides the spacing around it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// Literals, names, templates, invocations.
+    Operand,
+    /// `match`.
+    Keyword,
+    /// A binary operator.
+    Operator,
+    /// `!`, or `-` and `&` (block reference) where no operand precedes them.
+    Unary,
+    Question,
+    /// The `:` of `c ? a : b`.
+    Else,
+    /// Any other `:`: named arguments, match arms, type annotations.
+    Colon,
+    Comma,
+    Open,
+    Close,
+}
+
+/// Format an expression: an instruction, a match arm, a parameter list or
+/// the inside of template braces. Returns `None` for text it does not
+/// understand, which is then kept as written.
+fn format_expression(text: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut prev: Option<Kind> = None;
+    // Open `?` per nesting level, to tell `c ? a : b` from `name: value`
+    let mut questions = vec![0usize];
+    let mut i = 0;
+    let mut spaced = false;
+    while i < text.len() {
+        let rest = &text[i..];
+        let c = rest.chars().next().expect("in bounds");
+        if c.is_whitespace() {
+            spaced = true;
+            i += c.len_utf8();
+            continue;
+        }
+        let (piece, mut kind) = lex_piece(rest)?;
+        let piece_len = piece.len();
+        let piece = match kind {
+            Kind::Operand if piece.starts_with("**") || piece.starts_with("~~") => format_template(piece),
+            Kind::Operand if piece.starts_with('[') || piece.starts_with("![") => format_link(piece),
+            _ => piece.to_string(),
+        };
+        let operand_before = matches!(prev, Some(Kind::Operand | Kind::Close));
+        match piece.as_str() {
+            "-" | "&" if !operand_before => kind = Kind::Unary,
+            "?" => *questions.last_mut().expect("never empty") += 1,
+            ":" => {
+                let open = questions.last_mut().expect("never empty");
+                if *open > 0 {
+                    *open -= 1;
+                    kind = Kind::Else;
+                }
+            }
+            _ => {}
+        }
+        match kind {
+            Kind::Open => questions.push(0),
+            Kind::Close if questions.len() > 1 => {
+                questions.pop();
+            }
+            _ => {}
+        }
+        if let Some(prev) = prev
+            && space_between(prev, kind, spaced)
+        {
+            out.push(' ');
+        }
+        out.push_str(&piece);
+        prev = Some(kind);
+        spaced = false;
+        i += piece_len;
+    }
+    Some(out)
+}
+
+/// Whether a space separates a piece of kind `prev` from one of kind `next`.
+/// Between operands (`otherwise v`), the source decides.
+fn space_between(prev: Kind, next: Kind, spaced: bool) -> bool {
+    use Kind::*;
+    match (prev, next) {
+        (Unary, Unary) => spaced,
+        (_, Close) | (Open, _) | (_, Comma | Colon) | (Unary, _) => false,
+        (Comma | Colon, _) => true,
+        (_, Operator | Question | Else | Unary) | (Operator | Question | Else | Keyword, _) => true,
+        _ => spaced,
+    }
+}
+
+/// The next piece of an expression and its kind, or `None` for text the
+/// formatter does not understand.
+fn lex_piece(text: &str) -> Option<(&str, Kind)> {
+    let c = text.chars().next()?;
+    let piece = |len: usize, kind| Some((&text[..len], kind));
+    match c {
+        '"' => piece(text[1..].find('"')? + 2, Kind::Operand),
+        '`' => {
+            let ticks = text.len() - text.trim_start_matches('`').len();
+            let fence = &text[..ticks];
+            piece(ticks + text[ticks..].find(fence)? + ticks, Kind::Operand)
+        }
+        '\\' => piece(1 + text[1..].chars().next().map_or(0, char::len_utf8), Kind::Operand),
+        '0'..='9' => piece(text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len()), Kind::Operand),
+        c if c.is_ascii_alphabetic() || c == '_' => {
+            let len = text.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(text.len());
+            let kind = if &text[..len] == "match" { Kind::Keyword } else { Kind::Operand };
+            piece(len, kind)
+        }
+        '#' => {
+            let after = &text[1..];
+            let len = if after.starts_with(['*', ':']) {
+                1
+            } else {
+                after.find(|c: char| !c.is_alphanumeric() && c != '_').unwrap_or(after.len())
+            };
+            piece(1 + len, Kind::Operand)
+        }
+        '*' if text.starts_with("**") => piece(delimited_len(text, "**")?, Kind::Operand),
+        '~' if text.starts_with("~~") => piece(delimited_len(text, "~~")?, Kind::Operand),
+        '[' => piece(link_len(text, 0)?, Kind::Operand),
+        '!' if text.starts_with("![") => piece(link_len(text, 1)?, Kind::Operand),
+        '<' if text.starts_with("<!--") => piece(text.find("-->")? + 3, Kind::Operand),
+        '=' | '!' | '<' | '>' if text[1..].starts_with('=') => piece(2, Kind::Operator),
+        '&' | '|' if text[1..].starts_with(c) => piece(2, Kind::Operator),
+        '!' => piece(1, Kind::Unary),
+        '=' | '<' | '>' | '&' | '|' | '+' | '-' | '*' | '/' | '%' => piece(1, Kind::Operator),
+        '?' => piece(1, Kind::Question),
+        ':' => piece(1, Kind::Colon),
+        ',' => piece(1, Kind::Comma),
+        '(' | '{' => piece(1, Kind::Open),
+        ')' | '}' => piece(1, Kind::Close),
+        _ => None,
+    }
+}
+
+/// Length of `**...**` or `~~...~~` starting `text`, skipping over template
+/// expressions.
+fn delimited_len(text: &str, marker: &str) -> Option<usize> {
+    let mut i = marker.len();
+    while i < text.len() {
+        let rest = &text[i..];
+        if rest.starts_with(marker) {
+            return Some(i + marker.len());
+        }
+        i += match rest.chars().next()? {
+            '{' => matching_len(rest, '{', '}')?,
+            '\\' => 1 + rest[1..].chars().next().map_or(0, char::len_utf8),
+            c => c.len_utf8(),
+        };
+    }
+    None
+}
+
+/// Length of the link `[text](dest)` at `text[skip..]`, `skip` included.
+fn link_len(text: &str, skip: usize) -> Option<usize> {
+    let label = matching_len(&text[skip..], '[', ']')?;
+    let dest = skip + label;
+    if !text[dest..].starts_with('(') {
+        return None;
+    }
+    Some(dest + matching_len(&text[dest..], '(', ')')?)
+}
+
+/// Length of the bracketed text starting `text` up to its matching `close`,
+/// skipping over strings and escapes.
+fn matching_len(text: &str, open: char, close: char) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut chars = text.char_indices();
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '"' => in_string = !in_string,
+            '\\' => {
+                chars.next();
+            }
+            c if in_string || (c != open && c != close) => {}
+            c if c == open => depth += 1,
+            _ => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(idx + 1);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Format the expressions inside `{...}` of a `**...**` or `~~...~~`
+/// template; the literal text is kept.
+fn format_template(template: &str) -> String {
+    let (marker, inner) = template.split_at(2);
+    let inner = &inner[..inner.len() - 2];
+    let mut out = String::from(marker);
+    let mut i = 0;
+    while i < inner.len() {
+        let rest = &inner[i..];
+        let c = rest.chars().next().expect("in bounds");
+        if c == '\\' {
+            let len = 1 + rest[1..].chars().next().map_or(0, char::len_utf8);
+            out.push_str(&rest[..len]);
+            i += len;
+        } else if c == '{'
+            && let Some(len) = matching_len(rest, '{', '}')
+        {
+            let expression = rest[1..len - 1].trim();
+            out.push('{');
+            out.push_str(&format_expression(expression).unwrap_or_else(|| expression.to_string()));
+            out.push('}');
+            i += len;
+        } else {
+            out.push(c);
+            i += c.len_utf8();
+        }
+    }
+    out.push_str(marker);
+    out
+}
+
+/// Format the arguments of an invocation `[a,b](#Block)`; the destination is kept.
+fn format_link(link: &str) -> String {
+    let open = link.find('[').expect("links start with a bracket");
+    let label = matching_len(&link[open..], '[', ']').expect("lexed as a link") + open;
+    let arguments = link[open + 1..label - 1].trim();
+    let arguments = format_expression(arguments).unwrap_or_else(|| arguments.to_string());
+    format!("{}{}{}", &link[..=open], arguments, &link[label - 1..])
+}
+
+// ---------------------------------------------------------------------------
+// Tables
+// ---------------------------------------------------------------------------
+
+/// Rewrite every top-level table as `| a   | b |` rows with aligned columns
+/// and a `|-----|---|` delimiter row.
+/// Tables whose rows do not all have one cell per column are kept as written.
+fn format_tables(source: &str) -> String {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
+    let mut tables = Vec::new();
+    let mut depth = 0usize;
+    for (event, range) in CmarkParser::new_ext(source, options).into_offset_iter() {
+        match event {
+            Event::Start(Tag::Table(alignments)) if depth == 0 => {
+                tables.push((range, alignments, Vec::<Vec<std::ops::Range<usize>>>::new()));
+                depth += 1;
+            }
+            Event::Start(Tag::TableHead | Tag::TableRow) if depth == 1 => {
+                if let Some((_, _, rows)) = tables.last_mut() {
+                    rows.push(Vec::new());
+                }
+                depth += 1;
+            }
+            Event::Start(Tag::TableCell) if depth == 2 => {
+                if let Some(row) = tables.last_mut().and_then(|(_, _, rows)| rows.last_mut()) {
+                    row.push(range);
+                }
+                depth += 1;
+            }
+            Event::Start(_) => depth += 1,
+            Event::End(TagEnd::Table) if depth == 1 => depth -= 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+    }
+
+    let mut out = source.to_string();
+    for (range, alignments, rows) in tables.into_iter().rev() {
+        let start = line_start(source, range.start);
+        let end = line_end(source, range.end.max(start + 1) - 1);
+        if let Some(table) = format_table(&source[start..end], source, &alignments, &rows) {
+            out.replace_range(start..end, &table);
+        }
+    }
+    out
+}
+
+fn format_table(
+    text: &str,
+    source: &str,
+    alignments: &[Alignment],
+    rows: &[Vec<std::ops::Range<usize>>],
+) -> Option<String> {
+    let columns = alignments.len();
+    if rows.iter().any(|row| row.len() != columns) || text.lines().count() != rows.len() + 1 {
+        return None;
+    }
+    let cells: Vec<Vec<&str>> = rows.iter().map(|row| row.iter().map(|cell| cell_text(&source[cell.clone()])).collect()).collect();
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| cells.iter().map(|row| row[column].chars().count()).max().unwrap_or(0).max(1))
+        .collect();
+    let ending = if text.contains("\r\n") { "\r\n" } else { "\n" };
+
+    let row_line = |row: &[&str]| {
+        let padded: Vec<String> = row.iter().zip(&widths).map(|(cell, &width)| format!("{cell:width$}")).collect();
+        format!("| {} |{ending}", padded.join(" | "))
+    };
+    let delimiter: Vec<String> = alignments
+        .iter()
+        .zip(&widths)
+        .map(|(alignment, &width)| match alignment {
+            Alignment::None => "-".repeat(width + 2),
+            Alignment::Left => format!(":{}", "-".repeat(width + 1)),
+            Alignment::Right => format!("{}:", "-".repeat(width + 1)),
+            Alignment::Center => format!(":{}:", "-".repeat(width)),
+        })
+        .collect();
+
+    let mut table = row_line(&cells[0]);
+    table.push_str(&format!("|{}|{ending}", delimiter.join("|")));
+    for row in &cells[1..] {
+        table.push_str(&row_line(row));
+    }
+    if !text.ends_with('\n') {
+        table.truncate(table.len() - ending.len());
+    }
+    Some(table)
+}
+
+/// The text of a table cell without surrounding pipes and whitespace.
+fn cell_text(cell: &str) -> &str {
+    let cell = cell.trim();
+    let cell = cell.strip_prefix('|').unwrap_or(cell);
+    let cell = match cell.strip_suffix('|') {
+        Some(stripped) if !stripped.ends_with('\\') => stripped,
+        _ => cell,
+    };
+    cell.trim()
+}
@@ -1,16 +1,21 @@
 pub mod template;
+pub mod types;
 pub mod value;
 
 use std::ops::Range;
 
+use crate::instruction::types::TypeAnnotation;
 use crate::instruction::value::Value;
 
 /// A single executable instruction parsed from an ordered list item.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Instruction {
-    /// Variable assignment: `variable = expression`
+    /// Variable assignment: `variable = expression`, optionally annotated
+    /// as `variable: Type = expression`.
     Assignment {
         variable: String,
+        annotation: Option<TypeAnnotation>,
         value: Value,
         span: Range<usize>,
     },
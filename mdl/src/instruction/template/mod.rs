@@ -1,7 +1,10 @@
 pub mod template_string;
 
+use std::fmt;
+
 /// A pattern template used in match arms.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Template {
     /// Match a specific number literal.
     NumberLiteral(f64),
@@ -26,14 +29,16 @@ pub enum Template {
 }
 
 /// Pattern for matching Markdown document structure.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum DocumentPattern {
     Inline(InlinePattern),
     Block(BlockPattern),
 }
 
 /// Pattern for matching inline Markdown elements.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum InlinePattern {
     Text(String),
     Strong(Vec<InlinePattern>),
@@ -49,7 +54,8 @@ pub enum InlinePattern {
 }
 
 /// Pattern for matching block-level Markdown elements.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BlockPattern {
     Paragraph(Vec<InlinePattern>),
     Heading {
@@ -61,3 +67,82 @@ pub enum BlockPattern {
         content: Option<String>,
     },
 }
+
+/// Source form of a pattern, as written in a match arm.
+impl fmt::Display for Template {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Template::NumberLiteral(n) => write!(f, "{}", n),
+            Template::StringLiteral(s) => write!(f, "\"{}\"", s),
+            Template::BooleanLiteral(b) => write!(f, "{}", b),
+            Template::UnitLiteral => write!(f, "()"),
+            Template::Strikethrough(None) => write!(f, "~~~~"),
+            Template::Strikethrough(Some(inner)) => write!(f, "~~{}~~", inner),
+            Template::Binding(name) => write!(f, "{}", name),
+            Template::DocumentPattern(DocumentPattern::Inline(pattern)) => write!(f, "{}", pattern),
+            Template::DocumentPattern(DocumentPattern::Block(pattern)) => write!(f, "{}", pattern),
+            Template::Compound(parts) => write_joined(f, parts, ", "),
+            Template::Wildcard => write!(f, "_"),
+            Template::Alternation(alternatives) => write_joined(f, alternatives, " | "),
+        }
+    }
+}
+
+impl fmt::Display for InlinePattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InlinePattern::Text(text) => write!(f, "{}", text),
+            InlinePattern::Strong(children) => {
+                write!(f, "**")?;
+                write_joined(f, children, "")?;
+                write!(f, "**")
+            }
+            InlinePattern::Emphasis(children) => {
+                write!(f, "*")?;
+                write_joined(f, children, "")?;
+                write!(f, "*")
+            }
+            InlinePattern::Strikethrough(children) => {
+                write!(f, "~~")?;
+                write_joined(f, children, "")?;
+                write!(f, "~~")
+            }
+            InlinePattern::CodeSpan(code) => write!(f, "`{}`", code),
+            InlinePattern::Link { dest, content } => {
+                write!(f, "[")?;
+                write_joined(f, content, "")?;
+                write!(f, "]({})", dest)
+            }
+            InlinePattern::Capture(name) => write!(f, "{{{}}}", name),
+        }
+    }
+}
+
+impl fmt::Display for BlockPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BlockPattern::Paragraph(content) => write_joined(f, content, ""),
+            BlockPattern::Heading { level, content } => {
+                write!(f, "{} ", "#".repeat(*level as usize))?;
+                write_joined(f, content, "")
+            }
+            BlockPattern::CodeBlock { language, content } => {
+                writeln!(f, "```{}", language.as_deref().unwrap_or(""))?;
+                if let Some(content) = content {
+                    write!(f, "{}", content)?;
+                }
+                write!(f, "```")
+            }
+        }
+    }
+}
+
+fn write_joined<T: fmt::Display>(f: &mut fmt::Formatter<'_>, items: &[T], separator: &str) -> fmt::Result {
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            write!(f, "{}", separator)?;
+        }
+        write!(f, "{}", item)?;
+    }
+    Ok(())
+}
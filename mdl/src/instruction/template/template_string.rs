@@ -1,13 +1,19 @@
+use std::ops::Range;
+
 use crate::instruction::value::Value;
 
 /// A string that can contain interpolated expressions.
 /// Used in Print (**{expr}**), Interpolation, and Strikethrough (~~expr~~).
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct TemplateString {
     pub parts: Vec<TemplateStringPart>,
+    /// Byte span of the whole template, including its Markdown delimiters.
+    pub span: Range<usize>,
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum TemplateStringPart {
     /// Literal text content.
     Literal(String),
@@ -16,15 +22,18 @@ pub enum TemplateStringPart {
 }
 
 impl TemplateString {
-    pub fn literal(s: impl Into<String>) -> Self {
+    pub fn literal(s: impl Into<String>, span: Range<usize>) -> Self {
         TemplateString {
             parts: vec![TemplateStringPart::Literal(s.into())],
+            span,
         }
     }
 
     pub fn single_expression(v: Value) -> Self {
+        let span = v.span();
         TemplateString {
             parts: vec![TemplateStringPart::Expression(v)],
+            span,
         }
     }
 }
@@ -0,0 +1,60 @@
+use std::fmt;
+
+/// A runtime type named in an annotation, e.g. `a: Number` in a parameter list
+/// or `x: String = ...` in an assignment. Names match the interpreter's
+/// runtime type names.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum TypeAnnotation {
+    Number,
+    Boolean,
+    String,
+    Unit,
+    Document,
+    Strikethrough,
+    Table,
+    Block,
+}
+
+impl TypeAnnotation {
+    pub const ALL: [TypeAnnotation; 8] = [
+        TypeAnnotation::Number,
+        TypeAnnotation::Boolean,
+        TypeAnnotation::String,
+        TypeAnnotation::Unit,
+        TypeAnnotation::Document,
+        TypeAnnotation::Strikethrough,
+        TypeAnnotation::Table,
+        TypeAnnotation::Block,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TypeAnnotation::Number => "Number",
+            TypeAnnotation::Boolean => "Boolean",
+            TypeAnnotation::String => "String",
+            TypeAnnotation::Unit => "Unit",
+            TypeAnnotation::Document => "Document",
+            TypeAnnotation::Strikethrough => "Strikethrough",
+            TypeAnnotation::Table => "Table",
+            TypeAnnotation::Block => "Block",
+        }
+    }
+
+    /// Look up a type by its (case-sensitive) name.
+    pub fn from_name(name: &str) -> Option<TypeAnnotation> {
+        TypeAnnotation::ALL.into_iter().find(|t| t.name() == name)
+    }
+
+    /// Note listing the valid type names, for unknown-type errors.
+    pub fn known_types_note() -> String {
+        let names: Vec<&str> = TypeAnnotation::ALL.iter().map(|t| t.name()).collect();
+        format!("known types are: {}", names.join(", "))
+    }
+}
+
+impl fmt::Display for TypeAnnotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
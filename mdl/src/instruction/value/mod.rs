@@ -1,3 +1,4 @@
+use std::fmt;
 use std::ops::Range;
 
 use crate::{
@@ -6,6 +7,7 @@ use crate::{
 };
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum UnaryOperator {
     /// Arithmetic negation: -x
     Negation,
@@ -14,6 +16,7 @@ pub enum UnaryOperator {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum BinaryOperator {
     Addition,
     Subtraction,
@@ -31,24 +34,40 @@ pub enum BinaryOperator {
 }
 
 /// An expression AST node. Represents a value-producing expression in the language.
+///
+/// Every node carries the byte span of its source text: the trailing
+/// `Range<usize>` of tuple variants, the `span` field of struct variants, and
+/// the template's span for `Print`, `Interpolation` and `Strikethrough`.
+/// [`Value::span`] returns it for any node.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Value {
     // Literals
-    StringLiteral(String),
-    NumberLiteral(f64),
-    BooleanLiteral(bool),
-    UnitLiteral,
+    StringLiteral(String, Range<usize>),
+    NumberLiteral(f64, Range<usize>),
+    BooleanLiteral(bool, Range<usize>),
+    UnitLiteral(Range<usize>),
 
     // References
     VariableReference(String, Range<usize>),
     PositionalArgumentReference(usize, Range<usize>), // #0, #1, etc.
-    SpreadArgumentReference,                          // #*
+    SpreadArgumentReference(Range<usize>),            // #*
+    NamedArgumentReference(String, Range<usize>),     // #name
+    NamedArgumentRecord(Range<usize>),                // #:
 
     // Invocations
     /// [args](#block) -- invoke block, return Document
-    BlockInvocation(Vec<Value>, BlockReference),
+    BlockInvocation(Vec<Value>, BlockReference, Range<usize>),
     /// ![args](#block) -- invoke block, evaluate Document
-    EvaluatedBlockInvocation(Vec<Value>, BlockReference),
+    EvaluatedBlockInvocation(Vec<Value>, BlockReference, Range<usize>),
+    /// [args]({expr}) -- invoke the block that the destination template evaluates to
+    DynamicBlockInvocation(Vec<Value>, TemplateString, Range<usize>),
+    /// ![args]({expr}) -- dynamic invocation, evaluate Document
+    EvaluatedDynamicBlockInvocation(Vec<Value>, TemplateString, Range<usize>),
+    /// name: value -- a named argument inside an invocation's argument list
+    NamedArgument(String, Box<Value>, Range<usize>),
+    /// &[](#block) -- reference a block as a value without invoking it
+    BlockRef(BlockReference, Range<usize>),
 
     // Inline Markdown semantics
     /// **{expr}** -- print to stdout
@@ -62,11 +81,16 @@ pub enum Value {
     UnaryOperation {
         operator: UnaryOperator,
         operand: Box<Value>,
+        span: Range<usize>,
     },
     BinaryOperation {
         operator: BinaryOperator,
         left: Box<Value>,
         right: Box<Value>,
+        /// Span of the operator token alone; runtime errors such as division
+        /// by zero point here.
+        operator_span: Range<usize>,
+        span: Range<usize>,
     },
 
     // Control flow
@@ -76,12 +100,98 @@ pub enum Value {
         condition: Box<Value>,
         true_branch: Box<Value>,
         false_branch: Option<Box<Value>>,
+        span: Range<usize>,
     },
 
     /// Pattern match expression
     Match {
         value: Box<Value>,
-        arms: Vec<(Template, Value)>,
-        otherwise: Option<(Option<String>, Box<Value>)>,
+        arms: Vec<MatchArm>,
+        otherwise: Option<OtherwiseArm>,
+        span: Range<usize>,
     },
+
+    // Recovery
+    /// Placeholder for an expression that failed to parse. The parser reports
+    /// the error separately and keeps going, so a program containing this
+    /// node never runs.
+    Error(Range<usize>),
+}
+
+impl fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            UnaryOperator::Negation => "-",
+            UnaryOperator::LogicalNot => "!",
+        })
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BinaryOperator::Addition => "+",
+            BinaryOperator::Subtraction => "-",
+            BinaryOperator::Multiplication => "*",
+            BinaryOperator::Division => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::LogicalAnd => "&&",
+            BinaryOperator::LogicalOr => "||",
+            BinaryOperator::Equality => "==",
+            BinaryOperator::Inequality => "!=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::GreaterThanOrEqual => ">=",
+            BinaryOperator::LessThanOrEqual => "<=",
+        })
+    }
+}
+
+impl Value {
+    /// Byte span of the expression in the source.
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            Value::StringLiteral(_, span)
+            | Value::NumberLiteral(_, span)
+            | Value::BooleanLiteral(_, span)
+            | Value::UnitLiteral(span)
+            | Value::VariableReference(_, span)
+            | Value::PositionalArgumentReference(_, span)
+            | Value::SpreadArgumentReference(span)
+            | Value::NamedArgumentReference(_, span)
+            | Value::NamedArgumentRecord(span)
+            | Value::BlockInvocation(_, _, span)
+            | Value::EvaluatedBlockInvocation(_, _, span)
+            | Value::DynamicBlockInvocation(_, _, span)
+            | Value::EvaluatedDynamicBlockInvocation(_, _, span)
+            | Value::NamedArgument(_, _, span)
+            | Value::BlockRef(_, span)
+            | Value::UnaryOperation { span, .. }
+            | Value::BinaryOperation { span, .. }
+            | Value::Conditional { span, .. }
+            | Value::Match { span, .. }
+            | Value::Error(span) => span.clone(),
+            Value::Print(ts) | Value::Interpolation(ts) | Value::Strikethrough(ts) => ts.span.clone(),
+        }
+    }
+}
+
+/// A single `pattern: result` arm of a match expression.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct MatchArm {
+    pub pattern: Template,
+    pub result: Value,
+    /// Byte span of the whole arm (pattern and result).
+    pub span: Range<usize>,
+}
+
+/// The `otherwise [binding]: result` fallback arm of a match expression.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct OtherwiseArm {
+    pub binding: Option<String>,
+    pub result: Box<Value>,
+    /// Byte span of the whole arm.
+    pub span: Range<usize>,
 }
@@ -1,16 +1,37 @@
+pub mod analysis;
 pub mod block;
 pub mod chain;
+pub mod codes;
+pub mod cst;
 pub mod document;
+pub mod format;
 pub mod instruction;
+pub mod lint;
 pub mod parser;
+pub mod suggest;
+pub mod visit;
+
+use std::ops::Range;
 
 use crate::block::Block;
+use crate::codes::Code;
+use crate::lint::Suppression;
 
 /// A parsed markdownlang program.
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Program {
     /// Top-level blocks (heading level 1).
     pub blocks: Vec<Block>,
     /// The source file ID (for error reporting with codespan-reporting).
     pub source_id: usize,
+    /// `<!-- mdl-allow: ... -->` comments, in source order.
+    pub suppressions: Vec<Suppression>,
+}
+
+impl Program {
+    /// Whether a warning with `code` at `span` is silenced in the source.
+    pub fn allows(&self, code: Code, span: &Range<usize>) -> bool {
+        self.suppressions.iter().any(|s| s.covers(code, span))
+    }
 }
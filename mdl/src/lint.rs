@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+use std::ops::Range;
+
+use codespan_reporting::diagnostic::Severity;
+
+use crate::Program;
+use crate::codes::Code;
+use crate::parser::ParseError;
+
+/// How a warning is reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Level {
+    /// Not reported at all.
+    Allow,
+    /// Reported; does not affect the exit code.
+    Warn,
+    /// Reported as an error; the command fails.
+    Deny,
+}
+
+/// Warning levels chosen on the command line (`-A`, `-W`, `-D`,
+/// `--deny-warnings`). A level set for a specific code overrides the level
+/// set for `warnings` as a whole; when one code is given several levels, the
+/// strictest wins. Errors are always denied.
+#[derive(Debug, Clone, Default)]
+pub struct LintLevels {
+    warnings: Option<Level>,
+    codes: HashMap<Code, Level>,
+}
+
+impl LintLevels {
+    /// Set the level of every warning.
+    pub fn set_all(&mut self, level: Level) {
+        self.warnings = Some(self.warnings.map_or(level, |current| current.max(level)));
+    }
+
+    /// Set the level of one warning code.
+    pub fn set(&mut self, code: Code, level: Level) {
+        let entry = self.codes.entry(code).or_insert(level);
+        *entry = (*entry).max(level);
+    }
+
+    /// Set a level from a command-line name: a warning code such as `W0201`
+    /// (any case), or `warnings` for all of them.
+    pub fn set_named(&mut self, name: &str, level: Level) -> Result<(), String> {
+        if name == "warnings" {
+            self.set_all(level);
+            return Ok(());
+        }
+        match Code::lookup(name) {
+            Some(code) if code.is_warning() => {
+                self.set(code, level);
+                Ok(())
+            }
+            Some(code) => Err(format!("{} is an error, not a warning; its level cannot be changed", code)),
+            None => Err(format!("unknown diagnostic code '{}'", name)),
+        }
+    }
+
+    /// The level a diagnostic with `code` is reported at.
+    pub fn level(&self, code: Code) -> Level {
+        if !code.is_warning() {
+            return Level::Deny;
+        }
+        self.codes
+            .get(&code)
+            .copied()
+            .or(self.warnings)
+            .unwrap_or(Level::Warn)
+    }
+
+    /// The level of a diagnostic reported in `program`. An `mdl-allow`
+    /// comment covering the diagnostic takes precedence over the command line.
+    pub fn level_in(&self, program: &Program, code: Code, span: Option<&Range<usize>>) -> Level {
+        match span {
+            Some(span) if program.allows(code, span) => Level::Allow,
+            _ => self.level(code),
+        }
+    }
+
+    /// Drop allowed warnings from `diagnostics` and turn denied ones into errors.
+    pub fn apply(&self, program: &Program, diagnostics: Vec<ParseError>) -> Vec<ParseError> {
+        diagnostics
+            .into_iter()
+            .filter_map(|diagnostic| {
+                if diagnostic.severity != Severity::Warning {
+                    return Some(diagnostic);
+                }
+                match self.level_in(program, diagnostic.code, Some(&diagnostic.span)) {
+                    Level::Allow => None,
+                    Level::Warn => Some(diagnostic),
+                    Level::Deny => {
+                        let code = diagnostic.code;
+                        let mut diagnostic = diagnostic.with_note(denied_note(code));
+                        diagnostic.severity = Severity::Error;
+                        Some(diagnostic)
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// Note attached to a warning reported as an error.
+pub fn denied_note(code: Code) -> String {
+    format!("warning {} is denied on the command line", code)
+}
+
+/// An `<!-- mdl-allow: W0201, W0202 -->` comment silencing warnings in the
+/// instruction or heading section directly after it.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Suppression {
+    /// The allowed warning codes.
+    pub codes: Vec<Code>,
+    /// The source covered: the whole list item, or the heading's block
+    /// including its sub-blocks.
+    pub span: Range<usize>,
+}
+
+impl Suppression {
+    /// Whether a diagnostic with `code` at `span` is silenced.
+    pub fn covers(&self, code: Code, span: &Range<usize>) -> bool {
+        self.codes.contains(&code) && self.span.start <= span.start && span.end <= self.span.end
+    }
+}
+
+/// Parse an HTML comment as a suppression. Returns None for ordinary
+/// comments, and an error message for unknown codes, error codes or an
+/// empty list. `warnings` allows every warning.
+pub fn parse_allow_comment(comment: &str) -> Option<Result<Vec<Code>, String>> {
+    let body = comment.trim().strip_prefix("<!--")?.strip_suffix("-->")?.trim();
+    let list = body.strip_prefix("mdl-allow:")?;
+
+    let mut codes = Vec::new();
+    for word in list.split(|c: char| c == ',' || c.is_whitespace()).filter(|w| !w.is_empty()) {
+        if word == "warnings" {
+            codes.extend(Code::ALL.into_iter().filter(Code::is_warning));
+            continue;
+        }
+        match Code::lookup(word) {
+            Some(code) if code.is_warning() => codes.push(code),
+            Some(code) => return Some(Err(format!("{} is an error and cannot be allowed", code))),
+            None => return Some(Err(format!("unknown diagnostic code '{}'", word))),
+        }
+    }
+    if codes.is_empty() {
+        return Some(Err("`mdl-allow` comment lists no codes".to_string()));
+    }
+    Some(Ok(codes))
+}
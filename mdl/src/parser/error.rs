@@ -1,48 +1,85 @@
 use std::ops::Range;
 
-use codespan_reporting::diagnostic::{Diagnostic, Label, Severity};
+use codespan_reporting::diagnostic::{Diagnostic, Label, LabelStyle, Severity};
+
+use crate::codes::Code;
 
 /// Parse errors with source location information.
 #[derive(Debug, Clone)]
 pub struct ParseError {
+    pub code: Code,
     pub message: String,
     pub span: Range<usize>,
     pub file_id: usize,
     pub severity: Severity,
     pub notes: Vec<String>,
+    /// Labelled source locations in addition to the bare primary span:
+    /// a message for the primary span itself, or related secondary spans.
+    pub labels: Vec<(LabelStyle, Range<usize>, String)>,
 }
 
 impl ParseError {
-    pub fn error(message: impl Into<String>, span: Range<usize>, file_id: usize) -> Self {
+    pub fn error(code: Code, message: impl Into<String>, span: Range<usize>, file_id: usize) -> Self {
         ParseError {
+            code,
             message: message.into(),
             span,
             file_id,
             severity: Severity::Error,
             notes: Vec::new(),
+            labels: Vec::new(),
         }
     }
 
-    pub fn warning(message: impl Into<String>, span: Range<usize>, file_id: usize) -> Self {
+    pub fn warning(code: Code, message: impl Into<String>, span: Range<usize>, file_id: usize) -> Self {
         ParseError {
+            code,
             message: message.into(),
             span,
             file_id,
             severity: Severity::Warning,
             notes: Vec::new(),
+            labels: Vec::new(),
         }
     }
 
+    /// Whether this is a warning, which does not stop the program from running.
+    pub fn is_warning(&self) -> bool {
+        self.severity == Severity::Warning
+    }
+
     pub fn with_note(mut self, note: impl Into<String>) -> Self {
         self.notes.push(note.into());
         self
     }
 
+    /// Attach a message to the primary span.
+    pub fn with_label(mut self, label: impl Into<String>) -> Self {
+        self.labels.push((LabelStyle::Primary, self.span.clone(), label.into()));
+        self
+    }
+
+    /// Point at a related source location in the same file.
+    pub fn with_secondary(mut self, span: Range<usize>, label: impl Into<String>) -> Self {
+        self.labels.push((LabelStyle::Secondary, span, label.into()));
+        self
+    }
+
     /// Convert to a codespan-reporting Diagnostic for display.
     pub fn to_diagnostic(&self) -> Diagnostic<usize> {
+        let mut labels = Vec::new();
+        if !self.labels.iter().any(|(style, ..)| *style == LabelStyle::Primary) {
+            labels.push(Label::primary(self.file_id, self.span.clone()));
+        }
+        labels.extend(
+            self.labels
+                .iter()
+                .map(|(style, span, label)| Label::new(*style, self.file_id, span.clone()).with_message(label)),
+        );
         Diagnostic::new(self.severity)
+            .with_code(self.code.as_str())
             .with_message(&self.message)
-            .with_labels(vec![Label::primary(self.file_id, self.span.clone())])
+            .with_labels(labels)
             .with_notes(self.notes.clone())
     }
 }
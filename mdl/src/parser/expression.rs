@@ -1,11 +1,13 @@
 use std::ops::Range;
 
-use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use pulldown_cmark::{Event, Tag, TagEnd};
 
+use crate::codes::Code;
 use crate::block::reference::BlockReference;
 use crate::instruction::Instruction;
 use crate::instruction::template::template_string::{TemplateString, TemplateStringPart};
-use crate::instruction::value::{BinaryOperator, UnaryOperator, Value};
+use crate::instruction::types::TypeAnnotation;
+use crate::instruction::value::{BinaryOperator, MatchArm, OtherwiseArm, UnaryOperator, Value};
 use crate::parser::error::ParseError;
 
 // ---------------------------------------------------------------------------
@@ -22,10 +24,12 @@ enum Token {
     Unit,
 
     // Identifiers & references
-    Ident(String, Range<usize>),
-    ArgRef(usize, Range<usize>),   // #0, #1, ...
+    Ident(String),
+    ArgRef(usize),                 // #0, #1, ...
     SpreadRef,                     // #*
-    Hash(usize),                   // bare # (carries byte offset for merge)
+    NamedArgRef(String),           // #name
+    NamedRecordRef,                // #:
+    Hash,                          // bare # (merged with what follows)
 
     // Keywords
     Match,
@@ -63,19 +67,22 @@ enum Token {
     Bold(TemplateString),
     Emphasis(TemplateString),
     Strike(TemplateString),
-    Link { text_tokens: Vec<Token>, dest: String },
-    Image { text_tokens: Vec<Token>, dest: String },
+    Link { text_tokens: Vec<SpannedToken>, dest: String, dest_offset: usize },
+    Image { text_tokens: Vec<SpannedToken>, dest: String, dest_offset: usize },
 
     // Nested unordered list (for match arms), stored as raw events
-    MatchArms(Vec<MatchArm>),
+    MatchArms(Vec<RawMatchArm>),
 }
 
+/// A token with the byte span of its source text.
+type SpannedToken = (Token, Range<usize>);
+
 #[derive(Debug, Clone)]
-struct MatchArm {
+struct RawMatchArm {
     /// Tokens for the pattern portion (before the colon).
-    pattern: (Vec<Token>, Range<usize>),
+    pattern: (Vec<SpannedToken>, Range<usize>),
     /// Tokens for the result portion (after the colon).
-    result: (Vec<Token>, Range<usize>),
+    result: (Vec<SpannedToken>, Range<usize>),
     /// Whether this arm's pattern text starts with "otherwise".
     is_otherwise: bool,
 }
@@ -101,59 +108,121 @@ enum OwnedEvent {
 // ---------------------------------------------------------------------------
 
 /// Parse the pulldown-cmark events for a single ordered list item into an Instruction.
+///
+/// Syntax errors do not stop parsing: the parser reports the error, stands a
+/// `Value::Error` in for the broken expression and picks up again at the next
+/// operator, closing delimiter or argument. The instruction is always
+/// returned, together with every error found in it.
 pub fn parse_instruction(
     events: &[(Event<'_>, Range<usize>)],
     _source: &str,
     span: Range<usize>,
     file_id: usize,
-) -> Result<Instruction, ParseError> {
-    let tokens = tokenize_events(events, file_id, span.clone())?;
+) -> (Instruction, Vec<ParseError>) {
+    let mut errors = Vec::new();
+    let tokens = tokenize_events(events, file_id, &mut errors);
     let mut parser = ExprParser::new(tokens, span.clone(), file_id);
 
-    // Check for assignment: ident = expr
-    if parser.is_assignment() {
-        let name = parser.expect_ident()?;
-        parser.expect_token_kind(TokenKind::Eq)?;
-        let value = parser.parse_expr(0)?;
-        if !parser.at_end() {
-            return Err(parser.error("unexpected tokens after assignment"));
-        }
-        Ok(Instruction::Assignment {
+    // Check for assignment: ident = expr, or ident: Type = expr
+    let instruction = if parser.is_assignment() {
+        let Some((Token::Ident(name), _)) = parser.advance() else {
+            unreachable!("assignment starts with an identifier");
+        };
+        let annotation = if parser.peek_kind() == Some(TokenKind::Colon) {
+            parser.advance();
+            parser.expect_type()
+        } else {
+            None
+        };
+        parser.advance(); // `=`, checked by is_assignment
+        let value = parser.parse_expr(0);
+        parser.expect_end("unexpected tokens after assignment");
+        Instruction::Assignment {
             variable: name,
+            annotation,
             value,
             span,
-        })
-    } else {
-        let value = parser.parse_expr(0)?;
-        if !parser.at_end() {
-            return Err(parser.error("unexpected tokens after expression"));
         }
-        Ok(Instruction::Expression { value, span })
+    } else {
+        let value = parser.parse_expr(0);
+        parser.expect_end("unexpected tokens after expression");
+        Instruction::Expression { value, span }
+    };
+
+    errors.append(&mut parser.errors);
+    (instruction, errors)
+}
+
+/// Parse a plain-text expression located at `span` in the source,
+/// e.g. a parameter default value. Recovers from errors like
+/// [`parse_instruction`].
+pub fn parse_expression_text(
+    text: &str,
+    span: Range<usize>,
+    file_id: usize,
+) -> (Value, Vec<ParseError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    if let Err(msg) = tokenize_text(text, &mut tokens, span.start) {
+        errors.push(ParseError::error(Code::InvalidSyntax, msg, span.clone(), file_id));
     }
+    merge_compound_operators(&mut tokens);
+    let mut parser = ExprParser::new(tokens, span.clone(), file_id);
+    let value = if parser.at_end() {
+        parser.recover(parser.error("expected an expression"))
+    } else {
+        parser.parse_expr(0)
+    };
+    parser.expect_end("unexpected tokens after expression");
+    errors.append(&mut parser.errors);
+    (value, errors)
 }
 
-/// Parse a raw text string as a template, extracting `{expr}` regions.
-/// Re-uses the expression tokenizer and parser.
+/// Parse a raw text string located at `offset` in the source as a template,
+/// extracting `{expr}` regions. Re-uses the expression tokenizer and parser,
+/// and fails with the first error in any region.
 pub fn parse_text_template(
     text: &str,
+    offset: usize,
     file_id: usize,
 ) -> Result<TemplateString, ParseError> {
-    let span = 0..text.len();
+    template_from_text(text, file_id, &|r: Range<usize>| offset + r.start..offset + r.end)
+}
+
+/// Parse text that has no place of its own in the source, such as document
+/// text built at runtime, as a template. Every node gets `span`, the source
+/// location the text is evaluated for.
+pub fn parse_detached_template(
+    text: &str,
+    span: Range<usize>,
+    file_id: usize,
+) -> Result<TemplateString, ParseError> {
+    template_from_text(text, file_id, &|_| span.clone())
+}
+
+/// Shared body of [`parse_text_template`] and [`parse_detached_template`].
+/// `place` maps a byte range of `text` to its span in the source.
+fn template_from_text(
+    text: &str,
+    file_id: usize,
+    place: &dyn Fn(Range<usize>) -> Range<usize>,
+) -> Result<TemplateString, ParseError> {
     let mut parts = Vec::new();
     let mut literal = String::new();
-    let chars: Vec<char> = text.chars().collect();
+    let chars: Vec<(usize, char)> = text.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map_or(text.len(), |&(b, _)| b);
     let mut i = 0;
 
     while i < chars.len() {
-        if chars[i] == '{' {
+        if chars[i].1 == '{' {
             // Find matching closing brace
             let mut depth = 1;
             let start = i + 1;
             i += 1;
             while i < chars.len() && depth > 0 {
-                if chars[i] == '{' {
+                if chars[i].1 == '{' {
                     depth += 1;
-                } else if chars[i] == '}' {
+                } else if chars[i].1 == '}' {
                     depth -= 1;
                 }
                 if depth > 0 {
@@ -166,23 +235,30 @@ pub fn parse_text_template(
                     parts.push(TemplateStringPart::Literal(std::mem::take(&mut literal)));
                 }
                 // Parse expression between braces
-                let expr_text: String = chars[start..i].iter().collect();
+                let expr_range = byte_at(start)..byte_at(i);
+                let expr_span = place(expr_range.clone());
                 let mut tokens = Vec::new();
-                tokenize_text(&expr_text, &mut tokens, 0)
-                    .map_err(|msg| ParseError::error(msg, span.clone(), file_id))?;
+                tokenize_text(&text[expr_range.clone()], &mut tokens, expr_range.start)
+                    .map_err(|msg| ParseError::error(Code::InvalidSyntax, msg, expr_span.clone(), file_id))?;
                 merge_compound_operators(&mut tokens);
-                let mut parser = ExprParser::new(tokens, span.clone(), file_id);
-                let expr = parser.parse_expr(0)?;
+                for (_, span) in &mut tokens {
+                    *span = place(span.clone());
+                }
+                let mut parser = ExprParser::new(tokens, expr_span, file_id);
+                let expr = parser.parse_expr(0);
+                if let Some(err) = parser.errors.into_iter().next() {
+                    return Err(err);
+                }
                 parts.push(TemplateStringPart::Expression(expr));
                 i += 1; // skip closing }
             } else {
                 // Unmatched brace, treat as literal
                 literal.push('{');
-                literal.extend(chars[start..].iter());
+                literal.extend(chars[start..].iter().map(|&(_, c)| c));
                 break;
             }
         } else {
-            literal.push(chars[i]);
+            literal.push(chars[i].1);
             i += 1;
         }
     }
@@ -195,18 +271,20 @@ pub fn parse_text_template(
         parts.push(TemplateStringPart::Literal(String::new()));
     }
 
-    Ok(TemplateString { parts })
+    Ok(TemplateString { parts, span: place(0..text.len()) })
 }
 
 // ---------------------------------------------------------------------------
 // Tokenizer: pulldown-cmark events → Token stream
 // ---------------------------------------------------------------------------
 
+/// Tokenize the events of one item. Errors are recorded in `errors` and the
+/// offending text skipped.
 fn tokenize_events(
     events: &[(Event<'_>, Range<usize>)],
     file_id: usize,
-    span: Range<usize>,
-) -> Result<Vec<Token>, ParseError> {
+    errors: &mut Vec<ParseError>,
+) -> Vec<SpannedToken> {
     let mut tokens = Vec::new();
     let mut i = 0;
 
@@ -219,13 +297,14 @@ fn tokenize_events(
             }
 
             Event::Text(s) => {
-                tokenize_text(s, &mut tokens, range.start)
-                    .map_err(|msg| ParseError::error(msg, span.clone(), file_id))?;
+                if let Err(msg) = tokenize_text(s, &mut tokens, range.start) {
+                    errors.push(ParseError::error(Code::InvalidSyntax, msg, range.clone(), file_id));
+                }
                 i += 1;
             }
 
             Event::Code(s) => {
-                tokens.push(Token::StringLit(s.to_string()));
+                tokens.push((Token::StringLit(s.to_string()), range.clone()));
                 i += 1;
             }
 
@@ -235,47 +314,43 @@ fn tokenize_events(
 
             Event::Start(Tag::Strong) => {
                 i += 1;
-                let ts = collect_template_string(events, &mut i, &|e| matches!(e, TagEnd::Strong), file_id, span.clone())?;
-                tokens.push(Token::Bold(ts));
+                let ts = collect_template_string(events, &mut i, &|e| matches!(e, TagEnd::Strong), file_id, range.clone(), errors);
+                tokens.push((Token::Bold(ts), range.clone()));
             }
 
             Event::Start(Tag::Strikethrough) => {
                 i += 1;
-                let ts = collect_template_string(events, &mut i, &|e| matches!(e, TagEnd::Strikethrough), file_id, span.clone())?;
-                tokens.push(Token::Strike(ts));
+                let ts = collect_template_string(events, &mut i, &|e| matches!(e, TagEnd::Strikethrough), file_id, range.clone(), errors);
+                tokens.push((Token::Strike(ts), range.clone()));
             }
 
             Event::Start(Tag::Link { dest_url, .. }) => {
                 let dest = dest_url.to_string();
+                let dest_offset = destination_offset(range, &dest);
                 i += 1;
-                let inner = collect_until_end(events, &mut i, |e| matches!(e, TagEnd::Link), file_id, span.clone())?;
-                tokens.push(Token::Link {
-                    text_tokens: inner,
-                    dest,
-                });
+                let inner = collect_until_end(events, &mut i, |e| matches!(e, TagEnd::Link), file_id, errors);
+                tokens.push((Token::Link { text_tokens: inner, dest, dest_offset }, range.clone()));
             }
 
             Event::Start(Tag::Image { dest_url, .. }) => {
                 let dest = dest_url.to_string();
+                let dest_offset = destination_offset(range, &dest);
                 i += 1;
-                let inner = collect_until_end(events, &mut i, |e| matches!(e, TagEnd::Image), file_id, span.clone())?;
-                tokens.push(Token::Image {
-                    text_tokens: inner,
-                    dest,
-                });
+                let inner = collect_until_end(events, &mut i, |e| matches!(e, TagEnd::Image), file_id, errors);
+                tokens.push((Token::Image { text_tokens: inner, dest, dest_offset }, range.clone()));
             }
 
             // Unordered list inside an ordered list item = match arms
             Event::Start(Tag::List(None)) => {
                 i += 1;
-                let arms = collect_match_arms(events, &mut i, file_id, span.clone())?;
-                tokens.push(Token::MatchArms(arms));
+                let arms = collect_match_arms(events, &mut i, file_id, errors);
+                tokens.push((Token::MatchArms(arms), range.clone()));
             }
 
             Event::Start(Tag::Emphasis) => {
                 i += 1;
-                let ts = collect_template_string(events, &mut i, &|e| matches!(e, TagEnd::Emphasis), file_id, span.clone())?;
-                tokens.push(Token::Emphasis(ts));
+                let ts = collect_template_string(events, &mut i, &|e| matches!(e, TagEnd::Emphasis), file_id, range.clone(), errors);
+                tokens.push((Token::Emphasis(ts), range.clone()));
             }
 
             // Skip other events we don't handle in expression context
@@ -289,29 +364,36 @@ fn tokenize_events(
     // (pulldown-cmark may split text around < and > producing separate tokens)
     merge_compound_operators(&mut tokens);
 
-    Ok(tokens)
+    tokens
+}
+
+/// Source offset of a link's destination, given the span of the whole link.
+/// mdl links are written inline as `[text](dest)`, so the destination ends
+/// just before the closing parenthesis.
+fn destination_offset(link: &Range<usize>, dest: &str) -> usize {
+    link.end.saturating_sub(dest.len() + 1).max(link.start)
 }
 
 /// Merge adjacent compound tokens that may have been split across text events.
-/// Handles: Gt+Eq → GtEq, Lt+Eq → LtEq, Hash+Star → SpreadRef, Hash+Number → ArgRef.
-fn merge_compound_operators(tokens: &mut Vec<Token>) {
+/// Handles: Gt+Eq → GtEq, Lt+Eq → LtEq, Hash+Star → SpreadRef, Hash+Number → ArgRef,
+/// Hash+Ident → NamedArgRef, Hash+Colon → NamedRecordRef.
+/// The merged token spans both of its parts.
+fn merge_compound_operators(tokens: &mut Vec<SpannedToken>) {
     // Merge adjacent tokens
     let mut i = 0;
     while i + 1 < tokens.len() {
-        let merge = match (&tokens[i], &tokens[i + 1]) {
+        let merge = match (&tokens[i].0, &tokens[i + 1].0) {
             (Token::Gt, Token::Eq) => Some(Token::GtEq),
             (Token::Lt, Token::Eq) => Some(Token::LtEq),
-            (Token::Hash(_), Token::Star) => Some(Token::SpreadRef),
-            (Token::Hash(offset), Token::Number(n)) => {
-                // Approximate span: from # to end of number (exact end unknown, use offset+2 as estimate)
-                let span = *offset..*offset + 2;
-                Some(Token::ArgRef(*n as usize, span))
-            }
+            (Token::Hash, Token::Star) => Some(Token::SpreadRef),
+            (Token::Hash, Token::Colon) => Some(Token::NamedRecordRef),
+            (Token::Hash, Token::Ident(name)) => Some(Token::NamedArgRef(name.clone())),
+            (Token::Hash, Token::Number(n)) => Some(Token::ArgRef(*n as usize)),
             _ => None,
         };
         if let Some(merged) = merge {
-            tokens[i] = merged;
-            tokens.remove(i + 1);
+            let end = tokens.remove(i + 1).1.end;
+            tokens[i] = (merged, tokens[i].1.start..end);
         } else {
             i += 1;
         }
@@ -325,8 +407,8 @@ fn collect_until_end(
     i: &mut usize,
     is_end: impl Fn(&TagEnd) -> bool,
     file_id: usize,
-    span: Range<usize>,
-) -> Result<Vec<Token>, ParseError> {
+    errors: &mut Vec<ParseError>,
+) -> Vec<SpannedToken> {
     let mut inner_events = Vec::new();
     let mut depth = 1u32;
 
@@ -354,7 +436,7 @@ fn collect_until_end(
         }
     }
 
-    tokenize_events(&inner_events, file_id, span)
+    tokenize_events(&inner_events, file_id, errors)
 }
 
 /// Parse a text string for `{expr}` interpolations, returning template parts.
@@ -366,9 +448,9 @@ fn parse_template_text_with_braces<'a>(
     brace_depth: &mut u32,
     expr_events: &mut Vec<(Event<'a>, Range<usize>)>,
     range: &Range<usize>,
-    span: &Range<usize>,
     file_id: usize,
-) -> Result<Vec<TemplateStringPart>, ParseError> {
+    errors: &mut Vec<ParseError>,
+) -> Vec<TemplateStringPart> {
     let mut parts = Vec::new();
     let mut literal = String::new();
     let chars: Vec<char> = s.chars().collect();
@@ -402,18 +484,20 @@ fn parse_template_text_with_braces<'a>(
                 // Calculate source-relative byte offset for the expression
                 let byte_start: usize = chars[..start].iter().map(|c| c.len_utf8()).sum();
                 let expr_offset = range.start + byte_start;
+                let expr_span = expr_offset..expr_offset + expr_text.len();
                 let mut tokens = Vec::new();
-                tokenize_text(&expr_text, &mut tokens, expr_offset)
-                    .map_err(|msg| ParseError::error(msg, span.clone(), file_id))?;
-                let mut parser = ExprParser::new(tokens, span.clone(), file_id);
-                let expr = parser.parse_expr(0)?;
+                if let Err(msg) = tokenize_text(&expr_text, &mut tokens, expr_offset) {
+                    errors.push(ParseError::error(Code::InvalidSyntax, msg, expr_span.clone(), file_id));
+                }
+                let expr = parse_tokens(tokens, expr_span, file_id, errors);
                 parts.push(TemplateStringPart::Expression(expr));
             } else {
                 // Expression spans beyond this text event
                 *brace_depth = depth;
                 let rest: String = chars[start..].iter().collect();
                 if !rest.is_empty() {
-                    expr_events.push((Event::Text(rest.into()), range.clone()));
+                    let byte_start: usize = chars[..start].iter().map(|c| c.len_utf8()).sum();
+                    expr_events.push((Event::Text(rest.into()), range.start + byte_start..range.end));
                 }
                 break;
             }
@@ -427,10 +511,11 @@ fn parse_template_text_with_braces<'a>(
         parts.push(TemplateStringPart::Literal(literal));
     }
 
-    Ok(parts)
+    parts
 }
 
 /// Collect the contents of a Bold/Strike tag and build a TemplateString directly.
+/// `span` is the source range of the whole tag, delimiters included.
 /// Text is preserved literally (whitespace included) with `{expr}` interpolations.
 /// Links and images inside `{...}` become expression parts (block invocations).
 ///
@@ -444,7 +529,8 @@ fn collect_template_string(
     is_end: &dyn Fn(&TagEnd) -> bool,
     file_id: usize,
     span: Range<usize>,
-) -> Result<TemplateString, ParseError> {
+    errors: &mut Vec<ParseError>,
+) -> TemplateString {
     let mut parts: Vec<TemplateStringPart> = Vec::new();
     // When > 0, we are inside `{...}` and collecting events for an expression.
     let mut brace_depth: u32 = 0;
@@ -455,13 +541,12 @@ fn collect_template_string(
         let (ref ev, ref range) = events[*i];
 
         // Check for the closing tag of the Bold/Strike container
-        if brace_depth == 0 {
-            if let Event::End(tag_end) = ev {
-                if is_end(tag_end) {
-                    *i += 1;
-                    break;
-                }
-            }
+        if brace_depth == 0
+            && let Event::End(tag_end) = ev
+            && is_end(tag_end)
+        {
+            *i += 1;
+            break;
         }
 
         match ev {
@@ -473,7 +558,8 @@ fn collect_template_string(
                 // Outside braces: scan for `{` to enter expression mode.
                 // A single text event may contain multiple `{expr}` regions
                 // (e.g. `{x} + {y}`), so we loop through the entire text.
-                let text_parts = parse_template_text_with_braces(s, &mut brace_depth, &mut expr_events, range, &span, file_id)?;
+                let text_parts =
+                    parse_template_text_with_braces(s, &mut brace_depth, &mut expr_events, range, file_id, errors);
                 for part in text_parts {
                     match part {
                         TemplateStringPart::Literal(s) => current_literal.push_str(&s),
@@ -503,22 +589,22 @@ fn collect_template_string(
                         if brace_depth == 0 {
                             // Text before } goes into expr_events
                             let before: String = chars[..ci].iter().collect();
+                            let close = range.start + before.len();
                             if !before.is_empty() {
                                 expr_events.push((
                                     Event::Text(before.into()),
-                                    range.clone(),
+                                    range.start..close,
                                 ));
                             }
                             // Parse collected events as expression
+                            let expr_start = expr_events.first().map_or(close, |(_, r)| r.start);
                             let tokens = tokenize_events(
                                 &expr_events,
                                 file_id,
-                                span.clone(),
-                            )?;
+                                errors,
+                            );
                             expr_events.clear();
-                            let mut parser =
-                                ExprParser::new(tokens, span.clone(), file_id);
-                            let expr = parser.parse_expr(0)?;
+                            let expr = parse_tokens(tokens, expr_start..close, file_id, errors);
                             parts.push(TemplateStringPart::Expression(expr));
 
                             // Text after } continues as literal
@@ -563,8 +649,9 @@ fn collect_template_string(
                     i,
                     &|e| matches!(e, TagEnd::Emphasis),
                     file_id,
-                    span.clone(),
-                )?;
+                    range.clone(),
+                    errors,
+                );
                 // Merge inner emphasis parts
                 if !current_literal.is_empty() {
                     parts.push(TemplateStringPart::Literal(
@@ -582,19 +669,18 @@ fn collect_template_string(
                     ));
                 }
                 let dest = dest_url.to_string();
+                let dest_offset = destination_offset(range, &dest);
+                let link_span = range.clone();
                 *i += 1;
                 let inner = collect_until_end(
                     events,
                     i,
                     |e| matches!(e, TagEnd::Link),
                     file_id,
-                    span.clone(),
-                )?;
-                let block_ref = parse_block_reference(&dest);
-                let args = parse_argument_list(inner, file_id, span.clone())?;
-                parts.push(TemplateStringPart::Expression(
-                    Value::BlockInvocation(args, block_ref),
-                ));
+                    errors,
+                );
+                let invocation = parse_invocation(inner, &dest, dest_offset, false, file_id, link_span, errors);
+                parts.push(TemplateStringPart::Expression(invocation));
             }
             Event::Start(Tag::Image { dest_url, .. }) => {
                 if !current_literal.is_empty() {
@@ -603,19 +689,18 @@ fn collect_template_string(
                     ));
                 }
                 let dest = dest_url.to_string();
+                let dest_offset = destination_offset(range, &dest);
+                let link_span = range.clone();
                 *i += 1;
                 let inner = collect_until_end(
                     events,
                     i,
                     |e| matches!(e, TagEnd::Image),
                     file_id,
-                    span.clone(),
-                )?;
-                let block_ref = parse_block_reference(&dest);
-                let args = parse_argument_list(inner, file_id, span.clone())?;
-                parts.push(TemplateStringPart::Expression(
-                    Value::EvaluatedBlockInvocation(args, block_ref),
-                ));
+                    errors,
+                );
+                let invocation = parse_invocation(inner, &dest, dest_offset, true, file_id, link_span, errors);
+                parts.push(TemplateStringPart::Expression(invocation));
             }
 
             _ => {
@@ -633,7 +718,7 @@ fn collect_template_string(
         parts.push(TemplateStringPart::Literal(String::new()));
     }
 
-    Ok(TemplateString { parts })
+    TemplateString { parts, span }
 }
 
 /// Collect match arms from an unordered list.
@@ -642,12 +727,12 @@ fn collect_match_arms(
     events: &[(Event<'_>, Range<usize>)],
     i: &mut usize,
     file_id: usize,
-    _span: Range<usize>,
-) -> Result<Vec<MatchArm>, ParseError> {
+    errors: &mut Vec<ParseError>,
+) -> Vec<RawMatchArm> {
     let mut arms = Vec::new();
 
     while *i < events.len() {
-        let (ref ev, ref span) = events[*i];
+        let (ref ev, _) = events[*i];
         match ev {
             Event::End(TagEnd::List(false)) => {
                 *i += 1;
@@ -655,7 +740,7 @@ fn collect_match_arms(
             }
             Event::Start(Tag::Item) => {
                 *i += 1;
-                let arm = collect_single_match_arm(events, i, file_id, span.clone())?;
+                let arm = collect_single_match_arm(events, i, file_id, errors);
                 arms.push(arm);
             }
             _ => {
@@ -664,15 +749,15 @@ fn collect_match_arms(
         }
     }
 
-    Ok(arms)
+    arms
 }
 
 fn collect_single_match_arm(
     events: &[(Event<'_>, Range<usize>)],
     i: &mut usize,
     file_id: usize,
-    span: Range<usize>,
-) -> Result<MatchArm, ParseError> {
+    errors: &mut Vec<ParseError>,
+) -> RawMatchArm {
     let mut pattern_span = 0..0;
     let mut result_span = 0..0;
     let mut pattern_events = Vec::new();
@@ -691,7 +776,6 @@ fn collect_single_match_arm(
         match ev {
             Event::End(TagEnd::Item) if list_depth == 0 => {
                 *i += 1;
-                current_span.end = span.end;
                 break;
             }
             Event::Start(Tag::List(_)) => {
@@ -709,13 +793,14 @@ fn collect_single_match_arm(
             Event::Text(text) if !writing_to_pattern && list_depth == 0 && text.contains(":") => {
                 *i += 1;
                 let (before, after) = text.split_once(":").unwrap();
-                current_events.push((Event::Text(before.into()), current_span.end..span.start));
-                current_span.end = span.start;
+                let colon = span.start + before.len();
+                current_events.push((Event::Text(before.into()), span.start..colon));
+                current_span.end = colon;
                 current_events = &mut result_events;
                 current_span = &mut result_span;
                 writing_to_pattern = true;
-                *current_span = span.clone();
-                current_events.push((Event::Text(after.into()), span.clone()));
+                *current_span = colon + 1..span.end;
+                current_events.push((Event::Text(after.into()), colon + 1..span.end));
             }
             _ => {
                 *i += 1;
@@ -725,33 +810,34 @@ fn collect_single_match_arm(
         }
     }
 
-    let mut pattern = tokenize_events(&pattern_events, file_id, pattern_span.clone())?;
-    let is_otherwise = if let Some(Token::Ident(otherwise, _)) = pattern.get(0)
-        && otherwise == "otherwise" && pattern.len() >= 2 { true } else { false };
-    let (result, is_otherwise) = if is_otherwise && result_events.len() == 0 {
+    let mut pattern = tokenize_events(&pattern_events, file_id, errors);
+    let is_otherwise = matches!(pattern.first(), Some((Token::Ident(kw), _)) if kw == "otherwise");
+    let (result, is_otherwise) = if is_otherwise && result_events.is_empty() && pattern.len() >= 2 {
         result_span = pattern_span.clone();
-        if let Some(Token::Colon) = pattern.get(2) {
+        if let Some((Token::Colon, _)) = pattern.get(2) {
             pattern.remove(2);
             (pattern.split_off(2), true)
         } else {
             (pattern.split_off(2), true)
         }
     } else {
-        (tokenize_events(&result_events, file_id, result_span.clone())?, is_otherwise)
+        (tokenize_events(&result_events, file_id, errors), is_otherwise)
     };
 
-    Ok(MatchArm {
+    RawMatchArm {
         pattern: (pattern, pattern_span),
         result: (result, result_span),
         is_otherwise,
-    })
+    }
 }
 
 // ---------------------------------------------------------------------------
 // Text tokenizer: raw text string → Token stream
 // ---------------------------------------------------------------------------
 
-fn tokenize_text(text: &str, tokens: &mut Vec<Token>, base_offset: usize) -> Result<(), String> {
+/// Tokenize `text`, found at `base_offset` in the source. Token spans are
+/// exact as long as `text` is the verbatim source.
+fn tokenize_text(text: &str, tokens: &mut Vec<SpannedToken>, base_offset: usize) -> Result<(), String> {
     let chars: Vec<char> = text.chars().collect();
     let len = chars.len();
     let mut i = 0;
@@ -767,8 +853,10 @@ fn tokenize_text(text: &str, tokens: &mut Vec<Token>, base_offset: usize) -> Res
         bp.push(offset);
         bp
     };
+    let span_of = |start: usize, end: usize| base_offset + byte_pos[start]..base_offset + byte_pos[end];
 
     while i < len {
+        let token_start = i;
         let c = chars[i];
         match c {
             ' ' | '\t' | '\n' | '\r' => {
@@ -787,7 +875,7 @@ fn tokenize_text(text: &str, tokens: &mut Vec<Token>, base_offset: usize) -> Res
                 }
                 let s: String = chars[start..i].iter().collect();
                 i += 1; // skip closing quote
-                tokens.push(Token::StringLit(s));
+                tokens.push((Token::StringLit(s), span_of(token_start, i)));
             }
 
             // Numbers
@@ -798,7 +886,7 @@ fn tokenize_text(text: &str, tokens: &mut Vec<Token>, base_offset: usize) -> Res
                 }
                 let num_str: String = chars[start..i].iter().collect();
                 if let Ok(n) = num_str.parse::<f64>() {
-                    tokens.push(Token::Number(n));
+                    tokens.push((Token::Number(n), span_of(token_start, i)));
                 }
             }
 
@@ -809,35 +897,44 @@ fn tokenize_text(text: &str, tokens: &mut Vec<Token>, base_offset: usize) -> Res
                     i += 1;
                 }
                 let ident: String = chars[start..i].iter().collect();
-                let span = base_offset + byte_pos[start]..base_offset + byte_pos[i];
-                match ident.as_str() {
-                    "true" => tokens.push(Token::True),
-                    "false" => tokens.push(Token::False),
-                    "match" => tokens.push(Token::Match),
-                    _ => tokens.push(Token::Ident(ident, span)),
-                }
+                let token = match ident.as_str() {
+                    "_" => Token::Underscore,
+                    "true" => Token::True,
+                    "false" => Token::False,
+                    "match" => Token::Match,
+                    _ => Token::Ident(ident),
+                };
+                tokens.push((token, span_of(token_start, i)));
             }
 
             // Argument references: #0, #1, #*
             '#' => {
-                let hash_start = i;
                 i += 1;
                 if i < len && chars[i] == '*' {
                     i += 1;
-                    tokens.push(Token::SpreadRef);
+                    tokens.push((Token::SpreadRef, span_of(token_start, i)));
+                } else if i < len && chars[i] == ':' {
+                    i += 1;
+                    tokens.push((Token::NamedRecordRef, span_of(token_start, i)));
+                } else if i < len && (chars[i].is_ascii_alphabetic() || chars[i] == '_') {
+                    let start = i;
+                    while i < len && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                        i += 1;
+                    }
+                    let name: String = chars[start..i].iter().collect();
+                    tokens.push((Token::NamedArgRef(name), span_of(token_start, i)));
                 } else if i < len && chars[i].is_ascii_digit() {
                     let start = i;
                     while i < len && chars[i].is_ascii_digit() {
                         i += 1;
                     }
                     let num_str: String = chars[start..i].iter().collect();
-                    let span = base_offset + byte_pos[hash_start]..base_offset + byte_pos[i];
                     if let Ok(n) = num_str.parse::<usize>() {
-                        tokens.push(Token::ArgRef(n, span));
+                        tokens.push((Token::ArgRef(n), span_of(token_start, i)));
                     }
                 } else {
                     // Bare # at end of text or before unknown char — emit Hash for merging
-                    tokens.push(Token::Hash(base_offset + byte_pos[hash_start]));
+                    tokens.push((Token::Hash, span_of(token_start, i)));
                 }
             }
 
@@ -846,79 +943,79 @@ fn tokenize_text(text: &str, tokens: &mut Vec<Token>, base_offset: usize) -> Res
                 i += 1;
                 if i < len && chars[i] == '=' {
                     i += 1;
-                    tokens.push(Token::EqEq);
+                    tokens.push((Token::EqEq, span_of(token_start, i)));
                 } else {
-                    tokens.push(Token::Eq);
+                    tokens.push((Token::Eq, span_of(token_start, i)));
                 }
             }
             '!' => {
                 i += 1;
                 if i < len && chars[i] == '=' {
                     i += 1;
-                    tokens.push(Token::BangEq);
+                    tokens.push((Token::BangEq, span_of(token_start, i)));
                 } else {
-                    tokens.push(Token::Bang);
+                    tokens.push((Token::Bang, span_of(token_start, i)));
                 }
             }
             '>' => {
                 i += 1;
                 if i < len && chars[i] == '=' {
                     i += 1;
-                    tokens.push(Token::GtEq);
+                    tokens.push((Token::GtEq, span_of(token_start, i)));
                 } else {
-                    tokens.push(Token::Gt);
+                    tokens.push((Token::Gt, span_of(token_start, i)));
                 }
             }
             '<' => {
                 i += 1;
                 if i < len && chars[i] == '=' {
                     i += 1;
-                    tokens.push(Token::LtEq);
+                    tokens.push((Token::LtEq, span_of(token_start, i)));
                 } else {
-                    tokens.push(Token::Lt);
+                    tokens.push((Token::Lt, span_of(token_start, i)));
                 }
             }
             '&' => {
                 i += 1;
                 if i < len && chars[i] == '&' {
                     i += 1;
-                    tokens.push(Token::AmpAmp);
+                    tokens.push((Token::AmpAmp, span_of(token_start, i)));
                 } else {
-                    tokens.push(Token::Amp);
+                    tokens.push((Token::Amp, span_of(token_start, i)));
                 }
             }
             '|' => {
                 i += 1;
                 if i < len && chars[i] == '|' {
                     i += 1;
-                    tokens.push(Token::PipePipe);
+                    tokens.push((Token::PipePipe, span_of(token_start, i)));
                 } else {
-                    tokens.push(Token::Pipe);
+                    tokens.push((Token::Pipe, span_of(token_start, i)));
                 }
             }
 
             // Single-character operators
-            '+' => { i += 1; tokens.push(Token::Plus); }
-            '-' => { i += 1; tokens.push(Token::Minus); }
-            '*' => { i += 1; tokens.push(Token::Star); }
-            '/' => { i += 1; tokens.push(Token::Slash); }
-            '%' => { i += 1; tokens.push(Token::Percent); }
-            '?' => { i += 1; tokens.push(Token::Question); }
-            ':' => { i += 1; tokens.push(Token::Colon); }
-            ',' => { i += 1; tokens.push(Token::Comma); }
+            '+' => { i += 1; tokens.push((Token::Plus, span_of(token_start, i))); }
+            '-' => { i += 1; tokens.push((Token::Minus, span_of(token_start, i))); }
+            '*' => { i += 1; tokens.push((Token::Star, span_of(token_start, i))); }
+            '/' => { i += 1; tokens.push((Token::Slash, span_of(token_start, i))); }
+            '%' => { i += 1; tokens.push((Token::Percent, span_of(token_start, i))); }
+            '?' => { i += 1; tokens.push((Token::Question, span_of(token_start, i))); }
+            ':' => { i += 1; tokens.push((Token::Colon, span_of(token_start, i))); }
+            ',' => { i += 1; tokens.push((Token::Comma, span_of(token_start, i))); }
             '(' => {
                 i += 1;
                 // Check for unit literal ()
                 if i < len && chars[i] == ')' {
                     i += 1;
-                    tokens.push(Token::Unit);
+                    tokens.push((Token::Unit, span_of(token_start, i)));
                 } else {
-                    tokens.push(Token::LParen);
+                    tokens.push((Token::LParen, span_of(token_start, i)));
                 }
             }
-            ')' => { i += 1; tokens.push(Token::RParen); }
-            '{' => { i += 1; tokens.push(Token::LBrace); }
-            '}' => { i += 1; tokens.push(Token::RBrace); }
+            ')' => { i += 1; tokens.push((Token::RParen, span_of(token_start, i))); }
+            '{' => { i += 1; tokens.push((Token::LBrace, span_of(token_start, i))); }
+            '}' => { i += 1; tokens.push((Token::RBrace, span_of(token_start, i))); }
 
             _ => {
                 i += 1; // skip unknown chars
@@ -942,6 +1039,8 @@ enum TokenKind {
     Ident,
     ArgRef,
     SpreadRef,
+    NamedArgRef,
+    NamedRecordRef,
     Hash,
     Match,
     Plus,
@@ -987,7 +1086,9 @@ fn token_kind(t: &Token) -> TokenKind {
         Token::Ident(..) => TokenKind::Ident,
         Token::ArgRef(..) => TokenKind::ArgRef,
         Token::SpreadRef => TokenKind::SpreadRef,
-        Token::Hash(_) => TokenKind::Hash,
+        Token::NamedArgRef(..) => TokenKind::NamedArgRef,
+        Token::NamedRecordRef => TokenKind::NamedRecordRef,
+        Token::Hash => TokenKind::Hash,
         Token::Match => TokenKind::Match,
         Token::Plus => TokenKind::Plus,
         Token::Minus => TokenKind::Minus,
@@ -1028,10 +1129,14 @@ fn token_kind(t: &Token) -> TokenKind {
 // ---------------------------------------------------------------------------
 
 struct ExprParser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     pos: usize,
+    /// Source range of the whole token stream; errors with no better
+    /// location point here.
     span: Range<usize>,
     file_id: usize,
+    /// Errors recovered from so far, in the order they were found.
+    errors: Vec<ParseError>,
 }
 
 // Binding powers (precedence). Higher = tighter binding.
@@ -1046,19 +1151,19 @@ const BP_MULTIPLICATIVE: u8 = 14; // * / %
 const BP_UNARY: u8 = 16;        // ! -
 
 impl ExprParser {
-    fn new(tokens: Vec<Token>, span: Range<usize>, file_id: usize) -> Self {
-        ExprParser { tokens, pos: 0, span, file_id }
+    fn new(tokens: Vec<SpannedToken>, span: Range<usize>, file_id: usize) -> Self {
+        ExprParser { tokens, pos: 0, span, file_id, errors: Vec::new() }
     }
 
     fn peek(&self) -> Option<&Token> {
-        self.tokens.get(self.pos)
+        self.tokens.get(self.pos).map(|(token, _)| token)
     }
 
     fn peek_kind(&self) -> Option<TokenKind> {
         self.peek().map(token_kind)
     }
 
-    fn advance(&mut self) -> Option<Token> {
+    fn advance(&mut self) -> Option<SpannedToken> {
         if self.pos < self.tokens.len() {
             let t = self.tokens[self.pos].clone();
             self.pos += 1;
@@ -1072,33 +1177,96 @@ impl ExprParser {
         self.pos >= self.tokens.len()
     }
 
+    /// Span of the next token. At the end of the stream, the span of the last
+    /// token, so that "unexpected end" errors point at where the input stops.
+    fn next_span(&self) -> Range<usize> {
+        match self.tokens.get(self.pos).or(self.tokens.last()) {
+            Some((_, span)) => span.clone(),
+            None => self.span.clone(),
+        }
+    }
+
+    /// Span from `start` to the end of the last consumed token.
+    fn span_from(&self, start: usize) -> Range<usize> {
+        let end = match self.pos.checked_sub(1) {
+            Some(last) => self.tokens[last].1.end,
+            None => self.span.start,
+        };
+        start..end.max(start)
+    }
+
     fn error(&self, msg: impl Into<String>) -> ParseError {
-        ParseError::error(msg, self.span.clone(), self.file_id)
+        self.error_at(Code::InvalidSyntax, msg, self.span.clone())
     }
 
-    fn expect_ident(&mut self) -> Result<String, ParseError> {
-        match self.advance() {
-            Some(Token::Ident(name, _)) => Ok(name),
-            _ => Err(self.error("expected identifier")),
+    fn error_at(&self, code: Code, msg: impl Into<String>, span: Range<usize>) -> ParseError {
+        ParseError::error(code, msg, span, self.file_id)
+    }
+
+    /// Record `error` and return the node that stands in for the expression
+    /// that could not be parsed, spanning the error's location.
+    fn recover(&mut self, error: ParseError) -> Value {
+        let span = error.span.clone();
+        self.errors.push(error);
+        Value::Error(span)
+    }
+
+    /// Consume the closing delimiter `kind`. If something else comes first,
+    /// report it and skip ahead to the delimiter (or the end of the item) so
+    /// parsing can resume after it.
+    fn expect_closing(&mut self, kind: TokenKind) {
+        if self.peek_kind() == Some(kind) {
+            self.advance();
+            return;
+        }
+        let err = self.error_at(Code::InvalidSyntax, format!("expected {:?}", kind), self.next_span());
+        self.errors.push(err);
+        while let Some((token, _)) = self.advance() {
+            if token_kind(&token) == kind {
+                break;
+            }
         }
     }
 
-    fn expect_token_kind(&mut self, kind: TokenKind) -> Result<Token, ParseError> {
-        match self.advance() {
-            Some(t) if token_kind(&t) == kind => Ok(t),
-            _ => Err(self.error(format!("expected {:?}", kind))),
+    /// Report any tokens left over after a complete expression and skip them.
+    fn expect_end(&mut self, msg: &str) {
+        if let Some((_, last)) = self.tokens.last()
+            && !self.at_end()
+        {
+            let span = self.next_span().start..last.end;
+            let err = self.error_at(Code::InvalidSyntax, msg, span);
+            self.errors.push(err);
+            self.pos = self.tokens.len();
         }
     }
 
+    /// Parse a type name in an annotation. An unknown or missing type is
+    /// reported and the annotation dropped.
+    fn expect_type(&mut self) -> Option<TypeAnnotation> {
+        let span = self.next_span();
+        let err = match self.advance() {
+            Some((Token::Ident(name), _)) => match TypeAnnotation::from_name(&name) {
+                Some(annotation) => return Some(annotation),
+                None => self
+                    .error_at(Code::UnknownType, format!("unknown type '{}'", name), span)
+                    .with_note(TypeAnnotation::known_types_note()),
+            },
+            _ => self.error_at(Code::InvalidSyntax, "expected a type name after ':'", span),
+        };
+        self.errors.push(err);
+        None
+    }
+
     /// Check if the token stream is an assignment: ident = expr
-    /// (ident followed by single `=`, not `==`)
+    /// (ident followed by single `=`, not `==`), or ident: Type = expr
     fn is_assignment(&self) -> bool {
         if self.tokens.len() < 3 {
             return false;
         }
         matches!(
-            (&self.tokens[0], &self.tokens[1]),
-            (Token::Ident(..), Token::Eq)
+            self.tokens.as_slice(),
+            [(Token::Ident(..), _), (Token::Eq, _), ..]
+                | [(Token::Ident(..), _), (Token::Colon, _), (Token::Ident(..), _), (Token::Eq, _), ..]
         )
     }
 
@@ -1106,8 +1274,11 @@ impl ExprParser {
     // Pratt parser core
     // ------------------------------------------------------------------
 
-    fn parse_expr(&mut self, min_bp: u8) -> Result<Value, ParseError> {
-        let mut left = self.parse_prefix()?;
+    fn parse_expr(&mut self, min_bp: u8) -> Value {
+        // Operations span from the start of their left operand, including any
+        // parentheses around it
+        let start = self.next_span().start;
+        let mut left = self.parse_prefix();
 
         loop {
             if self.at_end() {
@@ -1125,10 +1296,10 @@ impl ExprParser {
             // Special case: conditional operator (?)
             if kind == TokenKind::Question {
                 self.advance();
-                let true_branch = self.parse_expr(0)?;
+                let true_branch = self.parse_expr(0);
                 let false_branch = if self.peek_kind() == Some(TokenKind::Colon) {
                     self.advance();
-                    Some(Box::new(self.parse_expr(0)?))
+                    Some(Box::new(self.parse_expr(0)))
                 } else {
                     None
                 };
@@ -1136,12 +1307,13 @@ impl ExprParser {
                     condition: Box::new(left),
                     true_branch: Box::new(true_branch),
                     false_branch,
+                    span: self.span_from(start),
                 };
                 continue;
             }
 
-            let op = self.advance().unwrap();
-            let right = self.parse_expr(r_bp)?;
+            let (op, operator_span) = self.advance().unwrap();
+            let right = self.parse_expr(r_bp);
 
             let operator = match token_kind(&op) {
                 TokenKind::Plus => BinaryOperator::Addition,
@@ -1157,179 +1329,247 @@ impl ExprParser {
                 TokenKind::LtEq => BinaryOperator::LessThanOrEqual,
                 TokenKind::AmpAmp => BinaryOperator::LogicalAnd,
                 TokenKind::PipePipe => BinaryOperator::LogicalOr,
-                _ => return Err(self.error("unexpected infix operator")),
+                _ => unreachable!("infix_bp only accepts binary operators and '?'"),
             };
 
             left = Value::BinaryOperation {
                 operator,
                 left: Box::new(left),
                 right: Box::new(right),
+                operator_span,
+                span: self.span_from(start),
             };
         }
 
-        Ok(left)
+        left
     }
 
-    fn parse_prefix(&mut self) -> Result<Value, ParseError> {
-        let token = self.advance().ok_or_else(|| self.error("unexpected end of expression"))?;
+    fn parse_prefix(&mut self) -> Value {
+        let Some((token, span)) = self.advance() else {
+            let err = self.error_at(Code::InvalidSyntax, "unexpected end of expression", self.next_span());
+            return self.recover(err);
+        };
 
         match token {
             // Literals
-            Token::Number(n) => Ok(Value::NumberLiteral(n)),
-            Token::StringLit(s) => self.parse_string_interpolation(s),
-            Token::True => Ok(Value::BooleanLiteral(true)),
-            Token::False => Ok(Value::BooleanLiteral(false)),
-            Token::Unit => Ok(Value::UnitLiteral),
+            Token::Number(n) => Value::NumberLiteral(n, span),
+            Token::StringLit(s) => self.parse_string_interpolation(s, span),
+            Token::True => Value::BooleanLiteral(true, span),
+            Token::False => Value::BooleanLiteral(false, span),
+            Token::Unit => Value::UnitLiteral(span),
 
             // References
-            Token::Ident(name, span) => Ok(Value::VariableReference(name, span)),
-            Token::ArgRef(n, span) => Ok(Value::PositionalArgumentReference(n, span)),
-            Token::SpreadRef => Ok(Value::SpreadArgumentReference),
+            Token::Ident(name) => Value::VariableReference(name, span),
+            Token::ArgRef(n) => Value::PositionalArgumentReference(n, span),
+            Token::SpreadRef => Value::SpreadArgumentReference(span),
+            Token::NamedArgRef(name) => Value::NamedArgumentReference(name, span),
+            Token::NamedRecordRef => Value::NamedArgumentRecord(span),
 
             // Unary operators
             Token::Bang => {
-                let operand = self.parse_expr(BP_UNARY)?;
-                Ok(Value::UnaryOperation {
+                let operand = self.parse_expr(BP_UNARY);
+                Value::UnaryOperation {
                     operator: UnaryOperator::LogicalNot,
                     operand: Box::new(operand),
-                })
+                    span: self.span_from(span.start),
+                }
             }
             Token::Minus => {
-                let operand = self.parse_expr(BP_UNARY)?;
-                Ok(Value::UnaryOperation {
+                let operand = self.parse_expr(BP_UNARY);
+                Value::UnaryOperation {
                     operator: UnaryOperator::Negation,
                     operand: Box::new(operand),
-                })
+                    span: self.span_from(span.start),
+                }
             }
 
             // Parenthesized expression
             Token::LParen => {
-                let expr = self.parse_expr(0)?;
-                self.expect_token_kind(TokenKind::RParen)?;
-                Ok(expr)
+                let expr = self.parse_expr(0);
+                self.expect_closing(TokenKind::RParen);
+                expr
             }
 
             // Bold = Print
-            Token::Bold(ts) => Ok(Value::Print(ts)),
+            Token::Bold(ts) => Value::Print(ts),
 
             // Emphasis = transparent wrapper; unwrap to inner value
             Token::Emphasis(ts) => {
                 if ts.parts.len() == 1 {
                     match &ts.parts[0] {
-                        TemplateStringPart::Expression(val) => Ok(val.clone()),
+                        TemplateStringPart::Expression(val) => val.clone(),
                         TemplateStringPart::Literal(s) => {
+                            // The text starts right after the opening `*`
                             let mut inner_tokens = Vec::new();
-                            tokenize_text(s, &mut inner_tokens, 0)
-                                .map_err(|msg| self.error(msg))?;
+                            if let Err(msg) = tokenize_text(s, &mut inner_tokens, span.start + 1) {
+                                let err = self.error_at(Code::InvalidSyntax, msg, span.clone());
+                                self.errors.push(err);
+                            }
                             merge_compound_operators(&mut inner_tokens);
-                            let mut inner = ExprParser::new(inner_tokens, self.span.clone(), self.file_id);
-                            inner.parse_expr(0)
+                            parse_tokens(inner_tokens, span, self.file_id, &mut self.errors)
                         }
                     }
                 } else {
-                    Ok(Value::Interpolation(ts))
+                    Value::Interpolation(ts)
                 }
             }
 
             // Strikethrough = null / quotation
-            Token::Strike(ts) => Ok(Value::Strikethrough(ts)),
+            Token::Strike(ts) => Value::Strikethrough(ts),
 
             // Link = block invocation [args](#block)
-            Token::Link { text_tokens, dest } => {
-                let block_ref = parse_block_reference(&dest);
-                let args = parse_argument_list(text_tokens, self.file_id, self.span.clone())?;
-                Ok(Value::BlockInvocation(args, block_ref))
+            Token::Link { text_tokens, dest, dest_offset } => {
+                parse_invocation(text_tokens, &dest, dest_offset, false, self.file_id, span, &mut self.errors)
             }
 
             // Image = evaluated block invocation ![args](#block)
-            Token::Image { text_tokens, dest } => {
-                let block_ref = parse_block_reference(&dest);
-                let args = parse_argument_list(text_tokens, self.file_id, self.span.clone())?;
-                Ok(Value::EvaluatedBlockInvocation(args, block_ref))
+            Token::Image { text_tokens, dest, dest_offset } => {
+                parse_invocation(text_tokens, &dest, dest_offset, true, self.file_id, span, &mut self.errors)
             }
 
+            // Block reference &[](#block): the block as a value, not invoked
+            Token::Amp => match self.advance() {
+                Some((Token::Link { text_tokens, dest, .. }, link_span)) => {
+                    let span = span.start..link_span.end;
+                    if !text_tokens.is_empty() {
+                        let err = self.error_at(Code::InvalidBlockReference, "block reference cannot take arguments", span);
+                        return self.recover(err);
+                    }
+                    if is_dynamic_destination(&dest) {
+                        let err = self.error_at(Code::InvalidBlockReference, "block reference must have a static destination", span);
+                        return self.recover(err);
+                    }
+                    Value::BlockRef(parse_block_reference(&dest), span)
+                }
+                _ => {
+                    let err = self.error_at(
+                        Code::InvalidBlockReference,
+                        "expected a link after '&' (e.g. &[](#block))",
+                        self.span_from(span.start),
+                    );
+                    self.recover(err)
+                }
+            },
+
             // Match expression
             Token::Match => {
-                let scrutinee = self.parse_expr(BP_UNARY)?;
+                let scrutinee = self.parse_expr(BP_UNARY);
                 // The match arms should follow as a MatchArms token
                 match self.advance() {
-                    Some(Token::MatchArms(arms)) => {
-                        self.build_match_expr(scrutinee, arms)
+                    Some((Token::MatchArms(arms), _)) => {
+                        self.build_match_expr(scrutinee, arms, span.start)
+                    }
+                    _ => {
+                        let err = self.error_at(
+                            Code::InvalidMatch,
+                            "expected match arms (unordered list) after 'match'",
+                            self.span_from(span.start),
+                        );
+                        self.recover(err)
                     }
-                    _ => Err(self.error("expected match arms (unordered list) after 'match'")),
                 }
             }
 
             // Interpolation: {expr}
             Token::LBrace => {
-                let expr = self.parse_expr(0)?;
-                self.expect_token_kind(TokenKind::RBrace)?;
-                Ok(expr)
+                let expr = self.parse_expr(0);
+                self.expect_closing(TokenKind::RBrace);
+                expr
             }
 
-            _ => Err(self.error(format!("unexpected token: {:?}", token_kind(&token)))),
+            _ => {
+                let kind = token_kind(&token);
+                let err = self.error_at(Code::InvalidSyntax, format!("unexpected token: {:?}", kind), span);
+                // Leave operators and closing delimiters for the caller, which
+                // resumes parsing from them
+                if infix_bp(kind).is_some() || matches!(kind, TokenKind::RParen | TokenKind::RBrace | TokenKind::Colon) {
+                    self.pos -= 1;
+                }
+                self.recover(err)
+            }
         }
     }
 
-    /// Parse a string that may contain {expr} interpolations.
-    fn parse_string_interpolation(&mut self, s: String) -> Result<Value, ParseError> {
-        let parts = parse_template_parts(&s, self.file_id, self.span.clone())?;
-        if parts.len() == 1 {
-            if let TemplateStringPart::Literal(_) = &parts[0] {
-                return Ok(Value::StringLiteral(s));
-            }
-        }
+    /// Parse a string literal (span includes the quotes) that may contain
+    /// {expr} interpolations.
+    fn parse_string_interpolation(&mut self, s: String, span: Range<usize>) -> Value {
+        let parts = parse_template_parts(&s, span.clone(), self.file_id, &mut self.errors);
         if parts.iter().all(|p| matches!(p, TemplateStringPart::Literal(_))) {
-            return Ok(Value::StringLiteral(s));
+            return Value::StringLiteral(s, span);
         }
-        Ok(Value::Interpolation(TemplateString { parts }))
+        Value::Interpolation(TemplateString { parts, span })
     }
 
-    /// Build a match expression from parsed arms.
+    /// Build a match expression from parsed arms. An arm with an invalid
+    /// pattern is reported and left out.
     fn build_match_expr(
-        &self,
+        &mut self,
         scrutinee: Value,
-        arms: Vec<MatchArm>,
-    ) -> Result<Value, ParseError> {
-        use crate::instruction::template::Template;
-
-        let mut parsed_arms: Vec<(Template, Value)> = Vec::new();
-        let mut otherwise: Option<(Option<String>, Box<Value>)> = None;
+        arms: Vec<RawMatchArm>,
+        start: usize,
+    ) -> Value {
+        let mut parsed_arms: Vec<MatchArm> = Vec::new();
+        let mut otherwise: Option<OtherwiseArm> = None;
 
         for arm in arms {
+            let arm_span = arm.pattern.1.start.min(arm.result.1.start)
+                ..arm.pattern.1.end.max(arm.result.1.end);
             if arm.is_otherwise {
                 // Pattern tokens may still start with "otherwise" keyword; skip it
-                let binding_idx = match arm.pattern.0.get(0) {
-                    Some(Token::Ident(s, _)) if s == "otherwise" => 1,
+                let binding_idx = match arm.pattern.0.first() {
+                    Some((Token::Ident(s), _)) if s == "otherwise" => 1,
                     _ => 0,
                 };
                 let binding = match arm.pattern.0.get(binding_idx) {
-                    Some(Token::Ident(ident, _)) => Some(ident.clone()),
-                    Some(Token::Underscore) | None => None,
-                    Some(_) => return Err(ParseError::error("expected binding", arm.pattern.1, self.file_id)),
+                    Some((Token::Ident(ident), _)) => Some(ident.clone()),
+                    Some((Token::Underscore, _)) | None => None,
+                    Some((_, span)) => {
+                        self.errors.push(ParseError::error(Code::InvalidMatch, "expected binding", span.clone(), self.file_id));
+                        None
+                    }
                 };
-                let result_value = self.parse_arm_result(arm.result.0, arm.result.1)?;
-                otherwise = Some((binding, Box::new(result_value)));
+                let result_value = self.parse_arm_result(arm.result.0, arm.result.1);
+                otherwise = Some(OtherwiseArm {
+                    binding,
+                    result: Box::new(result_value),
+                    span: arm_span,
+                });
             } else {
-                let template = parse_pattern(&arm.pattern.0, arm.pattern.1, self.file_id)?;
-                let result_value = self.parse_arm_result(arm.result.0, arm.result.1)?;
-                parsed_arms.push((template, result_value));
+                let template = parse_pattern(&arm.pattern.0, arm.pattern.1, self.file_id);
+                let result_value = self.parse_arm_result(arm.result.0, arm.result.1);
+                match template {
+                    Ok(template) => parsed_arms.push(MatchArm {
+                        pattern: template,
+                        result: result_value,
+                        span: arm_span,
+                    }),
+                    Err(err) => self.errors.push(err),
+                }
             }
         }
 
-        Ok(Value::Match {
+        Value::Match {
             value: Box::new(scrutinee),
             arms: parsed_arms,
             otherwise,
-        })
+            span: self.span_from(start),
+        }
     }
 
-    fn parse_arm_result(&self, tokens: Vec<Token>, span: Range<usize>) -> Result<Value, ParseError> {
-        let mut parser = ExprParser::new(tokens, span, self.file_id);
-        parser.parse_expr(0)
+    fn parse_arm_result(&mut self, tokens: Vec<SpannedToken>, span: Range<usize>) -> Value {
+        parse_tokens(tokens, span, self.file_id, &mut self.errors)
     }
 }
 
+/// Parse `tokens` as an expression with a fresh parser, moving its errors
+/// into `errors`. `span` covers the tokens' source.
+fn parse_tokens(tokens: Vec<SpannedToken>, span: Range<usize>, file_id: usize, errors: &mut Vec<ParseError>) -> Value {
+    let mut parser = ExprParser::new(tokens, span, file_id);
+    let value = parser.parse_expr(0);
+    errors.append(&mut parser.errors);
+    value
+}
+
 /// Infix binding powers: returns (left_bp, right_bp) or None if not infix.
 fn infix_bp(kind: TokenKind) -> Option<(u8, u8)> {
     match kind {
@@ -1352,19 +1592,28 @@ fn infix_bp(kind: TokenKind) -> Option<(u8, u8)> {
 // Template string parsing
 // ---------------------------------------------------------------------------
 
-/// Parse a string's content for {expr} interpolations.
+/// Parse a string literal's content for {expr} interpolations. `span` is the
+/// literal's token span, delimiters included; expression spans are kept
+/// inside it.
 fn parse_template_parts(
     s: &str,
-    file_id: usize,
     span: Range<usize>,
-) -> Result<Vec<TemplateStringPart>, ParseError> {
+    file_id: usize,
+    errors: &mut Vec<ParseError>,
+) -> Vec<TemplateStringPart> {
     let mut parts = Vec::new();
     let mut current_literal = String::new();
-    let chars: Vec<char> = s.chars().collect();
+    let chars: Vec<(usize, char)> = s.char_indices().collect();
+    let byte_at = |i: usize| chars.get(i).map_or(s.len(), |&(b, _)| b);
+    // The content starts after the opening delimiter
+    let place = |r: Range<usize>| {
+        let clamp = |b: usize| (span.start + 1 + b).min(span.end);
+        clamp(r.start)..clamp(r.end)
+    };
     let mut i = 0;
 
     while i < chars.len() {
-        if chars[i] == '{' {
+        if chars[i].1 == '{' {
             // Flush current literal
             if !current_literal.is_empty() {
                 parts.push(TemplateStringPart::Literal(std::mem::take(&mut current_literal)));
@@ -1374,9 +1623,9 @@ fn parse_template_parts(
             let mut depth = 1u32;
             let start = i;
             while i < chars.len() {
-                if chars[i] == '{' {
+                if chars[i].1 == '{' {
                     depth += 1;
-                } else if chars[i] == '}' {
+                } else if chars[i].1 == '}' {
                     depth -= 1;
                     if depth == 0 {
                         break;
@@ -1384,19 +1633,23 @@ fn parse_template_parts(
                 }
                 i += 1;
             }
-            let expr_str: String = chars[start..i].iter().collect();
+            let expr_range = byte_at(start)..byte_at(i);
             if i < chars.len() {
                 i += 1; // skip closing }
             }
             // Parse the expression
+            let expr_span = place(expr_range.clone());
             let mut tokens = Vec::new();
-            tokenize_text(&expr_str, &mut tokens, 0)
-                .map_err(|msg| ParseError::error(msg, span.clone(), file_id))?;
-            let mut parser = ExprParser::new(tokens, span.clone(), file_id);
-            let expr = parser.parse_expr(0)?;
+            if let Err(msg) = tokenize_text(&s[expr_range.clone()], &mut tokens, expr_range.start) {
+                errors.push(ParseError::error(Code::InvalidSyntax, msg, expr_span.clone(), file_id));
+            }
+            for (_, token_span) in &mut tokens {
+                *token_span = place(token_span.clone());
+            }
+            let expr = parse_tokens(tokens, expr_span, file_id, errors);
             parts.push(TemplateStringPart::Expression(expr));
         } else {
-            current_literal.push(chars[i]);
+            current_literal.push(chars[i].1);
             i += 1;
         }
     }
@@ -1409,7 +1662,7 @@ fn parse_template_parts(
         parts.push(TemplateStringPart::Literal(String::new()));
     }
 
-    Ok(parts)
+    parts
 }
 
 // ---------------------------------------------------------------------------
@@ -1417,8 +1670,8 @@ fn parse_template_parts(
 // ---------------------------------------------------------------------------
 
 fn parse_block_reference(dest: &str) -> BlockReference {
-    if dest.starts_with('#') {
-        BlockReference::Local(dest[1..].to_string())
+    if let Some(name) = dest.strip_prefix('#') {
+        BlockReference::Local(name.to_string())
     } else if let Some((path, block)) = dest.rsplit_once('#') {
         if path.starts_with("http://") || path.starts_with("https://") {
             BlockReference::RemoteImport {
@@ -1436,29 +1689,69 @@ fn parse_block_reference(dest: &str) -> BlockReference {
     }
 }
 
+/// Whether a link destination contains `{expr}` interpolations
+/// and must be resolved at runtime.
+fn is_dynamic_destination(dest: &str) -> bool {
+    dest.contains('{')
+}
+
+/// Build a block invocation from link text tokens and a destination found at
+/// `dest_offset`. Destinations with `{expr}` interpolations produce a dynamic
+/// invocation. `span` covers the whole link.
+fn parse_invocation(
+    text_tokens: Vec<SpannedToken>,
+    dest: &str,
+    dest_offset: usize,
+    evaluated: bool,
+    file_id: usize,
+    span: Range<usize>,
+    errors: &mut Vec<ParseError>,
+) -> Value {
+    let args = parse_argument_list(text_tokens, file_id, errors);
+    if is_dynamic_destination(dest) {
+        let target = match parse_text_template(dest, dest_offset, file_id) {
+            Ok(target) => target,
+            Err(err) => {
+                errors.push(err);
+                return Value::Error(span);
+            }
+        };
+        return if evaluated {
+            Value::EvaluatedDynamicBlockInvocation(args, target, span)
+        } else {
+            Value::DynamicBlockInvocation(args, target, span)
+        };
+    }
+    let block_ref = parse_block_reference(dest);
+    if evaluated {
+        Value::EvaluatedBlockInvocation(args, block_ref, span)
+    } else {
+        Value::BlockInvocation(args, block_ref, span)
+    }
+}
+
 /// Parse comma-separated arguments from link text tokens.
+/// Entries written `name: value` become named arguments; they must follow
+/// every positional argument and each name may appear only once. Each
+/// argument is parsed on its own, so an error in one does not hide errors in
+/// the others.
 fn parse_argument_list(
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     file_id: usize,
-    span: Range<usize>,
-) -> Result<Vec<Value>, ParseError> {
+    errors: &mut Vec<ParseError>,
+) -> Vec<Value> {
     if tokens.is_empty() {
-        return Ok(Vec::new());
+        return Vec::new();
     }
 
     // Split tokens on commas and parse each segment
-    let mut args = Vec::new();
+    let mut segments = Vec::new();
     let mut current = Vec::new();
 
     for token in tokens {
-        if matches!(token, Token::Comma) {
+        if matches!(token.0, Token::Comma) {
             if !current.is_empty() {
-                let mut parser = ExprParser::new(
-                    std::mem::take(&mut current),
-                    span.clone(),
-                    file_id,
-                );
-                args.push(parser.parse_expr(0)?);
+                segments.push(std::mem::take(&mut current));
             }
         } else {
             current.push(token);
@@ -1466,11 +1759,50 @@ fn parse_argument_list(
     }
 
     if !current.is_empty() {
-        let mut parser = ExprParser::new(current, span.clone(), file_id);
-        args.push(parser.parse_expr(0)?);
+        segments.push(current);
     }
 
-    Ok(args)
+    let mut args = Vec::new();
+    let mut names: Vec<String> = Vec::new();
+    for mut segment in segments {
+        // Segments are never empty
+        let segment_span = segment[0].1.start..segment[segment.len() - 1].1.end;
+        let named = matches!(
+            segment.as_slice(),
+            [(Token::Ident(..), _), (Token::Colon, _), _, ..]
+        );
+        if !named {
+            if !names.is_empty() && !matches!(segment.as_slice(), [(Token::NamedRecordRef, _)]) {
+                errors.push(ParseError::error(
+                    Code::InvalidArgumentList,
+                    "positional argument follows named argument",
+                    segment_span.clone(),
+                    file_id,
+                ));
+            }
+            args.push(parse_tokens(segment, segment_span, file_id, errors));
+            continue;
+        }
+
+        let value_tokens = segment.split_off(2);
+        let Some((Token::Ident(name), name_span)) = segment.into_iter().next() else {
+            unreachable!("named argument segment starts with an identifier");
+        };
+        if names.contains(&name) {
+            errors.push(ParseError::error(
+                Code::InvalidArgumentList,
+                format!("duplicate named argument '{}'", name),
+                name_span.clone(),
+                file_id,
+            ));
+        }
+        let value_span = value_tokens[0].1.start..segment_span.end;
+        let value = parse_tokens(value_tokens, value_span, file_id, errors);
+        names.push(name.clone());
+        args.push(Value::NamedArgument(name, Box::new(value), name_span.start..segment_span.end));
+    }
+
+    args
 }
 
 // ---------------------------------------------------------------------------
@@ -1478,13 +1810,13 @@ fn parse_argument_list(
 // ---------------------------------------------------------------------------
 
 fn parse_pattern(
-    tokens: &[Token],
+    tokens: &[SpannedToken],
     span: Range<usize>,
     file_id: usize,
 ) -> Result<crate::instruction::template::Template, ParseError> {
     use crate::instruction::template::Template;
     
-    let split = tokens.split(|x| matches!(x, Token::Pipe));
+    let split = tokens.split(|(x, _)| matches!(x, Token::Pipe));
     let mut templates: Vec<Template> = Vec::new();
 
     for ele in split {
@@ -1495,30 +1827,30 @@ fn parse_pattern(
         return Ok(templates.remove(0))
     }
 
-    return Ok(Template::Alternation(templates))
+    Ok(Template::Alternation(templates))
 }
 
 fn parse_single_pattern(
-    tokens: &[Token],
+    tokens: &[SpannedToken],
     span: Range<usize>,
     file_id: usize,
 ) -> Result<crate::instruction::template::Template, ParseError> {
     use crate::instruction::template::Template;
 
     match tokens {
-        [Token::Number(value)] => Ok(Template::NumberLiteral(*value)),
-        [Token::True] => Ok(Template::BooleanLiteral(true)),
-        [Token::False] => Ok(Template::BooleanLiteral(false)),
-        [Token::Unit] => Ok(Template::UnitLiteral),
-        [Token::StringLit(string)] => Ok(Template::StringLiteral(string.clone())),
-        [Token::Underscore] => Ok(Template::Wildcard),
-        [Token::Ident(ident, _span)] => Ok(Template::Binding(ident.clone())),
-        [Token::Strike(ts)] => {
+        [(Token::Number(value), _)] => Ok(Template::NumberLiteral(*value)),
+        [(Token::True, _)] => Ok(Template::BooleanLiteral(true)),
+        [(Token::False, _)] => Ok(Template::BooleanLiteral(false)),
+        [(Token::Unit, _)] => Ok(Template::UnitLiteral),
+        [(Token::StringLit(string), _)] => Ok(Template::StringLiteral(string.clone())),
+        [(Token::Underscore, _)] => Ok(Template::Wildcard),
+        [(Token::Ident(ident), _)] => Ok(Template::Binding(ident.clone())),
+        [(Token::Strike(ts), _)] => {
             // Strikethrough pattern: ~~binding~~ or ~~literal~~
             let inner = template_to_pattern_binding(ts);
             Ok(Template::Strikethrough(inner.map(Box::new)))
         }
-        [Token::Bold(ts)] => {
+        [(Token::Bold(ts), _)] => {
             // Bold pattern: **{binding}** or **literal**
             let inner = template_to_inline_patterns(ts);
             Ok(Template::DocumentPattern(
@@ -1527,7 +1859,7 @@ fn parse_single_pattern(
                 ),
             ))
         }
-        [Token::Emphasis(ts)] => {
+        [(Token::Emphasis(ts), _)] => {
             // Emphasis pattern: *{binding}* or *literal*
             let inner = template_to_inline_patterns(ts);
             Ok(Template::DocumentPattern(
@@ -1536,9 +1868,7 @@ fn parse_single_pattern(
                 ),
             ))
         }
-        _ => Err(ParseError::error(
-            "expected pattern", span, file_id
-        ))
+        _ => Err(ParseError::error(Code::InvalidMatch, "expected pattern", span, file_id))
     }
 }
 
@@ -1563,8 +1893,6 @@ fn template_to_pattern_binding(
             }
             _ => None,
         }
-    } else if ts.parts.is_empty() {
-        None
     } else {
         None
     }
@@ -1,6 +1,6 @@
 pub mod error;
 pub mod expression;
-mod structural;
+pub(crate) mod structural;
 
 pub use error::ParseError;
 
@@ -17,12 +17,29 @@ impl Parser {
         Parser { source, file_id }
     }
 
-    /// Parse the source Markdown into a complete Program.
-    pub fn parse(&self) -> Result<Program, Vec<ParseError>> {
-        let blocks = structural::parse_blocks(&self.source, self.file_id)?;
-        Ok(Program {
+    /// Parse the source Markdown into a complete Program. Warnings do not
+    /// stop parsing: the program is returned with the warnings found in it.
+    /// If the source has any error, fails with every diagnostic instead.
+    pub fn parse(&self) -> Result<(Program, Vec<ParseError>), Vec<ParseError>> {
+        let (program, diagnostics) = self.parse_recovering();
+        if diagnostics.iter().all(ParseError::is_warning) {
+            Ok((program, diagnostics))
+        } else {
+            Err(diagnostics)
+        }
+    }
+
+    /// Parse the source Markdown, recovering from errors. The program is
+    /// always returned; expressions that failed to parse appear in it as
+    /// `Value::Error` nodes, and the errors and warnings are returned
+    /// alongside it. Useful for tooling that must keep working on broken files.
+    pub fn parse_recovering(&self) -> (Program, Vec<ParseError>) {
+        let (blocks, suppressions, errors) = structural::parse_blocks(&self.source, self.file_id);
+        let program = Program {
             blocks,
             source_id: self.file_id,
-        })
+            suppressions,
+        };
+        (program, errors)
     }
 }
@@ -2,12 +2,16 @@ use std::ops::Range;
 
 use pulldown_cmark::{Event, HeadingLevel, Options, Parser as CmarkParser, Tag, TagEnd};
 
-use crate::block::Block;
+use crate::block::{Block, Parameter};
 use crate::chain::Chain;
+use crate::codes::Code;
+use crate::lint::{self, Suppression};
 use crate::chain::fence_group::FenceGroup;
 use crate::document::{
     ColumnAlignment, Document, DocumentNode, InlineNode,
 };
+use crate::instruction::types::TypeAnnotation;
+use crate::instruction::value::Value;
 use crate::parser::error::ParseError;
 use crate::parser::expression;
 
@@ -15,19 +19,26 @@ use crate::parser::expression;
 // Public API
 // ---------------------------------------------------------------------------
 
-/// Parse Markdown source text into a list of top-level blocks.
+/// Parse Markdown source text into a list of top-level blocks, the
+/// `<!-- mdl-allow: ... -->` suppressions found between them, and every error
+/// found along the way. Parsing never stops at the first error: instructions
+/// that fail to parse are kept with `Value::Error` placeholders.
 pub fn parse_blocks(
     source: &str,
     file_id: usize,
-) -> Result<Vec<Block>, Vec<ParseError>> {
+) -> (Vec<Block>, Vec<Suppression>, Vec<ParseError>) {
     let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TABLES;
     let parser = CmarkParser::new_ext(source, options);
+    let raw_events: Vec<(Event<'_>, Range<usize>)> = parser.into_offset_iter().collect();
+    let mut suppression_errors = Vec::new();
+    let suppressions = collect_suppressions(&raw_events, source, file_id, &mut suppression_errors);
+
     // The mdl language doesn't use HTML. Neutralize HTML events so that
     // characters like `<` are kept as plain text instead of being consumed
     // as HTML tags by pulldown-cmark, then merge adjacent Text events that
     // may have been split across event boundaries.
-    let raw_events: Vec<(Event<'_>, Range<usize>)> = parser
-        .into_offset_iter()
+    let raw_events: Vec<(Event<'_>, Range<usize>)> = raw_events
+        .into_iter()
         .map(|(event, range)| {
             let event = match event {
                 Event::Html(s) | Event::InlineHtml(s) => Event::Text(s),
@@ -39,8 +50,72 @@ pub fn parse_blocks(
     let events = merge_adjacent_text_events(raw_events);
 
     let mut state = ParseState::new(source, file_id);
-    state.process_events(&events)?;
-    state.finalize()
+    state.errors = suppression_errors;
+    state.process_events(&events);
+    let (blocks, errors) = state.finalize();
+    (blocks, suppressions, errors)
+}
+
+/// Find `<!-- mdl-allow: ... -->` HTML blocks and the source each one
+/// covers: the list item or heading section directly after it (further
+/// comments in between are skipped).
+fn collect_suppressions(
+    events: &[SpannedEvent<'_>],
+    source: &str,
+    file_id: usize,
+    errors: &mut Vec<ParseError>,
+) -> Vec<Suppression> {
+    let mut suppressions = Vec::new();
+    for (idx, (event, range)) in events.iter().enumerate() {
+        if !matches!(event, Event::Start(Tag::HtmlBlock)) {
+            continue;
+        }
+        let comment = source[range.clone()].trim();
+        let codes = match lint::parse_allow_comment(comment) {
+            None => continue,
+            Some(Ok(codes)) => codes,
+            Some(Err(message)) => {
+                errors.push(ParseError::error(Code::InvalidSuppression, message, range.clone(), file_id));
+                continue;
+            }
+        };
+
+        let mut next = events[idx + 1..].iter().filter(|(e, _)| {
+            !matches!(e, Event::Start(Tag::HtmlBlock) | Event::Html(_) | Event::End(TagEnd::HtmlBlock))
+        });
+        let span = match next.next() {
+            Some((Event::Start(Tag::List(Some(_))), _)) => match next.next() {
+                Some((Event::Start(Tag::Item), item)) => Some(item.clone()),
+                _ => None,
+            },
+            Some((Event::Start(Tag::Heading { level, .. }), heading)) => {
+                let level = heading_level_to_u8(level);
+                // The section ends at the next heading of the same or a higher level
+                let end = events
+                    .iter()
+                    .find(|(e, r)| {
+                        r.start > heading.start
+                            && matches!(e, Event::Start(Tag::Heading { level: l, .. }) if heading_level_to_u8(l) <= level)
+                    })
+                    .map_or(source.len(), |(_, r)| r.start);
+                Some(heading.start..end)
+            }
+            _ => None,
+        };
+        match span {
+            Some(span) => suppressions.push(Suppression { codes, span }),
+            None => errors.push(
+                ParseError::error(
+                    Code::InvalidSuppression,
+                    "`mdl-allow` comment is not followed by an instruction or heading",
+                    range.clone(),
+                    file_id,
+                )
+                .with_note("place the comment on its own line directly before the list item or heading"),
+            ),
+        }
+    }
+    suppressions
 }
 
 /// Merge consecutive `Event::Text` events into a single event with a combined
@@ -51,15 +126,15 @@ fn merge_adjacent_text_events(events: Vec<(Event<'_>, Range<usize>)>) -> Vec<(Ev
     let mut merged: Vec<(Event<'_>, Range<usize>)> = Vec::with_capacity(events.len());
 
     for (event, range) in events {
-        if let Event::Text(ref new_text) = event {
-            if let Some((Event::Text(prev_text), prev_range)) = merged.last_mut() {
-                // Extend the previous text event
-                let mut combined = prev_text.to_string();
-                combined.push_str(new_text);
-                *prev_text = combined.into();
-                prev_range.end = range.end;
-                continue;
-            }
+        if let Event::Text(ref new_text) = event
+            && let Some((Event::Text(prev_text), prev_range)) = merged.last_mut()
+        {
+            // Extend the previous text event
+            let mut combined = prev_text.to_string();
+            combined.push_str(new_text);
+            *prev_text = combined.into();
+            prev_range.end = range.end;
+            continue;
         }
         merged.push((event, range));
     }
@@ -71,6 +146,8 @@ fn merge_adjacent_text_events(events: Vec<(Event<'_>, Range<usize>)>) -> Vec<(Ev
 // Parse state
 // ---------------------------------------------------------------------------
 
+type SpannedEvent<'a> = (Event<'a>, Range<usize>);
+
 struct ParseState<'a> {
     source: &'a str,
     file_id: usize,
@@ -78,23 +155,44 @@ struct ParseState<'a> {
     block_stack: Vec<BlockBuilder>,
     /// Completed top-level blocks.
     top_blocks: Vec<Block>,
+    /// Source range of the content before the first heading, which belongs
+    /// to no block.
+    preamble: Option<Range<usize>>,
     errors: Vec<ParseError>,
 }
 
 struct BlockBuilder {
     name: String,
     level: u8,
+    parameters: Option<Vec<Parameter>>,
     chain_groups: Vec<FenceGroup>,
     children: Vec<Block>,
     body_nodes: Vec<DocumentNode>,
+    /// Span of the block's latest ordered list.
+    instruction_list: Option<Range<usize>>,
+    /// `Parameter` tables in the body, settled when the block is closed.
+    parameter_tables: Vec<ParameterTable>,
     span_start: usize,
 }
 
+/// A body table headed `Parameter`. It declares the block's parameters only
+/// if the block has an instruction chain; in a documentation block it stays
+/// ordinary body content.
+struct ParameterTable {
+    /// Index of the table in the block's body nodes.
+    body_index: usize,
+    parameters: Vec<Parameter>,
+    /// Errors from reading the table, reported only if it is a declaration.
+    errors: Vec<ParseError>,
+    span: Range<usize>,
+}
+
 impl BlockBuilder {
     fn into_block(self, span_end: usize) -> Block {
         Block {
             name: self.name,
             level: self.level,
+            parameters: self.parameters,
             chain: if self.chain_groups.is_empty() {
                 Chain::empty()
             } else {
@@ -118,19 +216,22 @@ impl<'a> ParseState<'a> {
             file_id,
             block_stack: Vec::new(),
             top_blocks: Vec::new(),
+            preamble: None,
             errors: Vec::new(),
         }
     }
 
-    fn process_events(
-        &mut self,
-        events: &[(Event<'_>, Range<usize>)],
-    ) -> Result<(), Vec<ParseError>> {
+    fn process_events(&mut self, events: &[(Event<'_>, Range<usize>)]) {
         let mut i = 0;
 
         while i < events.len() {
             let (ref ev, ref range) = events[i];
 
+            if self.block_stack.is_empty() && is_body_content(ev) {
+                let preamble = self.preamble.get_or_insert(range.clone());
+                preamble.end = range.end;
+            }
+
             match ev {
                 Event::Start(Tag::Heading { level, .. }) => {
                     let heading_level = heading_level_to_u8(level);
@@ -139,6 +240,12 @@ impl<'a> ParseState<'a> {
                     i += 1;
                     let name = collect_heading_text(events, &mut i);
 
+                    // Split off an optional parameter list: `Add (a, b = 1)`
+                    let (name, parameters) = self.split_heading_parameters(name, range);
+                    if let Some(params) = &parameters {
+                        self.check_parameters(params);
+                    }
+
                     // Normalize: strip leading/trailing whitespace, collapse interior whitespace
                     let name = normalize_block_name(&name);
 
@@ -149,9 +256,12 @@ impl<'a> ParseState<'a> {
                     self.block_stack.push(BlockBuilder {
                         name,
                         level: heading_level,
+                        parameters,
                         chain_groups: Vec::new(),
                         children: Vec::new(),
                         body_nodes: Vec::new(),
+                        instruction_list: None,
+                        parameter_tables: Vec::new(),
                         span_start: range.start,
                     });
                 }
@@ -159,7 +269,7 @@ impl<'a> ParseState<'a> {
                 // Ordered list = instruction chain
                 Event::Start(Tag::List(Some(_start_num))) => {
                     i += 1;
-                    self.process_ordered_list(events, &mut i)?;
+                    self.process_ordered_list(events, &mut i, range);
                 }
 
                 // Unordered list outside instruction context = body content
@@ -212,7 +322,15 @@ impl<'a> ParseState<'a> {
                         })
                         .collect();
                     i += 1;
+                    let table_start = i;
                     let (headers, rows) = self.collect_table(events, &mut i);
+
+                    // A `Parameter | Default` table may declare the block's parameters
+                    if is_parameter_table(&headers) && !self.block_stack.is_empty() {
+                        let table = self.read_parameter_table(&events[table_start..i], range);
+                        self.block_stack.last_mut().unwrap().parameter_tables.push(table);
+                    }
+
                     if let Some(builder) = self.block_stack.last_mut() {
                         builder.body_nodes.push(DocumentNode::Table {
                             alignments: aligns,
@@ -244,17 +362,21 @@ impl<'a> ParseState<'a> {
                 }
             }
         }
-
-        Ok(())
     }
 
     /// Process an ordered list: extract fence indices and instructions.
+    /// Adjacent items with the same number form a fence group; anything else
+    /// starts a new group, so groups stay in source order. A list that follows
+    /// another one with only comments in between (such as `mdl-allow`) simply
+    /// continues it. Numbers that go down, and lists split by other content,
+    /// are reported as warnings.
     fn process_ordered_list(
         &mut self,
         events: &[(Event<'_>, Range<usize>)],
         i: &mut usize,
-    ) -> Result<(), Vec<ParseError>> {
-        let mut items: Vec<(u64, Vec<(Event<'_>, Range<usize>)>)> = Vec::new();
+        list: &Range<usize>,
+    ) {
+        let mut items: Vec<(u64, Vec<SpannedEvent<'_>>)> = Vec::new();
 
         while *i < events.len() {
             let (ref ev, ref range) = events[*i];
@@ -280,9 +402,32 @@ impl<'a> ParseState<'a> {
 
         // Group items by fence index into FenceGroups
         let Some(builder) = self.block_stack.last_mut() else {
-            return Ok(());
+            return;
         };
 
+        let split = builder
+            .instruction_list
+            .replace(list.clone())
+            .filter(|previous| !is_blank_or_comments(&self.source[previous.end.min(list.start)..list.start]));
+        // Groups are never continued across other content
+        let mut first_group = 0;
+        if let Some(previous) = split {
+            let first_line = |span: &Range<usize>| {
+                span.start..span.start + self.source[span.clone()].find('\n').unwrap_or(span.len())
+            };
+            self.errors.push(
+                ParseError::warning(
+                    Code::SplitInstructionList,
+                    format!("instructions of block '{}' are split by other content", builder.name),
+                    first_line(list),
+                    self.file_id,
+                )
+                .with_secondary(first_line(&previous), "previous instruction list")
+                .with_note("the lists run as one chain in source order; keep a block's instructions in a single list"),
+            );
+            first_group = builder.chain_groups.len();
+        }
+
         for (fence_index, item_events) in items {
             // Parse the item events into an Instruction
             let span = if let Some((_, r)) = item_events.first() {
@@ -291,29 +436,50 @@ impl<'a> ParseState<'a> {
                 0..0
             };
 
-            match expression::parse_instruction(&item_events, self.source, span.clone(), self.file_id) {
-                Ok(instruction) => {
-                    // Find or create the FenceGroup for this index
-                    if let Some(group) = builder
-                        .chain_groups
-                        .last_mut()
-                        .filter(|g| g.index == fence_index)
-                    {
-                        group.instructions.push(instruction);
-                    } else {
-                        builder.chain_groups.push(FenceGroup {
-                            index: fence_index,
-                            instructions: vec![instruction],
-                        });
-                    }
-                }
-                Err(err) => {
-                    self.errors.push(err);
-                }
+            // Instructions with syntax errors are kept so later passes still
+            // see the whole chain
+            let (instruction, errors) =
+                expression::parse_instruction(&item_events, self.source, span.clone(), self.file_id);
+            self.errors.extend(errors);
+
+            // Join the previous item's FenceGroup, or start a new one
+            if let Some(group) = builder.chain_groups[first_group..]
+                .last_mut()
+                .filter(|g| g.index == fence_index)
+            {
+                group.instructions.push(instruction);
+                continue;
             }
+            // A split list is already reported; its numbers are only checked against each other
+            let earlier = builder.chain_groups[first_group..].iter().find(|g| g.index == fence_index);
+            let previous = builder.chain_groups[first_group..].last().map(|g| g.index);
+            if let Some(earlier) = earlier {
+                self.errors.push(
+                    ParseError::warning(
+                        Code::FenceOrder,
+                        format!("instruction number {} repeats an earlier fence", fence_index),
+                        span.clone(),
+                        self.file_id,
+                    )
+                    .with_secondary(earlier.instructions[0].span().clone(), format!("fence {} is here", fence_index))
+                    .with_note("only adjacent items with the same number share a fence; this one runs after the items above it"),
+                );
+            } else if let Some(previous) = previous.filter(|&previous| fence_index < previous) {
+                self.errors.push(
+                    ParseError::warning(
+                        Code::FenceOrder,
+                        format!("instruction number {} is lower than the previous number {}", fence_index, previous),
+                        span.clone(),
+                        self.file_id,
+                    )
+                    .with_note("instructions run in source order, whatever their numbers"),
+                );
+            }
+            builder.chain_groups.push(FenceGroup {
+                index: fence_index,
+                instructions: vec![instruction],
+            });
         }
-
-        Ok(())
     }
 
     /// Collect all events for a single list item until End(Item).
@@ -422,6 +588,216 @@ impl<'a> ParseState<'a> {
         inlines
     }
 
+    /// Split an optional trailing parameter list off heading text:
+    /// `Add (a: Number, b = 1)` → (`Add`, [a, b]). The list is located in the
+    /// raw heading source and only recognized when every entry starts with a
+    /// plain identifier, optionally followed by `: Type` where the type name is
+    /// capitalized; otherwise the parentheses stay part of the block name.
+    fn split_heading_parameters(
+        &mut self,
+        text: String,
+        heading: &Range<usize>,
+    ) -> (String, Option<Vec<Parameter>>) {
+        let raw = self.source[heading.clone()].lines().next().unwrap_or("");
+        let line = strip_closing_hashes(raw);
+        let Some(open) = parameter_list_start(line) else {
+            return (text, None);
+        };
+        let list = &line[open..];
+        // The heading text must end with the list verbatim (no inline markup inside it)
+        let Some(name) = text.trim_end().strip_suffix(list) else {
+            return (text, None);
+        };
+
+        let inner_start = heading.start + open + 1;
+        let inner = &list[1..list.len() - 1];
+        // `()` declares that the block takes no parameters
+        if inner.trim().is_empty() {
+            return (name.to_string(), Some(Vec::new()));
+        }
+        let mut parameters = Vec::new();
+        for (offset, entry) in split_top_level(inner, ',') {
+            let (name_part, default_part) = match split_default(entry) {
+                Some(eq) => (&entry[..eq], Some((eq + 1, &entry[eq + 1..]))),
+                None => (entry, None),
+            };
+            let (name_part, type_part) = match name_part.split_once(':') {
+                Some((name, ty)) => (name, Some((name.len() + 1, ty))),
+                None => (name_part, None),
+            };
+            let param_name = name_part.trim();
+            let start = inner_start + offset + (name_part.len() - name_part.trim_start().len());
+            let annotation = type_part.and_then(|(colon_offset, ty)| {
+                let ty_start = inner_start + offset + colon_offset + (ty.len() - ty.trim_start().len());
+                self.parse_annotation(ty.trim(), ty_start)
+            });
+            let default = default_part.map(|(eq_offset, expr)| {
+                let expr_start = inner_start + offset + eq_offset + (expr.len() - expr.trim_start().len());
+                self.parse_default(expr.trim(), expr_start)
+            });
+            parameters.push(Parameter {
+                name: param_name.to_string(),
+                annotation,
+                default,
+                span: start..start + param_name.len(),
+            });
+        }
+
+        (name.to_string(), Some(parameters))
+    }
+
+    /// Read parameters from a `Parameter | Type | Default` table in the
+    /// current block. `Type` and `Default` columns are optional and located by
+    /// header name; any other columns (e.g. a description) are ignored.
+    fn read_parameter_table(&mut self, events: &[SpannedEvent<'_>], table: &Range<usize>) -> ParameterTable {
+        let errors_before = self.errors.len();
+        let mut parameters = Vec::new();
+        let mut in_head = false;
+        let mut headers: Vec<String> = Vec::new();
+        let mut cells: Vec<Range<usize>> = Vec::new();
+        let mut type_column = None;
+        let mut default_column = None;
+
+        for (ev, range) in events {
+            match ev {
+                Event::Start(Tag::TableHead) => in_head = true,
+                Event::End(TagEnd::TableHead) => {
+                    in_head = false;
+                    type_column = headers.iter().position(|h| h == "type");
+                    default_column = headers.iter().position(|h| h == "default");
+                }
+                Event::Start(Tag::TableRow) => cells.clear(),
+                Event::Start(Tag::TableCell) if in_head => {
+                    headers.push(trimmed_cell(self.source, range).0.to_ascii_lowercase());
+                }
+                Event::Start(Tag::TableCell) => cells.push(range.clone()),
+                Event::End(TagEnd::TableRow) if !in_head => {
+                    let Some(name_cell) = cells.first() else {
+                        continue;
+                    };
+                    let (name, start) = trimmed_cell(self.source, name_cell);
+                    if !is_identifier(name) {
+                        self.errors.push(ParseError::error(
+                            Code::InvalidParameter,
+                            format!("invalid parameter name '{}'", name),
+                            start..start + name.len(),
+                            self.file_id,
+                        ));
+                        continue;
+                    }
+                    let annotation = type_column.and_then(|col| cells.get(col)).and_then(|cell| {
+                        let (ty, ty_start) = trimmed_cell(self.source, cell);
+                        if ty.is_empty() {
+                            None
+                        } else {
+                            self.parse_annotation(ty, ty_start)
+                        }
+                    });
+                    let default = default_column.and_then(|col| cells.get(col)).and_then(|cell| {
+                        let (expr, expr_start) = trimmed_cell(self.source, cell);
+                        if expr.is_empty() {
+                            None
+                        } else {
+                            Some(self.parse_default(expr, expr_start))
+                        }
+                    });
+                    parameters.push(Parameter {
+                        name: name.to_string(),
+                        annotation,
+                        default,
+                        span: start..start + name.len(),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        self.check_parameters(&parameters);
+        ParameterTable {
+            body_index: self.block_stack.last().map_or(0, |builder| builder.body_nodes.len()),
+            parameters,
+            errors: self.errors.split_off(errors_before),
+            span: table.start..table.start + self.source[table.clone()].trim_end().len(),
+        }
+    }
+
+    /// Build a finished block, settling its parameter tables: in a block with
+    /// an instruction chain they declare its parameters and leave the body.
+    fn close_block(&mut self, mut builder: BlockBuilder, span_end: usize) -> Block {
+        let tables = std::mem::take(&mut builder.parameter_tables);
+        if !builder.chain_groups.is_empty() {
+            for table in tables.iter().rev() {
+                builder.body_nodes.remove(table.body_index);
+            }
+            for table in tables {
+                if builder.parameters.is_some() {
+                    self.errors.push(
+                        ParseError::error(
+                            Code::InvalidParameter,
+                            format!("block '{}' declares parameters more than once", builder.name),
+                            table.span,
+                            self.file_id,
+                        )
+                        .with_note("declare parameters either in the heading or in one parameter table"),
+                    );
+                } else {
+                    self.errors.extend(table.errors);
+                    builder.parameters = Some(table.parameters);
+                }
+            }
+        }
+        builder.into_block(span_end)
+    }
+
+    fn parse_annotation(&mut self, text: &str, start: usize) -> Option<TypeAnnotation> {
+        let annotation = TypeAnnotation::from_name(text);
+        if annotation.is_none() {
+            self.errors.push(
+                ParseError::error(
+                    Code::UnknownType,
+                    format!("unknown type '{}'", text),
+                    start..start + text.len(),
+                    self.file_id,
+                )
+                .with_note(TypeAnnotation::known_types_note()),
+            );
+        }
+        annotation
+    }
+
+    fn parse_default(&mut self, text: &str, start: usize) -> Value {
+        let (value, errors) = expression::parse_expression_text(text, start..start + text.len(), self.file_id);
+        self.errors.extend(errors);
+        value
+    }
+
+    /// Reject duplicate names and required parameters that follow optional ones.
+    fn check_parameters(&mut self, params: &[Parameter]) {
+        for (idx, param) in params.iter().enumerate() {
+            if params[..idx].iter().any(|p| p.name == param.name) {
+                self.errors.push(ParseError::error(
+                    Code::InvalidParameter,
+                    format!("duplicate parameter '{}'", param.name),
+                    param.span.clone(),
+                    self.file_id,
+                ));
+            }
+        }
+        if let Some(first_optional) = params.iter().position(|p| p.default.is_some())
+            && let Some(required) = params[first_optional..].iter().find(|p| p.default.is_none())
+        {
+            self.errors.push(
+                ParseError::error(
+                    Code::InvalidParameter,
+                    format!("required parameter '{}' follows a parameter with a default", required.name),
+                    required.span.clone(),
+                    self.file_id,
+                )
+                .with_note("parameters with defaults must come last"),
+            );
+        }
+    }
+
     /// Collect table headers and rows.
     fn collect_table(
         &self,
@@ -562,7 +938,7 @@ impl<'a> ParseState<'a> {
         while let Some(top) = self.block_stack.last() {
             if top.level >= new_level {
                 let builder = self.block_stack.pop().unwrap();
-                let block = builder.into_block(span_end);
+                let block = self.close_block(builder, span_end);
 
                 if let Some(parent) = self.block_stack.last_mut() {
                     // This block becomes a child of the parent
@@ -577,12 +953,20 @@ impl<'a> ParseState<'a> {
         }
     }
 
-    fn finalize(mut self) -> Result<Vec<Block>, Vec<ParseError>> {
+    fn finalize(mut self) -> (Vec<Block>, Vec<ParseError>) {
         let end = self.source.len();
 
+        if let Some(mut preamble) = self.preamble.take() {
+            preamble.end = preamble.start + self.source[preamble.clone()].trim_end().len();
+            self.errors.push(
+                ParseError::warning(Code::ContentOutsideBlock, "content outside any block is ignored", preamble, self.file_id)
+                    .with_note("start the file with a heading, or move this content below one"),
+            );
+        }
+
         // Close all remaining blocks
         while let Some(builder) = self.block_stack.pop() {
-            let block = builder.into_block(end);
+            let block = self.close_block(builder, end);
             if let Some(parent) = self.block_stack.last_mut() {
                 parent.children.push(block);
             } else {
@@ -590,11 +974,7 @@ impl<'a> ParseState<'a> {
             }
         }
 
-        if self.errors.is_empty() {
-            Ok(self.top_blocks)
-        } else {
-            Err(self.errors)
-        }
+        (self.top_blocks, self.errors)
     }
 }
 
@@ -602,6 +982,28 @@ impl<'a> ParseState<'a> {
 // Helpers
 // ---------------------------------------------------------------------------
 
+/// Whether `text` holds nothing but whitespace and HTML comments.
+pub(crate) fn is_blank_or_comments(text: &str) -> bool {
+    let mut rest = text.trim_start();
+    while let Some(comment) = rest.strip_prefix("<!--") {
+        let Some(end) = comment.find("-->") else {
+            return false;
+        };
+        rest = comment[end + 3..].trim_start();
+    }
+    rest.is_empty()
+}
+
+/// Whether `event` starts a top-level element that belongs to the enclosing
+/// block: an instruction list or body content.
+fn is_body_content(event: &Event<'_>) -> bool {
+    matches!(
+        event,
+        Event::Start(Tag::Paragraph | Tag::List(_) | Tag::CodeBlock(_) | Tag::Table(_) | Tag::BlockQuote(_))
+            | Event::Rule
+    )
+}
+
 fn heading_level_to_u8(level: &HeadingLevel) -> u8 {
     match level {
         HeadingLevel::H1 => 1,
@@ -639,8 +1041,130 @@ fn collect_heading_text(events: &[(Event<'_>, Range<usize>)], i: &mut usize) ->
     name
 }
 
+/// Strip an ATX closing sequence (`## Add (a) ##`) and trailing whitespace.
+pub(crate) fn strip_closing_hashes(line: &str) -> &str {
+    let line = line.trim_end();
+    let stripped = line.trim_end_matches('#');
+    if stripped.len() < line.len() && stripped.ends_with(char::is_whitespace) {
+        stripped.trim_end()
+    } else {
+        line
+    }
+}
+
+/// Find the byte offset of the `(` whose matching `)` ends `line`.
+fn find_trailing_group(line: &str) -> Option<usize> {
+    if !line.ends_with(')') {
+        return None;
+    }
+    let mut opens = Vec::new();
+    let mut in_string = false;
+    for (idx, c) in line.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' if !in_string => opens.push(idx),
+            ')' if !in_string => {
+                let open = opens.pop()?;
+                if idx == line.len() - 1 {
+                    return Some(open);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Byte offset of the parameter list ending a heading line, e.g. the `(` of
+/// `Add (a: Number, b = 1)`. The list is only recognized when every entry
+/// starts with a plain identifier, optionally followed by `: Type` where the
+/// type name is capitalized; otherwise the parentheses are part of the name.
+pub(crate) fn parameter_list_start(line: &str) -> Option<usize> {
+    let open = find_trailing_group(line)?;
+    let inner = &line[open + 1..line.len() - 1];
+    if inner.trim().is_empty() {
+        return Some(open);
+    }
+    let valid = split_top_level(inner, ',').into_iter().all(|(_, entry)| {
+        let name_part = split_default(entry).map_or(entry, |eq| &entry[..eq]);
+        match name_part.split_once(':') {
+            Some((name, ty)) => is_identifier(name.trim()) && is_type_name(ty.trim()),
+            None => is_identifier(name_part.trim()),
+        }
+    });
+    valid.then_some(open)
+}
+
+/// Split `text` at `sep` characters outside strings and brackets,
+/// yielding each piece with its byte offset.
+fn split_top_level(text: &str, sep: char) -> Vec<(usize, &str)> {
+    let mut pieces = Vec::new();
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut start = 0;
+    for (idx, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '[' | '{' if !in_string => depth += 1,
+            ')' | ']' | '}' if !in_string => depth = depth.saturating_sub(1),
+            c if c == sep && !in_string && depth == 0 => {
+                pieces.push((start, &text[start..idx]));
+                start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    pieces.push((start, &text[start..]));
+    pieces
+}
+
+/// Byte offset of the `=` separating a parameter from its default, if any.
+fn split_default(entry: &str) -> Option<usize> {
+    let bytes = entry.as_bytes();
+    let eq = entry.find('=')?;
+    // `==`, `<=` etc. can only appear inside the default itself
+    (bytes.get(eq + 1) != Some(&b'=')).then_some(eq)
+}
+
+/// Trimmed source text of a table cell and its starting byte offset.
+fn trimmed_cell<'s>(source: &'s str, cell: &Range<usize>) -> (&'s str, usize) {
+    let raw = source[cell.clone()].trim_end().trim_end_matches('|');
+    let text = raw.trim_start().trim_start_matches('|');
+    let lead = raw.len() - text.len();
+    let text_trimmed = text.trim_start();
+    (text_trimmed.trim_end(), cell.start + lead + (text.len() - text_trimmed.len()))
+}
+
+/// Whether a table header marks a parameter declaration table.
+fn is_parameter_table(headers: &[Vec<InlineNode>]) -> bool {
+    let header_text = |cell: &Vec<InlineNode>| -> String {
+        cell.iter()
+            .map(|node| match node {
+                InlineNode::Text(s) => s.as_str(),
+                _ => "",
+            })
+            .collect::<String>()
+            .trim()
+            .to_ascii_lowercase()
+    };
+    matches!(headers.first().map(header_text).as_deref(), Some("parameter" | "parameters"))
+}
+
+/// Whether `s` looks like a type name in an annotation (a capitalized identifier).
+fn is_type_name(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_uppercase()) && is_identifier(s)
+}
+
+/// Whether `s` is a valid variable name (same rules as the expression tokenizer).
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_')
+        && !matches!(s, "_" | "true" | "false" | "match")
+}
+
 /// Normalize block name: strip leading/trailing whitespace, collapse interior whitespace.
-fn normalize_block_name(name: &str) -> String {
+pub(crate) fn normalize_block_name(name: &str) -> String {
     name.split_whitespace().collect::<Vec<_>>().join(" ")
 }
 
@@ -704,10 +1228,10 @@ fn extract_item_number(source: &str, item_start: usize) -> u64 {
 fn parse_list_marker(text: &str) -> Option<u64> {
     let trimmed = text.trim();
     for sep in ['.', ')'] {
-        if let Some(pos) = trimmed.find(sep) {
-            if let Ok(n) = trimmed[..pos].parse::<u64>() {
-                return Some(n);
-            }
+        if let Some(pos) = trimmed.find(sep)
+            && let Ok(n) = trimmed[..pos].parse::<u64>()
+        {
+            return Some(n);
         }
     }
     None
@@ -0,0 +1,85 @@
+/// Levenshtein distance between two strings, counted in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The candidate most likely meant by `name`: the closest one by
+/// case-insensitive edit distance, within a third of the name's length (at
+/// least one edit). A candidate differing only in case always wins, since
+/// block lookups are case-sensitive except for the entry point.
+/// Ties go to the alphabetically first candidate.
+pub fn best_match<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    let lower = name.to_lowercase();
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|candidate| *candidate != name)
+        .map(|candidate| (edit_distance(&lower, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= limit)
+        .min()
+        .map(|(_, candidate)| candidate)
+}
+
+/// A "did you mean" note for `name`, if any candidate is close enough.
+pub fn did_you_mean<'a>(name: &str, candidates: impl IntoIterator<Item = &'a str>) -> Option<String> {
+    best_match(name, candidates).map(|candidate| format!("did you mean '{}'?", candidate))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_counts_characters() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("héllo", "hello"), 1);
+        assert_eq!(edit_distance("Main", "main"), 1);
+    }
+
+    #[test]
+    fn best_match_stays_within_a_third_of_the_name() {
+        // One edit is always allowed, even for short names
+        assert_eq!(best_match("ab", ["ax"]), Some("ax"));
+        assert_eq!(best_match("ab", ["xy"]), None);
+        // Six characters allow two edits, not three
+        assert_eq!(best_match("Helper", ["Helpxx"]), Some("Helpxx"));
+        assert_eq!(best_match("Helper", ["Hexxxx"]), None);
+    }
+
+    #[test]
+    fn best_match_prefers_the_closest_then_alphabetical() {
+        // "Help" sorts first but is two edits away
+        assert_eq!(best_match("Helper", ["Help", "Helpers"]), Some("Helpers"));
+        assert_eq!(best_match("cat", ["hat", "bat"]), Some("bat"));
+    }
+
+    #[test]
+    fn best_match_ignores_case_and_the_name_itself() {
+        assert_eq!(best_match("main", ["Mains", "Main"]), Some("Main"));
+        assert_eq!(best_match("Main", ["Main"]), None);
+        assert_eq!(best_match("MAIN", ["Maim"]), Some("Maim"));
+    }
+
+    #[test]
+    fn no_candidates_means_no_suggestion() {
+        assert_eq!(best_match("Main", []), None);
+        assert_eq!(did_you_mean("Main", []), None);
+    }
+
+    #[test]
+    fn did_you_mean_quotes_the_candidate() {
+        assert_eq!(did_you_mean("Hepler", ["Helper", "Main"]), Some("did you mean 'Helper'?".to_string()));
+    }
+}
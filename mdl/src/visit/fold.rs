@@ -0,0 +1,304 @@
+//! Traversal of the program tree by value, for passes that replace nodes
+//! with nodes of a different shape (e.g. constant folding). Each `fold_*`
+//! method takes a node and returns its replacement; the default rebuilds the
+//! node from its folded children.
+
+use crate::Program;
+use crate::block::reference::BlockReference;
+use crate::block::{Block, Parameter};
+use crate::chain::Chain;
+use crate::chain::fence_group::FenceGroup;
+use crate::document::{Document, DocumentNode, InlineNode};
+use crate::instruction::Instruction;
+use crate::instruction::template::template_string::{TemplateString, TemplateStringPart};
+use crate::instruction::template::{BlockPattern, DocumentPattern, InlinePattern, Template};
+use crate::instruction::value::{MatchArm, OtherwiseArm, Value};
+
+/// Rebuild a program tree, node by node.
+pub trait Fold {
+    fn fold_program(&mut self, program: Program) -> Program {
+        walk_program(self, program)
+    }
+
+    fn fold_block(&mut self, block: Block) -> Block {
+        walk_block(self, block)
+    }
+
+    fn fold_parameter(&mut self, parameter: Parameter) -> Parameter {
+        walk_parameter(self, parameter)
+    }
+
+    fn fold_chain(&mut self, chain: Chain) -> Chain {
+        walk_chain(self, chain)
+    }
+
+    fn fold_fence_group(&mut self, group: FenceGroup) -> FenceGroup {
+        walk_fence_group(self, group)
+    }
+
+    fn fold_instruction(&mut self, instruction: Instruction) -> Instruction {
+        walk_instruction(self, instruction)
+    }
+
+    fn fold_value(&mut self, value: Value) -> Value {
+        walk_value(self, value)
+    }
+
+    fn fold_block_reference(&mut self, reference: BlockReference) -> BlockReference {
+        reference
+    }
+
+    fn fold_template_string(&mut self, template: TemplateString) -> TemplateString {
+        walk_template_string(self, template)
+    }
+
+    fn fold_match_arm(&mut self, arm: MatchArm) -> MatchArm {
+        walk_match_arm(self, arm)
+    }
+
+    fn fold_otherwise_arm(&mut self, arm: OtherwiseArm) -> OtherwiseArm {
+        walk_otherwise_arm(self, arm)
+    }
+
+    fn fold_template(&mut self, template: Template) -> Template {
+        walk_template(self, template)
+    }
+
+    fn fold_inline_pattern(&mut self, pattern: InlinePattern) -> InlinePattern {
+        walk_inline_pattern(self, pattern)
+    }
+
+    fn fold_block_pattern(&mut self, pattern: BlockPattern) -> BlockPattern {
+        walk_block_pattern(self, pattern)
+    }
+
+    fn fold_document(&mut self, document: Document) -> Document {
+        walk_document(self, document)
+    }
+
+    fn fold_document_node(&mut self, node: DocumentNode) -> DocumentNode {
+        walk_document_node(self, node)
+    }
+
+    fn fold_inline_node(&mut self, node: InlineNode) -> InlineNode {
+        walk_inline_node(self, node)
+    }
+}
+
+fn fold_values<F: Fold + ?Sized>(f: &mut F, values: Vec<Value>) -> Vec<Value> {
+    values.into_iter().map(|value| f.fold_value(value)).collect()
+}
+
+/// Fold a boxed value in place, reusing its allocation.
+fn fold_boxed<F: Fold + ?Sized>(f: &mut F, mut value: Box<Value>) -> Box<Value> {
+    let inner = std::mem::replace(&mut *value, Value::Error(0..0));
+    *value = f.fold_value(inner);
+    value
+}
+
+fn fold_inline_patterns<F: Fold + ?Sized>(f: &mut F, patterns: Vec<InlinePattern>) -> Vec<InlinePattern> {
+    patterns.into_iter().map(|pattern| f.fold_inline_pattern(pattern)).collect()
+}
+
+fn fold_inline_nodes<F: Fold + ?Sized>(f: &mut F, nodes: Vec<InlineNode>) -> Vec<InlineNode> {
+    nodes.into_iter().map(|node| f.fold_inline_node(node)).collect()
+}
+
+fn fold_documents<F: Fold + ?Sized>(f: &mut F, documents: Vec<Document>) -> Vec<Document> {
+    documents.into_iter().map(|document| f.fold_document(document)).collect()
+}
+
+pub fn walk_program<F: Fold + ?Sized>(f: &mut F, program: Program) -> Program {
+    Program { blocks: program.blocks.into_iter().map(|block| f.fold_block(block)).collect(), ..program }
+}
+
+pub fn walk_block<F: Fold + ?Sized>(f: &mut F, block: Block) -> Block {
+    let parameters =
+        block.parameters.map(|parameters| parameters.into_iter().map(|parameter| f.fold_parameter(parameter)).collect());
+    let body = f.fold_document(block.body);
+    let chain = f.fold_chain(block.chain);
+    let children = block.children.into_iter().map(|child| f.fold_block(child)).collect();
+    Block { parameters, body, chain, children, ..block }
+}
+
+pub fn walk_parameter<F: Fold + ?Sized>(f: &mut F, parameter: Parameter) -> Parameter {
+    Parameter { default: parameter.default.map(|default| f.fold_value(default)), ..parameter }
+}
+
+pub fn walk_chain<F: Fold + ?Sized>(f: &mut F, chain: Chain) -> Chain {
+    Chain { groups: chain.groups.into_iter().map(|group| f.fold_fence_group(group)).collect() }
+}
+
+pub fn walk_fence_group<F: Fold + ?Sized>(f: &mut F, group: FenceGroup) -> FenceGroup {
+    FenceGroup {
+        instructions: group.instructions.into_iter().map(|instruction| f.fold_instruction(instruction)).collect(),
+        ..group
+    }
+}
+
+pub fn walk_instruction<F: Fold + ?Sized>(f: &mut F, instruction: Instruction) -> Instruction {
+    match instruction {
+        Instruction::Assignment { variable, annotation, value, span } => {
+            Instruction::Assignment { variable, annotation, value: f.fold_value(value), span }
+        }
+        Instruction::Expression { value, span } => Instruction::Expression { value: f.fold_value(value), span },
+    }
+}
+
+pub fn walk_value<F: Fold + ?Sized>(f: &mut F, value: Value) -> Value {
+    match value {
+        Value::StringLiteral(..)
+        | Value::NumberLiteral(..)
+        | Value::BooleanLiteral(..)
+        | Value::UnitLiteral(_)
+        | Value::VariableReference(..)
+        | Value::PositionalArgumentReference(..)
+        | Value::SpreadArgumentReference(_)
+        | Value::NamedArgumentReference(..)
+        | Value::NamedArgumentRecord(_)
+        | Value::Error(_) => value,
+        Value::BlockInvocation(args, reference, span) => {
+            let args = fold_values(f, args);
+            Value::BlockInvocation(args, f.fold_block_reference(reference), span)
+        }
+        Value::EvaluatedBlockInvocation(args, reference, span) => {
+            let args = fold_values(f, args);
+            Value::EvaluatedBlockInvocation(args, f.fold_block_reference(reference), span)
+        }
+        Value::DynamicBlockInvocation(args, target, span) => {
+            let args = fold_values(f, args);
+            Value::DynamicBlockInvocation(args, f.fold_template_string(target), span)
+        }
+        Value::EvaluatedDynamicBlockInvocation(args, target, span) => {
+            let args = fold_values(f, args);
+            Value::EvaluatedDynamicBlockInvocation(args, f.fold_template_string(target), span)
+        }
+        Value::NamedArgument(name, value, span) => Value::NamedArgument(name, fold_boxed(f, value), span),
+        Value::BlockRef(reference, span) => Value::BlockRef(f.fold_block_reference(reference), span),
+        Value::Print(template) => Value::Print(f.fold_template_string(template)),
+        Value::Interpolation(template) => Value::Interpolation(f.fold_template_string(template)),
+        Value::Strikethrough(template) => Value::Strikethrough(f.fold_template_string(template)),
+        Value::UnaryOperation { operator, operand, span } => {
+            Value::UnaryOperation { operator, operand: fold_boxed(f, operand), span }
+        }
+        Value::BinaryOperation { operator, left, right, operator_span, span } => {
+            let left = fold_boxed(f, left);
+            let right = fold_boxed(f, right);
+            Value::BinaryOperation { operator, left, right, operator_span, span }
+        }
+        Value::Conditional { condition, true_branch, false_branch, span } => {
+            let condition = fold_boxed(f, condition);
+            let true_branch = fold_boxed(f, true_branch);
+            let false_branch = false_branch.map(|branch| fold_boxed(f, branch));
+            Value::Conditional { condition, true_branch, false_branch, span }
+        }
+        Value::Match { value, arms, otherwise, span } => {
+            let value = fold_boxed(f, value);
+            let arms = arms.into_iter().map(|arm| f.fold_match_arm(arm)).collect();
+            let otherwise = otherwise.map(|arm| f.fold_otherwise_arm(arm));
+            Value::Match { value, arms, otherwise, span }
+        }
+    }
+}
+
+pub fn walk_template_string<F: Fold + ?Sized>(f: &mut F, template: TemplateString) -> TemplateString {
+    let parts = template
+        .parts
+        .into_iter()
+        .map(|part| match part {
+            TemplateStringPart::Literal(_) => part,
+            TemplateStringPart::Expression(value) => TemplateStringPart::Expression(f.fold_value(value)),
+        })
+        .collect();
+    TemplateString { parts, ..template }
+}
+
+pub fn walk_match_arm<F: Fold + ?Sized>(f: &mut F, arm: MatchArm) -> MatchArm {
+    let pattern = f.fold_template(arm.pattern);
+    MatchArm { pattern, result: f.fold_value(arm.result), span: arm.span }
+}
+
+pub fn walk_otherwise_arm<F: Fold + ?Sized>(f: &mut F, arm: OtherwiseArm) -> OtherwiseArm {
+    OtherwiseArm { result: fold_boxed(f, arm.result), ..arm }
+}
+
+pub fn walk_template<F: Fold + ?Sized>(f: &mut F, template: Template) -> Template {
+    match template {
+        Template::NumberLiteral(_)
+        | Template::StringLiteral(_)
+        | Template::BooleanLiteral(_)
+        | Template::UnitLiteral
+        | Template::Strikethrough(None)
+        | Template::Binding(_)
+        | Template::Wildcard => template,
+        Template::Strikethrough(Some(inner)) => Template::Strikethrough(Some(Box::new(f.fold_template(*inner)))),
+        Template::DocumentPattern(DocumentPattern::Inline(pattern)) => {
+            Template::DocumentPattern(DocumentPattern::Inline(f.fold_inline_pattern(pattern)))
+        }
+        Template::DocumentPattern(DocumentPattern::Block(pattern)) => {
+            Template::DocumentPattern(DocumentPattern::Block(f.fold_block_pattern(pattern)))
+        }
+        Template::Compound(parts) => Template::Compound(parts.into_iter().map(|part| f.fold_template(part)).collect()),
+        Template::Alternation(parts) => {
+            Template::Alternation(parts.into_iter().map(|part| f.fold_template(part)).collect())
+        }
+    }
+}
+
+pub fn walk_inline_pattern<F: Fold + ?Sized>(f: &mut F, pattern: InlinePattern) -> InlinePattern {
+    match pattern {
+        InlinePattern::Text(_) | InlinePattern::CodeSpan(_) | InlinePattern::Capture(_) => pattern,
+        InlinePattern::Strong(children) => InlinePattern::Strong(fold_inline_patterns(f, children)),
+        InlinePattern::Emphasis(children) => InlinePattern::Emphasis(fold_inline_patterns(f, children)),
+        InlinePattern::Strikethrough(children) => InlinePattern::Strikethrough(fold_inline_patterns(f, children)),
+        InlinePattern::Link { dest, content } => InlinePattern::Link { dest, content: fold_inline_patterns(f, content) },
+    }
+}
+
+pub fn walk_block_pattern<F: Fold + ?Sized>(f: &mut F, pattern: BlockPattern) -> BlockPattern {
+    match pattern {
+        BlockPattern::Paragraph(content) => BlockPattern::Paragraph(fold_inline_patterns(f, content)),
+        BlockPattern::Heading { level, content } => {
+            BlockPattern::Heading { level, content: fold_inline_patterns(f, content) }
+        }
+        BlockPattern::CodeBlock { .. } => pattern,
+    }
+}
+
+pub fn walk_document<F: Fold + ?Sized>(f: &mut F, document: Document) -> Document {
+    Document { nodes: document.nodes.into_iter().map(|node| f.fold_document_node(node)).collect() }
+}
+
+pub fn walk_document_node<F: Fold + ?Sized>(f: &mut F, node: DocumentNode) -> DocumentNode {
+    match node {
+        DocumentNode::Paragraph(content) => DocumentNode::Paragraph(fold_inline_nodes(f, content)),
+        DocumentNode::Heading { level, content } => {
+            DocumentNode::Heading { level, content: fold_inline_nodes(f, content) }
+        }
+        DocumentNode::CodeBlock { .. } | DocumentNode::HorizontalRule => node,
+        DocumentNode::Blockquote(document) => DocumentNode::Blockquote(f.fold_document(document)),
+        DocumentNode::Table { alignments, headers, rows } => {
+            let headers = headers.into_iter().map(|cell| fold_inline_nodes(f, cell)).collect();
+            let rows = rows
+                .into_iter()
+                .map(|row| row.into_iter().map(|cell| fold_inline_nodes(f, cell)).collect())
+                .collect();
+            DocumentNode::Table { alignments, headers, rows }
+        }
+        DocumentNode::OrderedList { start, items } => DocumentNode::OrderedList { start, items: fold_documents(f, items) },
+        DocumentNode::UnorderedList { items } => DocumentNode::UnorderedList { items: fold_documents(f, items) },
+    }
+}
+
+pub fn walk_inline_node<F: Fold + ?Sized>(f: &mut F, node: InlineNode) -> InlineNode {
+    match node {
+        InlineNode::Text(_) | InlineNode::CodeSpan(_) | InlineNode::SoftBreak | InlineNode::HardBreak => node,
+        InlineNode::Strong(children) => InlineNode::Strong(fold_inline_nodes(f, children)),
+        InlineNode::Emphasis(children) => InlineNode::Emphasis(fold_inline_nodes(f, children)),
+        InlineNode::Strikethrough(children) => InlineNode::Strikethrough(fold_inline_nodes(f, children)),
+        InlineNode::Link { dest, title, content } => {
+            InlineNode::Link { dest, title, content: fold_inline_nodes(f, content) }
+        }
+        InlineNode::Image { dest, title, alt } => InlineNode::Image { dest, title, alt: fold_inline_nodes(f, alt) },
+    }
+}
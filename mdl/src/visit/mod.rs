@@ -0,0 +1,317 @@
+//! Traversal of the program tree.
+//!
+//! [`Visit`] walks a tree by reference, [`visit_mut::VisitMut`] by mutable
+//! reference and [`fold::Fold`] by value, rebuilding it. Each trait has one
+//! method per node type, whose default implementation calls the `walk_*`
+//! function of the same node to visit its children in source order. A pass
+//! overrides the methods for the nodes it cares about, and calls the `walk_*`
+//! function from its override to keep descending:
+//!
+//! ```
+//! use mdl::instruction::value::Value;
+//! use mdl::visit::{self, Visit};
+//!
+//! /// Count the variable reads in a program.
+//! struct Reads(usize);
+//!
+//! impl<'ast> Visit<'ast> for Reads {
+//!     fn visit_value(&mut self, value: &'ast Value) {
+//!         if let Value::VariableReference(..) = value {
+//!             self.0 += 1;
+//!         }
+//!         visit::walk_value(self, value);
+//!     }
+//! }
+//!
+//! let source = "# Main\n1. x = 1\n2. **{x + x}**\n";
+//! let (program, _) = mdl::parser::Parser::new(source.to_string(), 0).parse().unwrap();
+//! let mut reads = Reads(0);
+//! reads.visit_program(&program);
+//! assert_eq!(reads.0, 2);
+//! ```
+
+pub mod fold;
+pub mod visit_mut;
+
+use crate::Program;
+use crate::block::reference::BlockReference;
+use crate::block::{Block, Parameter};
+use crate::chain::Chain;
+use crate::chain::fence_group::FenceGroup;
+use crate::document::{Document, DocumentNode, InlineNode};
+use crate::instruction::Instruction;
+use crate::instruction::template::template_string::{TemplateString, TemplateStringPart};
+use crate::instruction::template::{BlockPattern, DocumentPattern, InlinePattern, Template};
+use crate::instruction::value::{MatchArm, OtherwiseArm, Value};
+
+/// Visit the nodes of a program tree by reference. `'ast` is the lifetime of
+/// the tree, so visitors can keep references to the nodes they see.
+pub trait Visit<'ast> {
+    fn visit_program(&mut self, program: &'ast Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_block(&mut self, block: &'ast Block) {
+        walk_block(self, block)
+    }
+
+    fn visit_parameter(&mut self, parameter: &'ast Parameter) {
+        walk_parameter(self, parameter)
+    }
+
+    fn visit_chain(&mut self, chain: &'ast Chain) {
+        walk_chain(self, chain)
+    }
+
+    fn visit_fence_group(&mut self, group: &'ast FenceGroup) {
+        walk_fence_group(self, group)
+    }
+
+    fn visit_instruction(&mut self, instruction: &'ast Instruction) {
+        walk_instruction(self, instruction)
+    }
+
+    fn visit_value(&mut self, value: &'ast Value) {
+        walk_value(self, value)
+    }
+
+    fn visit_block_reference(&mut self, _reference: &'ast BlockReference) {}
+
+    fn visit_template_string(&mut self, template: &'ast TemplateString) {
+        walk_template_string(self, template)
+    }
+
+    fn visit_match_arm(&mut self, arm: &'ast MatchArm) {
+        walk_match_arm(self, arm)
+    }
+
+    fn visit_otherwise_arm(&mut self, arm: &'ast OtherwiseArm) {
+        walk_otherwise_arm(self, arm)
+    }
+
+    fn visit_template(&mut self, template: &'ast Template) {
+        walk_template(self, template)
+    }
+
+    fn visit_inline_pattern(&mut self, pattern: &'ast InlinePattern) {
+        walk_inline_pattern(self, pattern)
+    }
+
+    fn visit_block_pattern(&mut self, pattern: &'ast BlockPattern) {
+        walk_block_pattern(self, pattern)
+    }
+
+    fn visit_document(&mut self, document: &'ast Document) {
+        walk_document(self, document)
+    }
+
+    fn visit_document_node(&mut self, node: &'ast DocumentNode) {
+        walk_document_node(self, node)
+    }
+
+    fn visit_inline_node(&mut self, node: &'ast InlineNode) {
+        walk_inline_node(self, node)
+    }
+}
+
+pub fn walk_program<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, program: &'ast Program) {
+    for block in &program.blocks {
+        v.visit_block(block);
+    }
+}
+
+/// Parameters, then the body, then the chain, then the sub-blocks.
+pub fn walk_block<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, block: &'ast Block) {
+    for parameter in block.parameters.iter().flatten() {
+        v.visit_parameter(parameter);
+    }
+    v.visit_document(&block.body);
+    v.visit_chain(&block.chain);
+    for child in &block.children {
+        v.visit_block(child);
+    }
+}
+
+pub fn walk_parameter<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, parameter: &'ast Parameter) {
+    if let Some(default) = &parameter.default {
+        v.visit_value(default);
+    }
+}
+
+pub fn walk_chain<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, chain: &'ast Chain) {
+    for group in &chain.groups {
+        v.visit_fence_group(group);
+    }
+}
+
+pub fn walk_fence_group<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, group: &'ast FenceGroup) {
+    for instruction in &group.instructions {
+        v.visit_instruction(instruction);
+    }
+}
+
+pub fn walk_instruction<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, instruction: &'ast Instruction) {
+    match instruction {
+        Instruction::Assignment { value, .. } | Instruction::Expression { value, .. } => v.visit_value(value),
+    }
+}
+
+/// Visit the direct sub-expressions, templates and patterns of `value`.
+pub fn walk_value<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, value: &'ast Value) {
+    match value {
+        Value::StringLiteral(..)
+        | Value::NumberLiteral(..)
+        | Value::BooleanLiteral(..)
+        | Value::UnitLiteral(_)
+        | Value::VariableReference(..)
+        | Value::PositionalArgumentReference(..)
+        | Value::SpreadArgumentReference(_)
+        | Value::NamedArgumentReference(..)
+        | Value::NamedArgumentRecord(_)
+        | Value::Error(_) => {}
+        Value::BlockInvocation(args, reference, _) | Value::EvaluatedBlockInvocation(args, reference, _) => {
+            for arg in args {
+                v.visit_value(arg);
+            }
+            v.visit_block_reference(reference);
+        }
+        Value::DynamicBlockInvocation(args, target, _) | Value::EvaluatedDynamicBlockInvocation(args, target, _) => {
+            for arg in args {
+                v.visit_value(arg);
+            }
+            v.visit_template_string(target);
+        }
+        Value::NamedArgument(_, value, _) => v.visit_value(value),
+        Value::BlockRef(reference, _) => v.visit_block_reference(reference),
+        Value::Print(template) | Value::Interpolation(template) | Value::Strikethrough(template) => {
+            v.visit_template_string(template)
+        }
+        Value::UnaryOperation { operand, .. } => v.visit_value(operand),
+        Value::BinaryOperation { left, right, .. } => {
+            v.visit_value(left);
+            v.visit_value(right);
+        }
+        Value::Conditional { condition, true_branch, false_branch, .. } => {
+            v.visit_value(condition);
+            v.visit_value(true_branch);
+            if let Some(false_branch) = false_branch {
+                v.visit_value(false_branch);
+            }
+        }
+        Value::Match { value, arms, otherwise, .. } => {
+            v.visit_value(value);
+            for arm in arms {
+                v.visit_match_arm(arm);
+            }
+            if let Some(otherwise) = otherwise {
+                v.visit_otherwise_arm(otherwise);
+            }
+        }
+    }
+}
+
+pub fn walk_template_string<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, template: &'ast TemplateString) {
+    for part in &template.parts {
+        match part {
+            TemplateStringPart::Literal(_) => {}
+            TemplateStringPart::Expression(value) => v.visit_value(value),
+        }
+    }
+}
+
+pub fn walk_match_arm<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, arm: &'ast MatchArm) {
+    v.visit_template(&arm.pattern);
+    v.visit_value(&arm.result);
+}
+
+pub fn walk_otherwise_arm<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, arm: &'ast OtherwiseArm) {
+    v.visit_value(&arm.result);
+}
+
+pub fn walk_template<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, template: &'ast Template) {
+    match template {
+        Template::NumberLiteral(_)
+        | Template::StringLiteral(_)
+        | Template::BooleanLiteral(_)
+        | Template::UnitLiteral
+        | Template::Strikethrough(None)
+        | Template::Binding(_)
+        | Template::Wildcard => {}
+        Template::Strikethrough(Some(inner)) => v.visit_template(inner),
+        Template::DocumentPattern(DocumentPattern::Inline(pattern)) => v.visit_inline_pattern(pattern),
+        Template::DocumentPattern(DocumentPattern::Block(pattern)) => v.visit_block_pattern(pattern),
+        Template::Compound(parts) | Template::Alternation(parts) => {
+            for part in parts {
+                v.visit_template(part);
+            }
+        }
+    }
+}
+
+pub fn walk_inline_pattern<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, pattern: &'ast InlinePattern) {
+    match pattern {
+        InlinePattern::Text(_) | InlinePattern::CodeSpan(_) | InlinePattern::Capture(_) => {}
+        InlinePattern::Strong(children)
+        | InlinePattern::Emphasis(children)
+        | InlinePattern::Strikethrough(children)
+        | InlinePattern::Link { content: children, .. } => {
+            for child in children {
+                v.visit_inline_pattern(child);
+            }
+        }
+    }
+}
+
+pub fn walk_block_pattern<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, pattern: &'ast BlockPattern) {
+    match pattern {
+        BlockPattern::Paragraph(content) | BlockPattern::Heading { content, .. } => {
+            for child in content {
+                v.visit_inline_pattern(child);
+            }
+        }
+        BlockPattern::CodeBlock { .. } => {}
+    }
+}
+
+pub fn walk_document<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, document: &'ast Document) {
+    for node in &document.nodes {
+        v.visit_document_node(node);
+    }
+}
+
+pub fn walk_document_node<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast DocumentNode) {
+    match node {
+        DocumentNode::Paragraph(content) | DocumentNode::Heading { content, .. } => {
+            for inline in content {
+                v.visit_inline_node(inline);
+            }
+        }
+        DocumentNode::CodeBlock { .. } | DocumentNode::HorizontalRule => {}
+        DocumentNode::Blockquote(document) => v.visit_document(document),
+        DocumentNode::Table { headers, rows, .. } => {
+            for inline in headers.iter().chain(rows.iter().flatten()).flatten() {
+                v.visit_inline_node(inline);
+            }
+        }
+        DocumentNode::OrderedList { items, .. } | DocumentNode::UnorderedList { items } => {
+            for item in items {
+                v.visit_document(item);
+            }
+        }
+    }
+}
+
+pub fn walk_inline_node<'ast, V: Visit<'ast> + ?Sized>(v: &mut V, node: &'ast InlineNode) {
+    match node {
+        InlineNode::Text(_) | InlineNode::CodeSpan(_) | InlineNode::SoftBreak | InlineNode::HardBreak => {}
+        InlineNode::Strong(children)
+        | InlineNode::Emphasis(children)
+        | InlineNode::Strikethrough(children)
+        | InlineNode::Link { content: children, .. }
+        | InlineNode::Image { alt: children, .. } => {
+            for child in children {
+                v.visit_inline_node(child);
+            }
+        }
+    }
+}
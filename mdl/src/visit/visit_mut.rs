@@ -0,0 +1,282 @@
+//! Traversal of the program tree by mutable reference, for passes that
+//! rewrite nodes in place. Mirrors [`super::Visit`] method for method.
+
+use crate::Program;
+use crate::block::reference::BlockReference;
+use crate::block::{Block, Parameter};
+use crate::chain::Chain;
+use crate::chain::fence_group::FenceGroup;
+use crate::document::{Document, DocumentNode, InlineNode};
+use crate::instruction::Instruction;
+use crate::instruction::template::template_string::{TemplateString, TemplateStringPart};
+use crate::instruction::template::{BlockPattern, DocumentPattern, InlinePattern, Template};
+use crate::instruction::value::{MatchArm, OtherwiseArm, Value};
+
+/// Visit the nodes of a program tree by mutable reference.
+pub trait VisitMut {
+    fn visit_program_mut(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+
+    fn visit_parameter_mut(&mut self, parameter: &mut Parameter) {
+        walk_parameter_mut(self, parameter)
+    }
+
+    fn visit_chain_mut(&mut self, chain: &mut Chain) {
+        walk_chain_mut(self, chain)
+    }
+
+    fn visit_fence_group_mut(&mut self, group: &mut FenceGroup) {
+        walk_fence_group_mut(self, group)
+    }
+
+    fn visit_instruction_mut(&mut self, instruction: &mut Instruction) {
+        walk_instruction_mut(self, instruction)
+    }
+
+    fn visit_value_mut(&mut self, value: &mut Value) {
+        walk_value_mut(self, value)
+    }
+
+    fn visit_block_reference_mut(&mut self, _reference: &mut BlockReference) {}
+
+    fn visit_template_string_mut(&mut self, template: &mut TemplateString) {
+        walk_template_string_mut(self, template)
+    }
+
+    fn visit_match_arm_mut(&mut self, arm: &mut MatchArm) {
+        walk_match_arm_mut(self, arm)
+    }
+
+    fn visit_otherwise_arm_mut(&mut self, arm: &mut OtherwiseArm) {
+        walk_otherwise_arm_mut(self, arm)
+    }
+
+    fn visit_template_mut(&mut self, template: &mut Template) {
+        walk_template_mut(self, template)
+    }
+
+    fn visit_inline_pattern_mut(&mut self, pattern: &mut InlinePattern) {
+        walk_inline_pattern_mut(self, pattern)
+    }
+
+    fn visit_block_pattern_mut(&mut self, pattern: &mut BlockPattern) {
+        walk_block_pattern_mut(self, pattern)
+    }
+
+    fn visit_document_mut(&mut self, document: &mut Document) {
+        walk_document_mut(self, document)
+    }
+
+    fn visit_document_node_mut(&mut self, node: &mut DocumentNode) {
+        walk_document_node_mut(self, node)
+    }
+
+    fn visit_inline_node_mut(&mut self, node: &mut InlineNode) {
+        walk_inline_node_mut(self, node)
+    }
+}
+
+pub fn walk_program_mut<V: VisitMut + ?Sized>(v: &mut V, program: &mut Program) {
+    for block in &mut program.blocks {
+        v.visit_block_mut(block);
+    }
+}
+
+pub fn walk_block_mut<V: VisitMut + ?Sized>(v: &mut V, block: &mut Block) {
+    for parameter in block.parameters.iter_mut().flatten() {
+        v.visit_parameter_mut(parameter);
+    }
+    v.visit_document_mut(&mut block.body);
+    v.visit_chain_mut(&mut block.chain);
+    for child in &mut block.children {
+        v.visit_block_mut(child);
+    }
+}
+
+pub fn walk_parameter_mut<V: VisitMut + ?Sized>(v: &mut V, parameter: &mut Parameter) {
+    if let Some(default) = &mut parameter.default {
+        v.visit_value_mut(default);
+    }
+}
+
+pub fn walk_chain_mut<V: VisitMut + ?Sized>(v: &mut V, chain: &mut Chain) {
+    for group in &mut chain.groups {
+        v.visit_fence_group_mut(group);
+    }
+}
+
+pub fn walk_fence_group_mut<V: VisitMut + ?Sized>(v: &mut V, group: &mut FenceGroup) {
+    for instruction in &mut group.instructions {
+        v.visit_instruction_mut(instruction);
+    }
+}
+
+pub fn walk_instruction_mut<V: VisitMut + ?Sized>(v: &mut V, instruction: &mut Instruction) {
+    match instruction {
+        Instruction::Assignment { value, .. } | Instruction::Expression { value, .. } => v.visit_value_mut(value),
+    }
+}
+
+pub fn walk_value_mut<V: VisitMut + ?Sized>(v: &mut V, value: &mut Value) {
+    match value {
+        Value::StringLiteral(..)
+        | Value::NumberLiteral(..)
+        | Value::BooleanLiteral(..)
+        | Value::UnitLiteral(_)
+        | Value::VariableReference(..)
+        | Value::PositionalArgumentReference(..)
+        | Value::SpreadArgumentReference(_)
+        | Value::NamedArgumentReference(..)
+        | Value::NamedArgumentRecord(_)
+        | Value::Error(_) => {}
+        Value::BlockInvocation(args, reference, _) | Value::EvaluatedBlockInvocation(args, reference, _) => {
+            for arg in args {
+                v.visit_value_mut(arg);
+            }
+            v.visit_block_reference_mut(reference);
+        }
+        Value::DynamicBlockInvocation(args, target, _) | Value::EvaluatedDynamicBlockInvocation(args, target, _) => {
+            for arg in args {
+                v.visit_value_mut(arg);
+            }
+            v.visit_template_string_mut(target);
+        }
+        Value::NamedArgument(_, value, _) => v.visit_value_mut(value),
+        Value::BlockRef(reference, _) => v.visit_block_reference_mut(reference),
+        Value::Print(template) | Value::Interpolation(template) | Value::Strikethrough(template) => {
+            v.visit_template_string_mut(template)
+        }
+        Value::UnaryOperation { operand, .. } => v.visit_value_mut(operand),
+        Value::BinaryOperation { left, right, .. } => {
+            v.visit_value_mut(left);
+            v.visit_value_mut(right);
+        }
+        Value::Conditional { condition, true_branch, false_branch, .. } => {
+            v.visit_value_mut(condition);
+            v.visit_value_mut(true_branch);
+            if let Some(false_branch) = false_branch {
+                v.visit_value_mut(false_branch);
+            }
+        }
+        Value::Match { value, arms, otherwise, .. } => {
+            v.visit_value_mut(value);
+            for arm in arms {
+                v.visit_match_arm_mut(arm);
+            }
+            if let Some(otherwise) = otherwise {
+                v.visit_otherwise_arm_mut(otherwise);
+            }
+        }
+    }
+}
+
+pub fn walk_template_string_mut<V: VisitMut + ?Sized>(v: &mut V, template: &mut TemplateString) {
+    for part in &mut template.parts {
+        match part {
+            TemplateStringPart::Literal(_) => {}
+            TemplateStringPart::Expression(value) => v.visit_value_mut(value),
+        }
+    }
+}
+
+pub fn walk_match_arm_mut<V: VisitMut + ?Sized>(v: &mut V, arm: &mut MatchArm) {
+    v.visit_template_mut(&mut arm.pattern);
+    v.visit_value_mut(&mut arm.result);
+}
+
+pub fn walk_otherwise_arm_mut<V: VisitMut + ?Sized>(v: &mut V, arm: &mut OtherwiseArm) {
+    v.visit_value_mut(&mut arm.result);
+}
+
+pub fn walk_template_mut<V: VisitMut + ?Sized>(v: &mut V, template: &mut Template) {
+    match template {
+        Template::NumberLiteral(_)
+        | Template::StringLiteral(_)
+        | Template::BooleanLiteral(_)
+        | Template::UnitLiteral
+        | Template::Strikethrough(None)
+        | Template::Binding(_)
+        | Template::Wildcard => {}
+        Template::Strikethrough(Some(inner)) => v.visit_template_mut(inner),
+        Template::DocumentPattern(DocumentPattern::Inline(pattern)) => v.visit_inline_pattern_mut(pattern),
+        Template::DocumentPattern(DocumentPattern::Block(pattern)) => v.visit_block_pattern_mut(pattern),
+        Template::Compound(parts) | Template::Alternation(parts) => {
+            for part in parts {
+                v.visit_template_mut(part);
+            }
+        }
+    }
+}
+
+pub fn walk_inline_pattern_mut<V: VisitMut + ?Sized>(v: &mut V, pattern: &mut InlinePattern) {
+    match pattern {
+        InlinePattern::Text(_) | InlinePattern::CodeSpan(_) | InlinePattern::Capture(_) => {}
+        InlinePattern::Strong(children)
+        | InlinePattern::Emphasis(children)
+        | InlinePattern::Strikethrough(children)
+        | InlinePattern::Link { content: children, .. } => {
+            for child in children {
+                v.visit_inline_pattern_mut(child);
+            }
+        }
+    }
+}
+
+pub fn walk_block_pattern_mut<V: VisitMut + ?Sized>(v: &mut V, pattern: &mut BlockPattern) {
+    match pattern {
+        BlockPattern::Paragraph(content) | BlockPattern::Heading { content, .. } => {
+            for child in content {
+                v.visit_inline_pattern_mut(child);
+            }
+        }
+        BlockPattern::CodeBlock { .. } => {}
+    }
+}
+
+pub fn walk_document_mut<V: VisitMut + ?Sized>(v: &mut V, document: &mut Document) {
+    for node in &mut document.nodes {
+        v.visit_document_node_mut(node);
+    }
+}
+
+pub fn walk_document_node_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut DocumentNode) {
+    match node {
+        DocumentNode::Paragraph(content) | DocumentNode::Heading { content, .. } => {
+            for inline in content {
+                v.visit_inline_node_mut(inline);
+            }
+        }
+        DocumentNode::CodeBlock { .. } | DocumentNode::HorizontalRule => {}
+        DocumentNode::Blockquote(document) => v.visit_document_mut(document),
+        DocumentNode::Table { headers, rows, .. } => {
+            for inline in headers.iter_mut().chain(rows.iter_mut().flatten()).flatten() {
+                v.visit_inline_node_mut(inline);
+            }
+        }
+        DocumentNode::OrderedList { items, .. } | DocumentNode::UnorderedList { items } => {
+            for item in items {
+                v.visit_document_mut(item);
+            }
+        }
+    }
+}
+
+pub fn walk_inline_node_mut<V: VisitMut + ?Sized>(v: &mut V, node: &mut InlineNode) {
+    match node {
+        InlineNode::Text(_) | InlineNode::CodeSpan(_) | InlineNode::SoftBreak | InlineNode::HardBreak => {}
+        InlineNode::Strong(children)
+        | InlineNode::Emphasis(children)
+        | InlineNode::Strikethrough(children)
+        | InlineNode::Link { content: children, .. }
+        | InlineNode::Image { alt: children, .. } => {
+            for child in children {
+                v.visit_inline_node_mut(child);
+            }
+        }
+    }
+}
@@ -0,0 +1,167 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use mdl::cst::{Node, SourceFile};
+
+fn markdown_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            markdown_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "md") {
+            files.push(path);
+        }
+    }
+}
+
+#[test]
+fn every_repository_program_round_trips() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let mut files = Vec::new();
+    markdown_files(&root.join("examples"), &mut files);
+    markdown_files(&root.join("test"), &mut files);
+    assert!(files.len() > 100);
+    for path in files {
+        let source = fs::read_to_string(&path).unwrap();
+        assert_eq!(SourceFile::parse(&source).to_string(), source, "{}", path.display());
+    }
+}
+
+#[test]
+fn odd_formatting_round_trips() {
+    let sources = [
+        "",
+        "no heading at all",
+        "#\n",
+        "   ##  Add  (a: Number, b = 1) ##  \r\n1)   **{a + b}**\r\n",
+        "Setext (x)\n===\n\n01. x = 1\n    continued\n\n\n02. **{x}**",
+        "# Main\n\n- not\n- instructions\n\n> 1. quoted\n\n| a | b |\n|:--|--:|\n| 1 | 2 |\n",
+        "# A\n1. a\n## B\n1. b\n### C\n# D\n",
+    ];
+    for source in sources {
+        assert_eq!(SourceFile::parse(source).to_string(), source);
+    }
+}
+
+#[test]
+fn headings_and_instructions_are_split_into_parts() {
+    let file = SourceFile::parse("Intro\n\n# Add (a, b = 1)\n\n1. x = a\n1) y = b\n2.  **{x + y}**\n\n## Helper ##\n");
+    assert!(matches!(&file.nodes[0], Node::Trivia(text) if text == "Intro\n\n"));
+
+    let add = file.find_block("Add").unwrap();
+    assert_eq!((add.level, add.prefix.as_str(), add.suffix.as_str()), (1, "# ", " (a, b = 1)\n"));
+    let items: Vec<_> = add.instructions().map(|item| (item.index(), item.delimiter, item.content.as_str())).collect();
+    assert_eq!(items, [(1, '.', "x = a\n"), (1, ')', "y = b\n"), (2, '.', "**{x + y}**\n\n")]);
+
+    let helper = add.blocks().next().unwrap();
+    assert_eq!((helper.name.as_str(), helper.suffix.as_str()), ("Helper", " ##\n"));
+    assert_eq!(file.find_block("Helper"), Some(helper));
+}
+
+#[test]
+fn rename_block_rewrites_heading_and_references() {
+    let source = "# Main\n\nCall it:\n\n1. [2](#Double)\n2. [x](#Doubled)\n\n#  Double  (n)\n\n1. **{n * 2}**\n";
+    let mut file = SourceFile::parse(source);
+    assert!(file.rename_block("Double", "Twice"));
+    assert!(!file.rename_block("Missing", "Other"));
+    assert_eq!(
+        file.to_string(),
+        "# Main\n\nCall it:\n\n1. [2](#Twice)\n2. [x](#Doubled)\n\n#  Twice  (n)\n\n1. **{n * 2}**\n"
+    );
+}
+
+#[test]
+fn rename_block_leaves_body_text_and_code_alone() {
+    let source = "# Main\n\nSee [here](#Double).\n\n```\n1. [2](#Double)\n```\n\n1. [`[2](#Double)`, 3](#Double)\n\n\
+                  # Double (s, n)\n\n1. **{s}**\n";
+    let mut file = SourceFile::parse(source);
+    assert!(file.rename_block("Double", "Twice"));
+    assert_eq!(
+        file.to_string(),
+        "# Main\n\nSee [here](#Double).\n\n```\n1. [2](#Double)\n```\n\n1. [`[2](#Double)`, 3](#Twice)\n\n\
+         # Twice (s, n)\n\n1. **{s}**\n"
+    );
+}
+
+#[test]
+fn rename_block_only_rewrites_links_to_the_renamed_block() {
+    let source = "# Main\n1. [[1](#Helper)](#A)\n2. [](#B)\n\n## A\n1. [#1](#Helper)\n\n### Helper\n1. #1\n\n\
+                  ## B\n1. [2](#Helper)\n\n### Helper\n1. #1\n";
+    let mut file = SourceFile::parse(source);
+    assert!(file.rename_block("Helper", "Inner"));
+    assert_eq!(
+        file.to_string(),
+        "# Main\n1. [[1](#Inner)](#A)\n2. [](#B)\n\n## A\n1. [#1](#Inner)\n\n### Inner\n1. #1\n\n\
+         ## B\n1. [2](#Helper)\n\n### Helper\n1. #1\n"
+    );
+}
+
+#[test]
+fn push_instruction_keeps_the_list_style() {
+    let mut file = SourceFile::parse("# Main\n\n  3) x = 1\n\n# Next\n1. y");
+    file.find_block_mut("Main").unwrap().push_instruction("**{x}**");
+    file.find_block_mut("Next").unwrap().push_instruction("**{y}**");
+    assert_eq!(file.to_string(), "# Main\n\n  3) x = 1\n\n  4) **{x}**\n# Next\n1. y\n2. **{y}**\n");
+}
+
+#[test]
+fn push_instruction_starts_a_list_before_nested_blocks() {
+    let mut file = SourceFile::parse("# Main\nSome text.\n## Inner\n1. x = 1\n");
+    file.find_block_mut("Main").unwrap().push_instruction("**{1}**");
+    assert_eq!(file.to_string(), "# Main\nSome text.\n\n1. **{1}**\n## Inner\n1. x = 1\n");
+
+    let mut file = SourceFile::parse("# Empty");
+    file.find_block_mut("Empty").unwrap().push_instruction("**{1}**");
+    assert_eq!(file.to_string(), "# Empty\n1. **{1}**\n");
+    assert_eq!(file.find_block("Empty").unwrap().instructions().count(), 1);
+}
+
+/// Number of instructions in each fence group of every block, depth first.
+fn fence_shape(source: &str) -> Vec<Vec<usize>> {
+    fn walk(blocks: &[mdl::block::Block], out: &mut Vec<Vec<usize>>) {
+        for block in blocks {
+            out.push(block.chain.groups.iter().map(|group| group.instructions.len()).collect());
+            walk(&block.children, out);
+        }
+    }
+    let (program, _) = mdl::parser::Parser::new(source.to_string(), 0).parse_recovering();
+    let mut shape = Vec::new();
+    walk(&program.blocks, &mut shape);
+    shape
+}
+
+#[test]
+fn renumber_compacts_fences_and_keeps_grouping() {
+    let source = "# Main\n10. x = 1\n10) y = 2\n<!-- mdl-allow: W0201 -->\n30. **{x}**\n\nText\n\n30. **{y}**\n\n## Sub\n5. a\n3. b\n5. c\n";
+    let mut file = SourceFile::parse(source);
+    assert!(file.renumber());
+    let renumbered = file.to_string();
+    assert_eq!(
+        renumbered,
+        "# Main\n1. x = 1\n1) y = 2\n<!-- mdl-allow: W0201 -->\n2. **{x}**\n\nText\n\n3. **{y}**\n\n## Sub\n1. a\n2. b\n3. c\n"
+    );
+    assert_eq!(fence_shape(&renumbered), fence_shape(source));
+    assert!(!SourceFile::parse(&renumbered).renumber());
+
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap();
+    let mut files = Vec::new();
+    markdown_files(&root.join("test"), &mut files);
+    for path in files {
+        let source = fs::read_to_string(&path).unwrap();
+        let mut file = SourceFile::parse(&source);
+        file.renumber();
+        assert_eq!(fence_shape(&file.to_string()), fence_shape(&source), "{}", path.display());
+    }
+}
+
+#[test]
+fn insert_fence_shifts_later_fences() {
+    let mut file = SourceFile::parse("# Main\n1. x = 1\n<!-- mdl-allow: W0201 -->\n2. **{x}**\n2. y = x\n5. **{y}**\n");
+    let main = file.find_block_mut("Main").unwrap();
+    main.insert_fence(2, "z = 0");
+    main.insert_fence(9, "**end**");
+    assert_eq!(
+        file.to_string(),
+        "# Main\n1. x = 1\n2. z = 0\n<!-- mdl-allow: W0201 -->\n3. **{x}**\n3. y = x\n6. **{y}**\n9. **end**\n"
+    );
+}
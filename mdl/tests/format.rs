@@ -0,0 +1,41 @@
+use std::fs;
+use std::path::Path;
+
+use mdl::format::format_source;
+
+#[test]
+fn instructions_get_canonical_spacing() {
+    let source = "#   Main   ##\n  1)  x=1+2*3\n01. y = - x\n2.  **{ x+y }** and **{[x ,y](#Add)}**\n\
+                  2. z = x>1?\"big\":\"small\"\n3. r = match z\n     * \"big\":x*2\n     * otherwise v :  -1\n\
+                  4. f = &[ ](#Add)\n\n## Add(a:Number,b=1)\n1. #0+#1";
+    let expected = "# Main\n1. x = 1 + 2 * 3\n1. y = -x\n2. **{x + y}** and **{[x, y](#Add)}**\n\
+                    2. z = x > 1 ? \"big\" : \"small\"\n3. r = match z\n    - \"big\": x * 2\n    - otherwise v: -1\n\
+                    4. f = &[](#Add)\n\n## Add (a: Number, b = 1)\n1. #0 + #1\n";
+    assert_eq!(format_source(source), expected);
+}
+
+#[test]
+fn rewrites_that_change_the_program_are_skipped() {
+    // `2*3*4` is Markdown emphasis; the arms are not nested under the item
+    let source = "# Main\n1. w = 2*3*4\n2. r = match w\n  - 1:2\n3. x=1\n";
+    assert_eq!(format_source(source), "# Main\n1. w = 2*3*4\n2. r = match w\n  - 1:2\n3. x = 1\n");
+}
+
+#[test]
+fn tables_get_aligned_columns() {
+    let source = "# Data\n\n| Name | Score |\n|:-|-:|\nAlice|95\n| Bob | 7 |\n\nText | kept\n";
+    let expected = "# Data\n\n| Name  | Score |\n|:------|------:|\n| Alice | 95    |\n| Bob   | 7     |\n\nText | kept\n";
+    assert_eq!(format_source(source), expected);
+}
+
+#[test]
+fn repository_programs_are_formatted_idempotently() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).parent().unwrap().join("examples");
+    for entry in fs::read_dir(root).unwrap() {
+        let path = entry.unwrap().path();
+        let source = fs::read_to_string(&path).unwrap();
+        let formatted = format_source(&source);
+        assert_eq!(formatted, source, "{} is not formatted", path.display());
+        assert_eq!(format_source(&formatted), formatted);
+    }
+}
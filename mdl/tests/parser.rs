@@ -0,0 +1,37 @@
+use mdl::instruction::Instruction;
+use mdl::instruction::value::{BinaryOperator, Value};
+use mdl::parser::Parser;
+
+#[test]
+fn parser_recovers_from_syntax_errors() {
+    let source = "# Main\n1. x = 1 + * 2\n2. y = [(3 4), ](#Add)\n3. **{x}**\n\n# Add (a)\n1. #a +\n";
+    let parser = Parser::new(source.to_string(), 0);
+    let (program, errors) = parser.parse_recovering();
+    let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        ["unexpected token: Star", "expected RParen", "unexpected end of expression"]
+    );
+    assert!(parser.parse().is_err());
+
+    // Every instruction is kept, with error nodes where parsing failed
+    let main = &program.blocks[0];
+    assert_eq!(main.chain.groups.len(), 3);
+    let Instruction::Assignment { value, .. } = &main.chain.groups[0].instructions[0] else {
+        panic!("expected an assignment");
+    };
+    let Value::BinaryOperation { operator: BinaryOperator::Addition, left, right, .. } = value else {
+        panic!("expected an addition, got {:?}", value);
+    };
+    // `1 + * 2` recovers as `1 + (<error> * 2)`
+    assert!(matches!(**left, Value::NumberLiteral(n, _) if n == 1.0));
+    let Value::BinaryOperation { operator: BinaryOperator::Multiplication, left, .. } = &**right else {
+        panic!("expected a multiplication, got {:?}", right);
+    };
+    assert!(matches!(**left, Value::Error(_)));
+    assert!(matches!(
+        &main.chain.groups[2].instructions[0],
+        Instruction::Expression { value: Value::Print(_), .. }
+    ));
+    assert_eq!(program.blocks[1].chain.groups.len(), 1);
+}
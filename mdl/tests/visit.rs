@@ -0,0 +1,164 @@
+use mdl::Program;
+use mdl::block::Block;
+use mdl::block::reference::BlockReference;
+use mdl::instruction::Instruction;
+use mdl::instruction::template::Template;
+use mdl::instruction::value::{BinaryOperator, Value};
+use mdl::visit::fold::{self, Fold};
+use mdl::visit::visit_mut::{self, VisitMut};
+use mdl::visit::{self, Visit};
+
+fn parse(source: &str) -> Program {
+    mdl::parser::Parser::new(source.to_string(), 0).parse().expect("program should parse").0
+}
+
+const SOURCE: &str = "# Main (n = 1 + 1)\n\nSome *body* text.\n\n1. x = [n, k: n * 2](#Helper)\n\
+                      2. r = match x\n    - y | 0: y\n    - otherwise: x > 1 ? ~~x~~\n\
+                      3. **{[r](#Helper)}**\n\n## Helper\n1. **{#0}**\n";
+
+/// Records the nodes it is shown, as short labels.
+#[derive(Default)]
+struct Trace(Vec<String>);
+
+impl<'ast> Visit<'ast> for Trace {
+    fn visit_block(&mut self, block: &'ast Block) {
+        self.0.push(format!("block {}", block.name));
+        visit::walk_block(self, block);
+    }
+
+    fn visit_value(&mut self, value: &'ast Value) {
+        if let Value::VariableReference(name, _) = value {
+            self.0.push(format!("read {name}"));
+        }
+        visit::walk_value(self, value);
+    }
+
+    fn visit_block_reference(&mut self, reference: &'ast BlockReference) {
+        self.0.push(format!("call {}", reference.block_name()));
+    }
+
+    fn visit_template(&mut self, template: &'ast Template) {
+        if let Template::Binding(name) = template {
+            self.0.push(format!("bind {name}"));
+        }
+        visit::walk_template(self, template);
+    }
+}
+
+#[test]
+fn visit_reaches_every_node_in_source_order() {
+    let program = parse(SOURCE);
+    let mut trace = Trace::default();
+    trace.visit_program(&program);
+    assert_eq!(
+        trace.0,
+        [
+            "block Main", "read n", "read n", "call Helper", "read x", "bind y", "read y", "read x", "read r",
+            "call Helper", "block Helper",
+        ]
+    );
+}
+
+#[test]
+fn visit_keeps_references_into_the_tree() {
+    /// Collect every number literal, borrowed from the program.
+    struct Numbers<'ast>(Vec<&'ast Value>);
+
+    impl<'ast> Visit<'ast> for Numbers<'ast> {
+        fn visit_value(&mut self, value: &'ast Value) {
+            if let Value::NumberLiteral(..) = value {
+                self.0.push(value);
+            }
+            visit::walk_value(self, value);
+        }
+    }
+
+    let program = parse(SOURCE);
+    let mut numbers = Numbers(Vec::new());
+    numbers.visit_program(&program);
+    let spans: Vec<_> = numbers.0.iter().map(|value| &SOURCE[value.span()]).collect();
+    assert_eq!(spans, ["1", "1", "2", "1"]);
+}
+
+#[test]
+fn visit_mut_renames_reads_and_writes() {
+    struct Rename;
+
+    impl VisitMut for Rename {
+        fn visit_instruction_mut(&mut self, instruction: &mut Instruction) {
+            if let Instruction::Assignment { variable, .. } = instruction
+                && variable == "x"
+            {
+                *variable = "total".to_string();
+            }
+            visit_mut::walk_instruction_mut(self, instruction);
+        }
+
+        fn visit_value_mut(&mut self, value: &mut Value) {
+            if let Value::VariableReference(name, _) = value
+                && name == "x"
+            {
+                *name = "total".to_string();
+            }
+            visit_mut::walk_value_mut(self, value);
+        }
+    }
+
+    let mut program = parse(SOURCE);
+    Rename.visit_program_mut(&mut program);
+    let mut trace = Trace::default();
+    trace.visit_program(&program);
+    assert!(!trace.0.iter().any(|label| label == "read x"));
+    assert_eq!(trace.0.iter().filter(|label| *label == "read total").count(), 2);
+    let Instruction::Assignment { variable, .. } = &program.blocks[0].chain.groups[0].instructions[0] else {
+        panic!("expected an assignment");
+    };
+    assert_eq!(variable, "total");
+}
+
+#[test]
+fn fold_replaces_constant_additions() {
+    /// Fold `a + b` on number literals, innermost first.
+    struct AddConstants;
+
+    impl Fold for AddConstants {
+        fn fold_value(&mut self, value: Value) -> Value {
+            match fold::walk_value(self, value) {
+                Value::BinaryOperation { operator: BinaryOperator::Addition, left, right, span, .. }
+                    if matches!((&*left, &*right), (Value::NumberLiteral(..), Value::NumberLiteral(..))) =>
+                {
+                    let (Value::NumberLiteral(a, _), Value::NumberLiteral(b, _)) = (*left, *right) else {
+                        unreachable!()
+                    };
+                    Value::NumberLiteral(a + b, span)
+                }
+                value => value,
+            }
+        }
+    }
+
+    let program = AddConstants.fold_program(parse("# Main (n = 1 + 2 + 3)\n1. x = n + (4 + 5)\n"));
+    let main = &program.blocks[0];
+    let default = main.parameters.as_ref().unwrap()[0].default.as_ref().unwrap();
+    assert!(matches!(default, Value::NumberLiteral(6.0, _)));
+    let Instruction::Assignment { value: Value::BinaryOperation { left, right, .. }, .. } =
+        &main.chain.groups[0].instructions[0]
+    else {
+        panic!("expected an addition");
+    };
+    assert!(matches!(**left, Value::VariableReference(..)));
+    assert!(matches!(**right, Value::NumberLiteral(9.0, _)));
+}
+
+#[test]
+fn templates_display_as_source() {
+    let program = parse(
+        "# Main\n1. r = match 1\n    - **{a}** | *{b}* | ~~d~~ | \"s\" | 2 | true | () | _: 0\n",
+    );
+    let Instruction::Assignment { value: Value::Match { arms, .. }, .. } =
+        &program.blocks[0].chain.groups[0].instructions[0]
+    else {
+        panic!("expected a match");
+    };
+    assert_eq!(arms[0].pattern.to_string(), "**{a}** | *{b}* | ~~d~~ | \"s\" | 2 | true | () | _");
+}